@@ -1,12 +1,9 @@
 use std::cmp::{min, max};
 use std::collections::BTreeMap;
-use std::fs::File;
-use std::io::{Read, Write};
-use std::mem::size_of;
 use std::thread;
 
 use system::error::{Error, Result, ENOENT, EBADF, EINVAL};
-use system::scheme::{Packet, Scheme};
+use system::scheme::{AsyncScheme, Packet, Reply, Scheme, Server};
 use system::syscall::{Stat, SEEK_SET, SEEK_CUR, SEEK_END};
 
 extern crate system;
@@ -196,19 +193,21 @@ impl Scheme for ExampleScheme {
     }
 }
 
+impl AsyncScheme for ExampleScheme {
+    fn handle_async(&mut self, packet: &Packet) -> Reply {
+        println!("Recv {:?}", packet);
+        let mut packet = *packet;
+        self.handle(&mut packet);
+        println!("Sent {:?}", packet);
+        Reply::Now(Error::demux(packet.a))
+    }
+}
+
 fn main() {
    //In order to handle example:, we create :example
    thread::spawn(|| {
        let mut scheme = ExampleScheme::new();
-       let mut socket = File::create(":example").unwrap();
-       loop {
-           let mut packet = Packet::default();
-           while socket.read(&mut packet).unwrap() == size_of::<Packet>() {
-               println!("Recv {:?}", packet);
-               scheme.handle(&mut packet);
-               socket.write(&packet).unwrap();
-               println!("Sent {:?}", packet);
-           }
-       }
+       let server = Server::new("example").unwrap();
+       server.run(&mut scheme).unwrap();
    });
 }
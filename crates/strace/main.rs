@@ -0,0 +1,159 @@
+#![deny(warnings)]
+
+extern crate system;
+
+use std::env;
+use std::fs::File;
+use std::io::{stderr, Read, Write};
+use std::mem::size_of;
+use std::os::unix::io::FromRawFd;
+use std::process::{exit, Command};
+use std::{ptr, slice};
+
+use system::error::Error;
+use system::scheme::Packet;
+use system::syscall::*;
+
+/// The most bytes of a buffer that are printed
+const PRINT_MAX: usize = 32;
+
+/// Format a buffer as an escaped string, truncated to `PRINT_MAX` bytes
+fn string(data: &[u8]) -> String {
+    let mut string = String::from("\"");
+    for &b in data.iter().take(PRINT_MAX) {
+        match b {
+            b'"' => string.push_str("\\\""),
+            b'\\' => string.push_str("\\\\"),
+            b'\n' => string.push_str("\\n"),
+            b'\r' => string.push_str("\\r"),
+            b'\t' => string.push_str("\\t"),
+            0x20 ... 0x7E => string.push(b as char),
+            _ => string.push_str(&format!("\\x{:02X}", b)),
+        }
+    }
+    string.push('"');
+    if data.len() > PRINT_MAX {
+        string.push_str("...");
+    }
+    string
+}
+
+/// Format a syscall, with the buffer that was sent on entry or exit
+fn call(packet: &Packet, data: &[u8]) -> String {
+    let args = match packet.a {
+        SYS_OPEN => format!("{}, {:#X}", string(data), packet.d),
+        SYS_MKDIR => format!("{}, {:#o}", string(data), packet.d),
+        SYS_RMDIR | SYS_UNLINK | SYS_CHDIR | SYS_STAT | SYS_UMOUNT => string(data),
+        SYS_LINK | SYS_RENAME | SYS_SYMLINK | SYS_READLINK | SYS_MOUNT => format!("{}, {:#X}", string(data), packet.d),
+        SYS_READ | SYS_WRITE | SYS_PREAD | SYS_PWRITE | SYS_FPATH | SYS_GETDENTS => format!("{}, {}, {}", packet.b, string(data), packet.d),
+        SYS_CLOSE | SYS_DUP | SYS_FSYNC | SYS_EXIT | SYS_SUPERVISE => format!("{}", packet.b),
+        SYS_LSEEK => format!("{}, {}, {}", packet.b, packet.c as isize, packet.d),
+        SYS_FTRUNCATE | SYS_DUP2 | SYS_FLOCK => format!("{}, {}", packet.b, packet.c),
+        SYS_FCNTL => format!("{}, {}, {:#X}", packet.b, packet.c, packet.d),
+        SYS_CLONE => format!("{:#X}", packet.b),
+        SYS_GETPID | SYS_YIELD => String::new(),
+        _ => format!("{:#X}, {:#X}, {:#X}", packet.b, packet.c, packet.d),
+    };
+
+    format!("{}({})", name(packet.a), args)
+}
+
+/// Format the result of a syscall
+fn result(value: usize) -> String {
+    match Error::demux(value) {
+        Ok(value) => format!("{}", value),
+        Err(err) => format!("-1 {} ({})", err.errno, err),
+    }
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let program = match args.next() {
+        Some(program) => program,
+        None => {
+            let _ = writeln!(stderr(), "strace: no program given");
+            exit(1);
+        }
+    };
+
+    let mut command = Command::new(&program);
+    for arg in args {
+        command.arg(&arg);
+    }
+
+    let mut child = match command.spawn_supervise() {
+        Ok(child) => child,
+        Err(err) => {
+            let _ = writeln!(stderr(), "strace: failed to execute {}: {}", program, err);
+            exit(1);
+        }
+    };
+
+    let mut supervisor = match sys_supervise(child.id() as usize) {
+        Ok(fd) => unsafe { File::from_raw_fd(fd) },
+        Err(err) => {
+            let _ = writeln!(stderr(), "strace: failed to supervise {}: {}", program, err);
+            exit(1);
+        }
+    };
+
+    // The syscall waiting for its result, as well as the buffer sent on entry
+    let mut pending: Option<(Packet, Vec<u8>)> = None;
+
+    let mut buf = [0; 4096 + size_of::<Packet>()];
+    loop {
+        let count = match supervisor.read(&mut buf) {
+            Ok(0) => break,
+            Ok(count) if count >= size_of::<Packet>() => count,
+            Ok(_) => continue,
+            Err(err) => {
+                let _ = writeln!(stderr(), "strace: failed to read syscall: {}", err);
+                break;
+            }
+        };
+
+        let packet = unsafe { ptr::read(buf.as_ptr() as *const Packet) };
+        let data = &buf[size_of::<Packet>()..count];
+
+        if packet.id == SUPERVISE_EXIT {
+            if let Some((entry, entry_data)) = pending.take() {
+                let line = match entry.a {
+                    SYS_READ | SYS_PREAD | SYS_FPATH => call(&entry, data),
+                    SYS_READLINK => format!("{} -> {}", call(&entry, &entry_data), string(data)),
+                    _ => call(&entry, &entry_data),
+                };
+                let _ = writeln!(stderr(), "{} = {}", line, result(packet.b));
+            }
+        } else {
+            // A syscall that never finished, such as a successful execve
+            if let Some((entry, entry_data)) = pending.take() {
+                let _ = writeln!(stderr(), "{} = ?", call(&entry, &entry_data));
+            }
+
+            if packet.a == SYS_EXIT {
+                let _ = writeln!(stderr(), "{} = ?", call(&packet, &[]));
+            } else {
+                pending = Some((packet, data.to_vec()));
+            }
+
+            let reply = [SUPERVISE_PASS, 0];
+            let reply_bytes = unsafe { slice::from_raw_parts(reply.as_ptr() as *const u8, size_of::<[usize; 2]>()) };
+            if let Err(err) = supervisor.write(reply_bytes) {
+                let _ = writeln!(stderr(), "strace: failed to reply to syscall: {}", err);
+                break;
+            }
+        }
+    }
+
+    if let Some((entry, entry_data)) = pending.take() {
+        let _ = writeln!(stderr(), "{} = ?", call(&entry, &entry_data));
+    }
+
+    match child.wait() {
+        Ok(status) => exit(status.code().unwrap_or(1)),
+        Err(err) => {
+            let _ = writeln!(stderr(), "strace: failed to wait for {}: {}", program, err);
+            exit(1);
+        }
+    }
+}
@@ -0,0 +1,19 @@
+//! The auxiliary vector, which the kernel places on the initial stack of a program after its
+//! environment, as pairs of a type and a value ending with `AT_NULL`
+
+/// The end of the auxiliary vector
+pub const AT_NULL: usize = 0;
+/// The address of the program headers of the executable
+pub const AT_PHDR: usize = 3;
+/// The size of a program header
+pub const AT_PHENT: usize = 4;
+/// The number of program headers
+pub const AT_PHNUM: usize = 5;
+/// The size of a page
+pub const AT_PAGESZ: usize = 6;
+/// The base address of the interpreter
+pub const AT_BASE: usize = 7;
+/// The entry point of the executable
+pub const AT_ENTRY: usize = 9;
+/// The address of 16 random bytes
+pub const AT_RANDOM: usize = 25;
@@ -1,3 +1,18 @@
+/// A rectangle of the display.
+///
+/// When writing to a `display:` resource opened with the `damage` path, each rectangle is
+/// followed by `w * h` pixels, and only those regions are copied to the screen. When writing to
+/// `display:cursor`, the rectangle gives the position of the cursor, followed by a `w * h` image
+/// if `w` and `h` are not zero.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(packed)]
+pub struct DisplayRect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
 #[cfg(target_arch = "x86")]
 #[allow(unused_assignments)]
 #[inline(always)]
@@ -0,0 +1,82 @@
+use core::ops::{Deref, DerefMut};
+use core::{mem, slice};
+
+/// Synchronization marker, sent after the events of one device report
+pub const INPUT_SYN: u16 = 0;
+/// A key was pressed, released or repeated. `code` is the scancode, `value` one of `KEY_*`
+pub const INPUT_KEY: u16 = 1;
+/// Relative motion. `code` is one of `REL_*`
+pub const INPUT_REL: u16 = 2;
+/// Absolute position. `code` is one of `ABS_*`
+pub const INPUT_ABS: u16 = 3;
+/// A button was pressed or released. `code` is one of `BTN_*`, `value` is 1 when pressed
+pub const INPUT_BUTTON: u16 = 4;
+/// Text produced by a key press. `value` is the character
+pub const INPUT_TEXT: u16 = 5;
+/// A device was added or removed. `code` is one of `HOTPLUG_*`. The current drivers cannot
+/// detect removal, so only `HOTPLUG_ADDED` is sent.
+pub const INPUT_HOTPLUG: u16 = 6;
+
+pub const KEY_RELEASE: i32 = 0;
+pub const KEY_PRESS: i32 = 1;
+pub const KEY_REPEAT: i32 = 2;
+
+pub const REL_X: u16 = 0;
+pub const REL_Y: u16 = 1;
+pub const REL_WHEEL: u16 = 2;
+pub const REL_HWHEEL: u16 = 3;
+
+pub const ABS_X: u16 = 0;
+pub const ABS_Y: u16 = 1;
+
+pub const BTN_LEFT: u16 = 0;
+pub const BTN_RIGHT: u16 = 1;
+pub const BTN_MIDDLE: u16 = 2;
+pub const BTN_SIDE: u16 = 3;
+pub const BTN_EXTRA: u16 = 4;
+
+pub const HOTPLUG_ADDED: u16 = 0;
+pub const HOTPLUG_REMOVED: u16 = 1;
+
+pub const MOD_SHIFT: u32 = 1;
+pub const MOD_CTRL: u32 = 2;
+pub const MOD_ALT: u32 = 4;
+pub const MOD_ALTGR: u32 = 8;
+pub const MOD_CAPS_LOCK: u32 = 0x10;
+
+/// An input event, read from the nodes of the `input:` scheme
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(packed)]
+pub struct InputEvent {
+    /// Monotonic time of the event, seconds
+    pub time_sec: i64,
+    /// Monotonic time of the event, nanoseconds
+    pub time_nsec: i32,
+    /// The device that produced the event
+    pub device: u32,
+    /// One of `INPUT_*`
+    pub kind: u16,
+    /// Meaning depends on `kind`
+    pub code: u16,
+    /// Meaning depends on `kind`
+    pub value: i32,
+    /// Modifiers held on the device, as `MOD_*` flags
+    pub modifiers: u32,
+}
+
+impl Deref for InputEvent {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(self as *const InputEvent as *const u8, mem::size_of::<InputEvent>()) as &[u8]
+        }
+    }
+}
+
+impl DerefMut for InputEvent {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe {
+            slice::from_raw_parts_mut(self as *mut InputEvent as *mut u8, mem::size_of::<InputEvent>()) as &mut [u8]
+        }
+    }
+}
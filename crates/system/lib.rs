@@ -6,12 +6,15 @@
 
 use core::{ptr, slice, str};
 
+pub mod auxv;
 pub mod error;
 #[cfg(target_os="redox")]
 pub mod externs;
 pub mod graphics;
+pub mod input;
 pub mod scheme;
 pub mod syscall;
+pub mod watch;
 
 /// Helper function for handling C strings, please do not copy it or make it pub or change it
 pub fn c_string_to_slice<'a>(ptr: *const u8) -> &'a [u8] {
@@ -1,5 +1,6 @@
 use core::ops::{Deref, DerefMut};
-use core::{mem, slice, str};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::{mem, ptr, slice, str};
 
 use super::error::*;
 use super::syscall::*;
@@ -14,6 +15,33 @@ pub struct Packet {
     pub d: usize
 }
 
+/// The id of a packet that a scheme writes on its own rather than in reply to a call
+///
+/// A notification with `a` set to one of the `WATCH_*` kinds and `b` and `c` set to the address
+/// and length of a path in the scheme reports a change to watchers of that path. The kernel only
+/// reports the changes of kernel schemes, so user schemes report their own this way.
+pub const PACKET_NOTIFY: usize = 0;
+
+/// The id of a packet that wakes the reader of the server resource
+///
+/// Writing a packet with this id makes the kernel queue one with the same id as a call, so that
+/// a thread blocked in reading calls returns, such as to take the replies of `AsyncScheme` that
+/// completed on another thread. It is not a call, and is not replied to.
+pub const PACKET_WAKE: usize = !0;
+
+impl Packet {
+    /// A notification of a change to `path`, which is relative to the root of the scheme
+    pub fn notify(kind: u16, path: &str) -> Packet {
+        Packet {
+            id: PACKET_NOTIFY,
+            a: kind as usize,
+            b: path.as_ptr() as usize,
+            c: path.len(),
+            d: 0
+        }
+    }
+}
+
 impl Deref for Packet {
     type Target = [u8];
     fn deref(&self) -> &[u8] {
@@ -31,20 +59,190 @@ impl DerefMut for Packet {
     }
 }
 
+/// The version of the `ExtPacket` layout
+///
+/// Later versions only add fields at the end, so a scheme can handle the fields it knows of.
+pub const EXT_PACKET_VERSION: usize = 1;
+
+/// A packet that also carries the process that made the call
+///
+/// A scheme reads and writes these instead of `Packet`s after setting `EXT_PACKET_VERSION` with
+/// `F_SETPACKET` on its server resource. The first fields are those of `Packet`, and replies are
+/// written back the same way, with `a` set to the result.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(packed)]
+pub struct ExtPacket {
+    pub id: usize,
+    pub a: usize,
+    pub b: usize,
+    pub c: usize,
+    pub d: usize,
+    /// The version of this layout, which is `EXT_PACKET_VERSION` or later
+    pub version: usize,
+    /// The process that made the call, or 0 for the kernel
+    pub pid: usize,
+    /// The user of that process, which is 0 until there are users
+    pub uid: u32,
+    /// The group of that process, which is 0 until there are groups
+    pub gid: u32,
+}
+
+impl ExtPacket {
+    /// The process that made the call
+    pub fn caller(&self) -> Caller {
+        Caller {
+            pid: self.pid,
+            uid: self.uid,
+            gid: self.gid,
+        }
+    }
+}
+
+impl Deref for ExtPacket {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(self as *const ExtPacket as *const u8, mem::size_of::<ExtPacket>()) as &[u8]
+        }
+    }
+}
+
+impl DerefMut for ExtPacket {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe {
+            slice::from_raw_parts_mut(self as *mut ExtPacket as *mut u8, mem::size_of::<ExtPacket>()) as &mut [u8]
+        }
+    }
+}
+
+/// The process that made a call, as given in an `ExtPacket`
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Caller {
+    pub pid: usize,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+/// The number of entries in each queue of a `Ring`
+pub const RING_ENTRIES: usize = 64;
+
+/// The call ring of a scheme, which is shared by the kernel and the scheme
+///
+/// A scheme gets its ring with `F_GETRING` on its server resource, after which the kernel submits
+/// calls to `sq` instead of having them read from the server resource. Reading the server resource
+/// then waits until there are calls in the ring and returns how many there are. Any number of
+/// workers may take calls with `take` and reply to them in any order with `complete`, after which
+/// `fsync` on the server resource hands the replies to the callers. Buffers of a call stay mapped
+/// until it is replied to.
+#[repr(C)]
+pub struct Ring {
+    /// The index of the next call to be taken, advanced by the scheme
+    pub sq_head: AtomicUsize,
+    /// The index of the next call to be submitted, advanced by the kernel
+    pub sq_tail: AtomicUsize,
+    /// The index of the next reply to be handed to its caller, advanced by the kernel
+    pub cq_head: AtomicUsize,
+    /// The index of the next reply slot to be claimed, advanced by the scheme
+    pub cq_tail: AtomicUsize,
+    /// Calls, in the order they were made
+    pub sq: [ExtPacket; RING_ENTRIES],
+    /// Replies, in the order their slots were claimed
+    pub cq: [Packet; RING_ENTRIES],
+    /// Whether the reply in each slot of `cq` has been written
+    pub cq_ready: [AtomicBool; RING_ENTRIES],
+}
+
+impl Ring {
+    /// Take the next call, if there is one
+    pub fn take(&self) -> Option<ExtPacket> {
+        loop {
+            let head = self.sq_head.load(Ordering::Acquire);
+            if head == self.sq_tail.load(Ordering::Acquire) {
+                return None;
+            }
+
+            // The call is copied before it is claimed, as its slot can be reused right after
+            let packet = self.sq[head % RING_ENTRIES];
+            if self.sq_head.compare_and_swap(head, head.wrapping_add(1), Ordering::AcqRel) == head {
+                return Some(packet);
+            }
+        }
+    }
+
+    /// Reply to a call, failing if the reply queue is full, in which case the scheme should
+    /// `fsync` its server resource and try again
+    pub fn complete(&self, packet: Packet) -> bool {
+        loop {
+            let tail = self.cq_tail.load(Ordering::Acquire);
+            if tail.wrapping_sub(self.cq_head.load(Ordering::Acquire)) >= RING_ENTRIES {
+                return false;
+            }
+
+            if self.cq_tail.compare_and_swap(tail, tail.wrapping_add(1), Ordering::AcqRel) == tail {
+                let slot = tail % RING_ENTRIES;
+                unsafe { ptr::write(&self.cq[slot] as *const Packet as *mut Packet, packet) };
+                self.cq_ready[slot].store(true, Ordering::Release);
+                return true;
+            }
+        }
+    }
+}
+
+/// Get the path of a call, where `b` and `c` are its address and length
+fn packet_path<'a>(packet: &Packet) -> &'a str {
+    unsafe { str::from_utf8_unchecked(slice::from_raw_parts(packet.b as *const u8, packet.c)) }
+}
+
+/// Get the buffer of a positional call, where `c` points to a single `IoVec`
+fn positional_buf<'a>(packet: &Packet) -> &'a mut [u8] {
+    let iov = unsafe { &*(packet.c as *const IoVec) };
+    unsafe { slice::from_raw_parts_mut(iov.iov_base as *mut u8, iov.iov_len) }
+}
+
+/// Split the buffer of a call taking two paths, where `d` is the length of the first path
+fn split_paths<'a>(packet: &Packet) -> Option<(&'a str, &'a str)> {
+    let paths = unsafe { str::from_utf8_unchecked(slice::from_raw_parts(packet.b as *const u8, packet.c)) };
+    if packet.d <= paths.len() && paths.is_char_boundary(packet.d) {
+        Some(paths.split_at(packet.d))
+    } else {
+        None
+    }
+}
+
 pub trait Scheme {
     fn handle(&mut self, packet: &mut Packet) {
         packet.a = Error::mux(match packet.a {
             SYS_OPEN => self.open(unsafe { str::from_utf8_unchecked(slice::from_raw_parts(packet.b as *const u8, packet.c)) }, packet.d),
             SYS_MKDIR => self.mkdir(unsafe { str::from_utf8_unchecked(slice::from_raw_parts(packet.b as *const u8, packet.c)) }, packet.d),
+            SYS_LINK => match split_paths(packet) {
+                Some((old, new)) => self.link(old, new),
+                None => Err(Error::new(EINVAL))
+            },
+            SYS_RENAME => match split_paths(packet) {
+                Some((old, new)) => self.rename(old, new),
+                None => Err(Error::new(EINVAL))
+            },
+            SYS_SYMLINK => match split_paths(packet) {
+                Some((target, path)) => self.symlink(target, path),
+                None => Err(Error::new(EINVAL))
+            },
             SYS_RMDIR => self.rmdir(unsafe { str::from_utf8_unchecked(slice::from_raw_parts(packet.b as *const u8, packet.c)) }),
+            SYS_STAT => self.stat(unsafe { str::from_utf8_unchecked(slice::from_raw_parts(packet.b as *const u8, packet.c)) }, unsafe { &mut *(packet.d as *mut Stat) }),
             SYS_UNLINK => self.unlink(unsafe { str::from_utf8_unchecked(slice::from_raw_parts(packet.b as *const u8, packet.c)) }),
 
             SYS_DUP => self.dup(packet.b),
             SYS_READ => self.read(packet.b, unsafe { slice::from_raw_parts_mut(packet.c as *mut u8, packet.d) }),
             SYS_WRITE => self.write(packet.b, unsafe { slice::from_raw_parts(packet.c as *const u8, packet.d) }),
+            SYS_PREAD => self.pread(packet.b, positional_buf(packet), packet.d),
+            SYS_PWRITE => self.pwrite(packet.b, positional_buf(packet), packet.d),
+            SYS_READV => self.readv(packet.b, unsafe { slice::from_raw_parts(packet.c as *const IoVec, packet.d) }),
+            SYS_WRITEV => self.writev(packet.b, unsafe { slice::from_raw_parts(packet.c as *const IoVec, packet.d) }),
             SYS_LSEEK => self.seek(packet.b, packet.c, packet.d),
             SYS_FPATH => self.fpath(packet.b, unsafe { slice::from_raw_parts_mut(packet.c as *mut u8, packet.d) }),
             SYS_FSTAT => self.fstat(packet.b, unsafe { &mut *(packet.c as *mut Stat) }),
+            SYS_GETDENTS => self.getdents(packet.b, unsafe { slice::from_raw_parts_mut(packet.c as *mut u8, packet.d) }),
+            SYS_FCNTL => self.fcntl(packet.b, packet.c, packet.d),
+            SYS_FLOCK => self.flock(packet.b, packet.c),
             SYS_FSYNC => self.fsync(packet.b),
             SYS_FTRUNCATE => self.ftruncate(packet.b, packet.c),
             SYS_CLOSE => self.close(packet.b),
@@ -53,6 +251,27 @@ pub trait Scheme {
         });
     }
 
+    /// Handle an extended packet, passing the caller to `open_as`, `mkdir_as` and `unlink_as`
+    fn handle_ext(&mut self, packet: &mut ExtPacket) {
+        let caller = packet.caller();
+        let mut inner = Packet {
+            id: packet.id,
+            a: packet.a,
+            b: packet.b,
+            c: packet.c,
+            d: packet.d
+        };
+
+        match inner.a {
+            SYS_OPEN => inner.a = Error::mux(self.open_as(packet_path(&inner), inner.d, &caller)),
+            SYS_MKDIR => inner.a = Error::mux(self.mkdir_as(packet_path(&inner), inner.d, &caller)),
+            SYS_UNLINK => inner.a = Error::mux(self.unlink_as(packet_path(&inner), &caller)),
+            _ => self.handle(&mut inner)
+        }
+
+        packet.a = inner.a;
+    }
+
     /* Scheme operations */
 
     #[allow(unused_variables)]
@@ -66,12 +285,34 @@ pub trait Scheme {
     }
 
     #[allow(unused_variables)]
-    fn rmdir(&mut self, path: &str) -> Result<usize> {
+    fn link(&mut self, old: &str, new: &str) -> Result<usize> {
         Err(Error::new(ENOENT))
     }
 
     #[allow(unused_variables)]
+    fn rename(&mut self, old: &str, new: &str) -> Result<usize> {
+        Err(Error::new(ENOENT))
+    }
+
+    #[allow(unused_variables)]
+    fn rmdir(&mut self, path: &str) -> Result<usize> {
+        Err(Error::new(ENOENT))
+    }
+
+    /// Stat a path, which by default opens it and stats the resource
     fn stat(&mut self, path: &str, stat: &mut Stat) -> Result<usize> {
+        let id = try!(self.open(path, O_RDONLY | O_NOFOLLOW));
+        let result = self.fstat(id, stat);
+        let _ = self.close(id);
+        result
+    }
+
+    /// Create a symlink at `path` pointing to `target`. Opening a symlink should return the
+    /// symlink itself, with a mode of `MODE_SYMLINK` and the target as contents, which the kernel
+    /// will then follow unless `O_NOFOLLOW` is given. The kernel only looks for symlinks once the
+    /// scheme sets `F_SETSYMLINKS` on its server resource.
+    #[allow(unused_variables)]
+    fn symlink(&mut self, target: &str, path: &str) -> Result<usize> {
         Err(Error::new(ENOENT))
     }
 
@@ -80,6 +321,24 @@ pub trait Scheme {
         Err(Error::new(ENOENT))
     }
 
+    /// Open a path for a caller, which by default ignores the caller
+    #[allow(unused_variables)]
+    fn open_as(&mut self, path: &str, flags: usize, caller: &Caller) -> Result<usize> {
+        self.open(path, flags)
+    }
+
+    /// Make a directory for a caller, which by default ignores the caller
+    #[allow(unused_variables)]
+    fn mkdir_as(&mut self, path: &str, mode: usize, caller: &Caller) -> Result<usize> {
+        self.mkdir(path, mode)
+    }
+
+    /// Unlink a path for a caller, which by default ignores the caller
+    #[allow(unused_variables)]
+    fn unlink_as(&mut self, path: &str, caller: &Caller) -> Result<usize> {
+        self.unlink(path)
+    }
+
     /* Resource operations */
     #[allow(unused_variables)]
     fn dup(&mut self, old_id: usize) -> Result<usize> {
@@ -101,6 +360,68 @@ pub trait Scheme {
         Err(Error::new(EBADF))
     }
 
+    /// Read at `offset` without changing the seek position, which by default seeks there and back
+    fn pread(&mut self, id: usize, buf: &mut [u8], offset: usize) -> Result<usize> {
+        let pos = try!(self.seek(id, 0, SEEK_CUR));
+        try!(self.seek(id, offset, SEEK_SET));
+        let result = self.read(id, buf);
+        try!(self.seek(id, pos, SEEK_SET));
+        result
+    }
+
+    /// Write at `offset` without changing the seek position, which by default seeks there and back
+    fn pwrite(&mut self, id: usize, buf: &[u8], offset: usize) -> Result<usize> {
+        let pos = try!(self.seek(id, 0, SEEK_CUR));
+        try!(self.seek(id, offset, SEEK_SET));
+        let result = self.write(id, buf);
+        try!(self.seek(id, pos, SEEK_SET));
+        result
+    }
+
+    /// Read to each buffer in turn, which by default reads until a buffer is not filled
+    fn readv(&mut self, id: usize, iov: &[IoVec]) -> Result<usize> {
+        let mut total = 0;
+        for vec in iov.iter() {
+            let buf = unsafe { slice::from_raw_parts_mut(vec.iov_base as *mut u8, vec.iov_len) };
+            match self.read(id, buf) {
+                Ok(count) => {
+                    total += count;
+                    if count < buf.len() {
+                        break;
+                    }
+                },
+                Err(err) => if total == 0 {
+                    return Err(err);
+                } else {
+                    break;
+                }
+            }
+        }
+        Ok(total)
+    }
+
+    /// Write each buffer in turn, which by default writes until a buffer is not written completely
+    fn writev(&mut self, id: usize, iov: &[IoVec]) -> Result<usize> {
+        let mut total = 0;
+        for vec in iov.iter() {
+            let buf = unsafe { slice::from_raw_parts(vec.iov_base as *const u8, vec.iov_len) };
+            match self.write(id, buf) {
+                Ok(count) => {
+                    total += count;
+                    if count < buf.len() {
+                        break;
+                    }
+                },
+                Err(err) => if total == 0 {
+                    return Err(err);
+                } else {
+                    break;
+                }
+            }
+        }
+        Ok(total)
+    }
+
     #[allow(unused_variables)]
     fn fpath(&self, id: usize, buf: &mut [u8]) -> Result<usize> {
         Err(Error::new(EBADF))
@@ -111,6 +432,32 @@ pub trait Scheme {
         Err(Error::new(EBADF))
     }
 
+    /// Read directory entries, as `Dirent` headers each followed by a name. Returning `ENOSYS`
+    /// makes readers fall back to reading the directory as a list of names.
+    #[allow(unused_variables)]
+    fn getdents(&mut self, id: usize, buf: &mut [u8]) -> Result<usize> {
+        Err(Error::new(ENOSYS))
+    }
+
+    /// Get (`F_GETFL`) or set (`F_SETFL`) the status flags of a resource, which are `O_NONBLOCK`
+    /// and `O_APPEND`. By default, no flags can be set.
+    #[allow(unused_variables)]
+    fn fcntl(&mut self, id: usize, cmd: usize, arg: usize) -> Result<usize> {
+        match cmd {
+            F_GETFL => Ok(0),
+            F_SETFL if arg == 0 => Ok(0),
+            _ => Err(Error::new(EINVAL))
+        }
+    }
+
+    /// Take (`LOCK_SH`, `LOCK_EX`) or release (`LOCK_UN`) an advisory lock, which must not wait if
+    /// `LOCK_NB` is given. Locks must be released when the resource is closed. Returning `ENOSYS`
+    /// makes the kernel track the locks of the scheme by path instead.
+    #[allow(unused_variables)]
+    fn flock(&mut self, id: usize, operation: usize) -> Result<usize> {
+        Err(Error::new(ENOSYS))
+    }
+
     #[allow(unused_variables)]
     fn fsync(&mut self, id: usize) -> Result<usize> {
         Err(Error::new(EBADF))
@@ -126,3 +473,139 @@ pub trait Scheme {
         Err(Error::new(EBADF))
     }
 }
+
+/// The reply to a call handled by an `AsyncScheme`
+pub enum Reply {
+    /// Reply now, with this result
+    Now(Result<usize>),
+    /// Reply later, from `AsyncScheme::completed`
+    Later,
+}
+
+/// A scheme that can reply to calls after handling them, so that one thread can serve callers
+/// that have to wait, such as readers of a pty or of a network connection
+///
+/// Calls that can be replied to right away can be dispatched to a `Scheme` by copying the packet,
+/// passing it to `Scheme::handle` and replying with `Error::demux(packet.a)`.
+pub trait AsyncScheme {
+    /// Handle a call. A call that has to wait returns `Reply::Later`, and the scheme keeps the id
+    /// of its packet to reply to it once it can
+    fn handle_async(&mut self, packet: &Packet) -> Reply;
+
+    /// Take a reply to a call that was deferred, as the id of its packet and its result. This is
+    /// called after every batch of calls and after every `Server::wake`, until it returns `None`
+    fn completed(&mut self) -> Option<(usize, Result<usize>)> {
+        None
+    }
+}
+
+/// The number of calls read at once by `Server::run`
+const SERVER_BATCH: usize = 16;
+
+/// The server side of a scheme, which reads calls and writes replies
+pub struct Server {
+    fd: usize,
+}
+
+impl Server {
+    /// Register a scheme with the kernel
+    pub fn new(name: &str) -> Result<Server> {
+        let mut path = [0; 256];
+        if name.len() + 1 > path.len() {
+            return Err(Error::new(ENAMETOOLONG));
+        }
+        path[0] = b':';
+        path[1..name.len() + 1].copy_from_slice(name.as_bytes());
+
+        let fd = try!(sys_open(unsafe { str::from_utf8_unchecked(&path[..name.len() + 1]) }, O_CREAT | O_RDWR));
+        Ok(Server {
+            fd: fd,
+        })
+    }
+
+    /// The file descriptor of the server resource
+    pub fn fd(&self) -> usize {
+        self.fd
+    }
+
+    /// Read calls, waiting until there is at least one, and return how many were read
+    pub fn receive(&self, packets: &mut [Packet]) -> Result<usize> {
+        let buf = unsafe {
+            slice::from_raw_parts_mut(packets.as_mut_ptr() as *mut u8, packets.len() * mem::size_of::<Packet>())
+        };
+        let count = try!(sys_read(self.fd, buf));
+        Ok(count / mem::size_of::<Packet>())
+    }
+
+    /// Write replies, which are packets with `a` set to the result
+    pub fn send(&self, packets: &[Packet]) -> Result<()> {
+        if packets.is_empty() {
+            return Ok(());
+        }
+
+        let buf = unsafe {
+            slice::from_raw_parts(packets.as_ptr() as *const u8, packets.len() * mem::size_of::<Packet>())
+        };
+        try!(sys_write(self.fd, buf));
+        Ok(())
+    }
+
+    /// Wake the thread that is reading calls, so that `run` takes the replies that completed
+    /// since, which can be called from any thread that shares the file descriptor
+    pub fn wake(&self) -> Result<()> {
+        self.send(&[Packet {
+            id: PACKET_WAKE,
+            a: 0,
+            b: 0,
+            c: 0,
+            d: 0
+        }])
+    }
+
+    /// Reply to a call by the id of its packet, such as one that was deferred
+    pub fn reply(&self, id: usize, result: Result<usize>) -> Result<()> {
+        self.send(&[Packet {
+            id: id,
+            a: Error::mux(result),
+            b: 0,
+            c: 0,
+            d: 0
+        }])
+    }
+
+    /// Serve a scheme until reading calls or writing replies fails
+    ///
+    /// Deferred replies are taken after every batch of calls, so a scheme that completes calls
+    /// on another thread calls `wake` to have them sent.
+    pub fn run<S: AsyncScheme>(&self, scheme: &mut S) -> Result<()> {
+        let mut packets = [Packet::default(); SERVER_BATCH];
+        loop {
+            let count = try!(self.receive(&mut packets));
+
+            // Replies are gathered at the start of the batch and written together
+            let mut replies = 0;
+            for i in 0..count {
+                if packets[i].id == PACKET_WAKE {
+                    continue;
+                }
+
+                if let Reply::Now(result) = scheme.handle_async(&packets[i]) {
+                    packets[replies] = packets[i];
+                    packets[replies].a = Error::mux(result);
+                    replies += 1;
+                }
+            }
+            try!(self.send(&packets[..replies]));
+
+            while let Some((id, result)) = scheme.completed() {
+                try!(self.reply(id, result));
+            }
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = sys_close(self.fd);
+    }
+}
@@ -15,3 +15,60 @@ pub mod arch;
 #[cfg(target_arch = "x86_64")]
 #[path="x86_64.rs"]
 pub mod arch;
+
+/// The name of a syscall number, used for debugging and tracing
+pub fn name(number: usize) -> &'static str {
+    match number {
+        // Redox
+        SYS_SUPERVISE => "supervise",
+        SYS_EXECVE_ENV => "execve_env",
+
+        // Unix
+        SYS_BRK => "brk",
+        SYS_CHDIR => "chdir",
+        SYS_CLONE => "clone",
+        SYS_CLOSE => "close",
+        SYS_CLOCK_GETTIME => "clock_gettime",
+        SYS_DUP => "dup",
+        SYS_DUP2 => "dup2",
+        SYS_EXECVE => "execve",
+        SYS_EXIT => "exit",
+        SYS_FCNTL => "fcntl",
+        SYS_FLOCK => "flock",
+        SYS_FPATH => "fpath",
+        SYS_FSTAT => "fstat",
+        SYS_FSYNC => "fsync",
+        SYS_FTRUNCATE => "ftruncate",
+        SYS_FUTEX => "futex",
+        SYS_GETDENTS => "getdents",
+        SYS_GETPID => "getpid",
+        SYS_GETRLIMIT => "getrlimit",
+        SYS_GETRUSAGE => "getrusage",
+        SYS_IOPL => "iopl",
+        SYS_LINK => "link",
+        SYS_LSEEK => "lseek",
+        SYS_MKDIR => "mkdir",
+        SYS_MOUNT => "mount",
+        SYS_NANOSLEEP => "nanosleep",
+        SYS_OPEN => "open",
+        SYS_PIPE2 => "pipe2",
+        SYS_PREAD => "pread",
+        SYS_PWRITE => "pwrite",
+        SYS_READ => "read",
+        SYS_READLINK => "readlink",
+        SYS_READV => "readv",
+        SYS_RENAME => "rename",
+        SYS_RMDIR => "rmdir",
+        SYS_SETRLIMIT => "setrlimit",
+        SYS_STAT => "stat",
+        SYS_SYMLINK => "symlink",
+        SYS_UMOUNT => "umount",
+        SYS_UNLINK => "unlink",
+        SYS_WAITPID => "waitpid",
+        SYS_WRITE => "write",
+        SYS_WRITEV => "writev",
+        SYS_YIELD => "yield",
+
+        _ => "unknown",
+    }
+}
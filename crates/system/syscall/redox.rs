@@ -1,7 +1,18 @@
-use syscall::arch::syscall1;
+use syscall::arch::{syscall1, syscall3};
 use error::Result;
 
 pub const SYS_SUPERVISE: usize = 1638; // loominatzi confirmed
+    /// `Packet::id` of a syscall that is about to run
+    pub const SUPERVISE_ENTER: usize = 0;
+    /// `Packet::id` of a passed through syscall that has finished
+    pub const SUPERVISE_EXIT: usize = 1;
+    /// Reply action making the syscall return the given value without running it
+    pub const SUPERVISE_RETURN: usize = 0;
+    /// Reply action running the syscall normally, reporting its result with `SUPERVISE_EXIT`
+    pub const SUPERVISE_PASS: usize = 1;
+/// `execve` with an environment, which is a separate call as `SYS_EXECVE` takes two arguments.
+/// A null environment keeps that of the process
+pub const SYS_EXECVE_ENV: usize = 1639;
 
 /// <!-- @MANSTART{supervise} -->
 /// Supervise a given child process' system calls.
@@ -21,12 +32,19 @@ pub const SYS_SUPERVISE: usize = 1638; // loominatzi confirmed
 ///
 /// The return value (if successful) is a file descriptor, from which syscalls can be read and written:
 /// the syscalls are read in `Packet` sized packages, containing the respective blocking syscall. If
-/// no syscall is blocking (or the last blocking syscall have been handled), 0 bytes will be read to
-/// the buffer.
+/// the process has exited, 0 bytes will be read to the buffer.
 ///
-/// Writing pointer sized integers to this file handle will set the EAX register of the particular
-/// process, after which the process is unblocked and the syscall buffer is emptied. The behavior of
-/// writing packages of unexpected size is unspecified.
+/// A packet with an `id` of `SUPERVISE_ENTER` is a syscall about to run, with the number in `a`
+/// and the arguments in `b`, `c` and `d`. A packet with an `id` of `SUPERVISE_EXIT` is a passed
+/// through syscall that has finished, with the number in `a` and the result in `b`. The packet is
+/// followed by the contents of the buffer the syscall refers to, if any and if it fits: the path of
+/// path based calls and the data of `write` on entry, the data of `read` and `fpath` on exit.
+///
+/// Writing a pointer sized integer to this file handle will make the syscall return it, after
+/// which the process is unblocked. Writing two pointer sized integers replies with an action and a
+/// value: `SUPERVISE_RETURN` with a value to return, or `SUPERVISE_PASS` to run the syscall
+/// normally. The process is blocked again once a passed through syscall finishes, until the
+/// `SUPERVISE_EXIT` packet is read.
 ///
 /// Note that a process blocked by a syscall will have its potential sleep cleared (i.e., it will
 /// not wake up after the sleep is finished).
@@ -38,3 +56,9 @@ pub const SYS_SUPERVISE: usize = 1638; // loominatzi confirmed
 pub fn sys_supervise(pid: usize) -> Result<usize> {
     unsafe { syscall1(SYS_SUPERVISE, pid) }
 }
+
+/// Replace the program of this process, giving it the environment `envp`, a null terminated
+/// array of `NAME=VALUE` strings
+pub unsafe fn sys_execve_env(path: *const u8, args: *const *const u8, envp: *const *const u8) -> Result<usize> {
+    syscall3(SYS_EXECVE_ENV, path as usize, args as usize, envp as usize)
+}
@@ -1,4 +1,4 @@
-use syscall::arch::{syscall0, syscall1, syscall2, syscall3, syscall5};
+use syscall::arch::{syscall0, syscall1, syscall2, syscall3, syscall4, syscall5};
 use error::Result;
 
 pub const SYS_BRK: usize = 45;
@@ -17,25 +17,86 @@ pub const SYS_CLONE: usize = 120;
     /// This is an important security measure, since otherwise the process would be able to fork it
     /// self right after starting, making supervising it impossible.
     pub const CLONE_SUPERVISE: usize = 0x400000;
+    /// Set the soft stack limit of the clone to the size given as the second argument, up to the
+    /// hard limit, instead of inheriting the soft limit of the parent
+    pub const CLONE_STACK: usize = 0x800000;
 pub const SYS_CLOSE: usize = 6;
 pub const SYS_CLOCK_GETTIME: usize = 265;
     pub const CLOCK_REALTIME: usize = 1;
     pub const CLOCK_MONOTONIC: usize = 4;
 pub const SYS_DUP: usize = 41;
+pub const SYS_DUP2: usize = 63;
 pub const SYS_EXECVE: usize = 11;
 pub const SYS_EXIT: usize = 1;
+pub const SYS_FCNTL: usize = 55;
+    pub const F_GETFD: usize = 1;
+    pub const F_SETFD: usize = 2;
+    pub const F_GETFL: usize = 3;
+    pub const F_SETFL: usize = 4;
+    /// Get the call timeout of a scheme, in milliseconds, from its server resource
+    pub const F_GETTIMEOUT: usize = 5;
+    /// Set the call timeout of a scheme, in milliseconds, on its server resource. Calls that are
+    /// not replied to in time fail with `ETIMEDOUT`. 0, the default, waits forever
+    pub const F_SETTIMEOUT: usize = 6;
+    /// Get the version of the packets a scheme reads and writes, from its server resource
+    pub const F_GETPACKET: usize = 7;
+    /// Set the version of the packets a scheme reads and writes, on its server resource. 0, the
+    /// default, is `Packet`, and `EXT_PACKET_VERSION` is `ExtPacket`
+    pub const F_SETPACKET: usize = 8;
+    /// Map the call ring of a scheme into it, on its server resource, returning its address. See
+    /// `Ring`
+    pub const F_GETRING: usize = 9;
+    /// Get whether a scheme has symlinks, from its server resource
+    pub const F_GETSYMLINKS: usize = 10;
+    /// Set whether a scheme has symlinks, on its server resource. The kernel only looks for
+    /// symlinks in the paths of schemes that have them, which none do by default
+    pub const F_SETSYMLINKS: usize = 11;
+    /// Close the file descriptor when executing a new program
+    pub const FD_CLOEXEC: usize = 1;
+pub const SYS_FLOCK: usize = 143;
+    pub const LOCK_SH: usize = 1;
+    pub const LOCK_EX: usize = 2;
+    pub const LOCK_NB: usize = 4;
+    pub const LOCK_UN: usize = 8;
 pub const SYS_FPATH: usize = 928;
 pub const SYS_FSTAT: usize = 28;
+    pub const MODE_TYPE: u16 = 0xF000;
+    pub const MODE_FIFO: u16 = 0x1000;
+    pub const MODE_CHR: u16 = 0x2000;
     pub const MODE_DIR: u16 = 0x4000;
     pub const MODE_FILE: u16 = 0x8000;
-    pub const MODE_ALL: u16 = MODE_DIR | MODE_FILE;
+    pub const MODE_SYMLINK: u16 = 0xA000;
+    /// The type bits of a mode, as `MODE_TYPE`. Types are not single bits, `MODE_SYMLINK`
+    /// including those of `MODE_FILE`, so a type is checked with `mode & MODE_TYPE == MODE_FILE`
+    pub const MODE_ALL: u16 = MODE_TYPE;
+    pub const MODE_PERM: u16 = 0x0FFF;
 pub const SYS_FSYNC: usize = 118;
 pub const SYS_FTRUNCATE: usize = 93;
 pub const SYS_FUTEX: usize = 240;
     pub const FUTEX_WAIT: usize = 0;
     pub const FUTEX_WAKE: usize = 1;
     pub const FUTEX_REQUEUE: usize = 2;
+pub const SYS_GETDENTS: usize = 141;
 pub const SYS_GETPID: usize = 20;
+pub const SYS_GETRLIMIT: usize = 76;
+    /// CPU time in seconds, after which the context is terminated
+    pub const RLIMIT_CPU: usize = 0;
+    /// The most that the stack can grow to, in bytes
+    pub const RLIMIT_STACK: usize = 3;
+    /// The number of contexts that can exist when a context is cloned
+    pub const RLIMIT_NPROC: usize = 6;
+    /// One more than the highest file descriptor that can be opened
+    pub const RLIMIT_NOFILE: usize = 7;
+    /// The size of the address space, counting the image, heap, mmap zone and stack, in bytes
+    pub const RLIMIT_AS: usize = 9;
+    /// The number of resource limits a context has
+    pub const RLIMIT_COUNT: usize = 10;
+    /// No limit
+    pub const RLIM_INFINITY: usize = !0;
+pub const SYS_GETRUSAGE: usize = 77;
+    pub const RUSAGE_SELF: usize = 0;
+    /// The children that have exited, and their children
+    pub const RUSAGE_CHILDREN: usize = !0;
 pub const SYS_IOPL: usize = 110;
 pub const SYS_LINK: usize = 9;
 pub const SYS_LSEEK: usize = 19;
@@ -43,6 +104,7 @@ pub const SYS_LSEEK: usize = 19;
     pub const SEEK_CUR: usize = 1;
     pub const SEEK_END: usize = 2;
 pub const SYS_MKDIR: usize = 39;
+pub const SYS_MOUNT: usize = 165;
 pub const SYS_NANOSLEEP: usize = 162;
 pub const SYS_OPEN: usize = 5;
     pub const O_RDONLY: usize = 0;
@@ -57,28 +119,124 @@ pub const SYS_OPEN: usize = 5;
     pub const O_CREAT: usize = 0x200;
     pub const O_TRUNC: usize = 0x400;
     pub const O_EXCL: usize = 0x800;
+    pub const O_CLOEXEC: usize = 0x1000000;
+    pub const O_NOFOLLOW: usize = 0x80000000;
 pub const SYS_PIPE2: usize = 331;
+pub const SYS_PREAD: usize = 180;
+pub const SYS_PWRITE: usize = 181;
 pub const SYS_READ: usize = 3;
+pub const SYS_READLINK: usize = 85;
+pub const SYS_READV: usize = 145;
+pub const SYS_RENAME: usize = 38;
 pub const SYS_RMDIR: usize = 84;
+pub const SYS_SETRLIMIT: usize = 75;
+pub const SYS_STAT: usize = 106;
+pub const SYS_SYMLINK: usize = 83;
+pub const SYS_UMOUNT: usize = 166;
 pub const SYS_UNLINK: usize = 10;
 pub const SYS_WAITPID: usize = 7;
 pub const SYS_WRITE: usize = 4;
+pub const SYS_WRITEV: usize = 146;
 pub const SYS_YIELD: usize = 158;
 
-#[derive(Copy, Clone, Debug, Default)]
+/// The version of the `Stat` layout
+pub const STAT_VERSION: u16 = 1;
+
+/// Information about a resource, as returned by `sys_fstat`
+///
+/// `st_version` is set to `STAT_VERSION` by `Stat::default`, so that schemes can tell which
+/// layout they are filling in.
+#[derive(Copy, Clone, Debug)]
 #[repr(packed)]
 pub struct Stat {
-    pub st_dev: u16,
-    pub st_ino: u16,
+    pub st_version: u16,
     pub st_mode: u16,
-    pub st_nlink: u16,
-    pub st_uid: u16,
-    pub st_gid: u16,
-    pub st_rdev: u16,
-    pub st_size: u32,
-    pub st_atime: u32,
-    pub st_mtime: u32,
-    pub st_ctime: u32
+    pub st_nlink: u32,
+    pub st_uid: u32,
+    pub st_gid: u32,
+    pub st_dev: u64,
+    pub st_ino: u64,
+    pub st_rdev: u64,
+    pub st_size: u64,
+    pub st_blksize: u32,
+    pub st_blocks: u64,
+    pub st_atime: u64,
+    pub st_atime_nsec: u32,
+    pub st_mtime: u64,
+    pub st_mtime_nsec: u32,
+    pub st_ctime: u64,
+    pub st_ctime_nsec: u32,
+}
+
+impl Default for Stat {
+    fn default() -> Stat {
+        Stat {
+            st_version: STAT_VERSION,
+            st_mode: 0,
+            st_nlink: 0,
+            st_uid: 0,
+            st_gid: 0,
+            st_dev: 0,
+            st_ino: 0,
+            st_rdev: 0,
+            st_size: 0,
+            st_blksize: 0,
+            st_blocks: 0,
+            st_atime: 0,
+            st_atime_nsec: 0,
+            st_mtime: 0,
+            st_mtime_nsec: 0,
+            st_ctime: 0,
+            st_ctime_nsec: 0,
+        }
+    }
+}
+
+/// Type of a directory entry that is not known
+pub const DT_UNKNOWN: u8 = 0;
+/// Type of a directory entry that is a pipe
+pub const DT_FIFO: u8 = 1;
+/// Type of a directory entry that is a character device
+pub const DT_CHR: u8 = 2;
+/// Type of a directory entry that is a directory
+pub const DT_DIR: u8 = 4;
+/// Type of a directory entry that is a block device
+pub const DT_BLK: u8 = 6;
+/// Type of a directory entry that is a regular file
+pub const DT_REG: u8 = 8;
+/// Type of a directory entry that is a symlink
+pub const DT_LNK: u8 = 10;
+
+/// The header of a directory entry, as returned by `sys_getdents`
+///
+/// The header is followed by the name of the entry, which is not terminated. `d_reclen` is the
+/// length of the header and the name together, so the next entry starts `d_reclen` bytes later.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(packed)]
+pub struct Dirent {
+    pub d_ino: u64,
+    pub d_reclen: u16,
+    pub d_type: u8,
+}
+
+/// The most buffers `sys_readv` and `sys_writev` accept at once
+pub const IOV_MAX: usize = 1024;
+
+/// A buffer of vectored I/O, as passed to `sys_readv` and `sys_writev`
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(packed)]
+pub struct IoVec {
+    pub iov_base: usize,
+    pub iov_len: usize,
+}
+
+impl IoVec {
+    pub fn new(buf: &[u8]) -> IoVec {
+        IoVec {
+            iov_base: buf.as_ptr() as usize,
+            iov_len: buf.len(),
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, Default)]
@@ -88,6 +246,43 @@ pub struct TimeSpec {
     pub tv_nsec: i32,
 }
 
+/// A resource limit, as read by `sys_getrlimit` and set by `sys_setrlimit`
+///
+/// The soft limit `rlim_cur` is the one that is enforced. It can be raised up to the hard limit
+/// `rlim_max`, which can only be lowered.
+#[derive(Copy, Clone, Debug)]
+#[repr(packed)]
+pub struct RLimit {
+    pub rlim_cur: usize,
+    pub rlim_max: usize,
+}
+
+impl RLimit {
+    /// A limit of `value` for both the soft and hard limit
+    pub fn new(value: usize) -> RLimit {
+        RLimit {
+            rlim_cur: value,
+            rlim_max: value,
+        }
+    }
+}
+
+impl Default for RLimit {
+    fn default() -> RLimit {
+        RLimit::new(RLIM_INFINITY)
+    }
+}
+
+/// Resource usage, as returned by `sys_getrusage`
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(packed)]
+pub struct RUsage {
+    /// The CPU time used
+    pub ru_utime: TimeSpec,
+    /// The most memory used at once, in kilobytes
+    pub ru_maxrss: usize,
+}
+
 pub unsafe fn sys_brk(addr: usize) -> Result<usize> {
     syscall1(SYS_BRK, addr)
 }
@@ -100,6 +295,11 @@ pub unsafe fn sys_clone(flags: usize) -> Result<usize> {
     syscall1(SYS_CLONE, flags)
 }
 
+/// Clone with a stack that can grow to `stack_size` bytes
+pub unsafe fn sys_clone_stack(flags: usize, stack_size: usize) -> Result<usize> {
+    syscall2(SYS_CLONE, flags | CLONE_STACK, stack_size)
+}
+
 pub fn sys_close(fd: usize) -> Result<usize> {
     unsafe { syscall1(SYS_CLOSE, fd) }
 }
@@ -112,6 +312,12 @@ pub fn sys_dup(fd: usize) -> Result<usize> {
     unsafe { syscall1(SYS_DUP, fd) }
 }
 
+/// Duplicate `fd` to `new_fd`, closing `new_fd` first if it is open
+pub fn sys_dup2(fd: usize, new_fd: usize) -> Result<usize> {
+    unsafe { syscall2(SYS_DUP2, fd, new_fd) }
+}
+
+/// Replace the program of this process, keeping its environment
 pub unsafe fn sys_execve(path: *const u8, args: *const *const u8) -> Result<usize> {
     syscall2(SYS_EXECVE, path as usize, args as usize)
 }
@@ -120,6 +326,20 @@ pub fn sys_exit(status: usize) -> Result<usize> {
     unsafe { syscall1(SYS_EXIT, status) }
 }
 
+/// Get or set the flags of a file descriptor (`F_GETFD`/`F_SETFD`), the status flags of its
+/// resource (`F_GETFL`/`F_SETFL`), or the call timeout (`F_GETTIMEOUT`/`F_SETTIMEOUT`), packet
+/// version (`F_GETPACKET`/`F_SETPACKET`), call ring (`F_GETRING`) and symlinks
+/// (`F_GETSYMLINKS`/`F_SETSYMLINKS`) of a scheme
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> Result<usize> {
+    unsafe { syscall3(SYS_FCNTL, fd, cmd, arg) }
+}
+
+/// Take (`LOCK_SH`, `LOCK_EX`) or release (`LOCK_UN`) an advisory lock on the resource of `fd`,
+/// failing with `EWOULDBLOCK` instead of waiting if `LOCK_NB` is given
+pub fn sys_flock(fd: usize, operation: usize) -> Result<usize> {
+    unsafe { syscall2(SYS_FLOCK, fd, operation) }
+}
+
 pub fn sys_fpath(fd: usize, buf: &mut [u8]) -> Result<usize> {
     unsafe { syscall3(SYS_FPATH, fd, buf.as_mut_ptr() as usize, buf.len()) }
 }
@@ -128,6 +348,10 @@ pub fn sys_fstat(fd: usize, stat: &mut Stat) -> Result<usize> {
     unsafe { syscall2(SYS_FSTAT, fd, stat as *mut Stat as usize) }
 }
 
+pub fn sys_getdents(fd: usize, buf: &mut [u8]) -> Result<usize> {
+    unsafe { syscall3(SYS_GETDENTS, fd, buf.as_mut_ptr() as usize, buf.len()) }
+}
+
 pub fn sys_fsync(fd: usize) -> Result<usize> {
     unsafe { syscall1(SYS_FSYNC, fd) }
 }
@@ -144,12 +368,20 @@ pub fn sys_getpid() -> Result<usize> {
     unsafe { syscall0(SYS_GETPID) }
 }
 
+pub fn sys_getrlimit(resource: usize, rlim: &mut RLimit) -> Result<usize> {
+    unsafe { syscall2(SYS_GETRLIMIT, resource, rlim as *mut RLimit as usize) }
+}
+
+pub fn sys_getrusage(who: usize, usage: &mut RUsage) -> Result<usize> {
+    unsafe { syscall2(SYS_GETRUSAGE, who, usage as *mut RUsage as usize) }
+}
+
 pub unsafe fn sys_iopl(level: usize) -> Result<usize> {
     syscall1(SYS_IOPL, level)
 }
 
-pub unsafe fn sys_link(old: *const u8, new: *const u8) -> Result<usize> {
-    syscall2(SYS_LINK, old as usize, new as usize)
+pub fn sys_link(old: &str, new: &str) -> Result<usize> {
+    unsafe { syscall4(SYS_LINK, old.as_ptr() as usize, old.len(), new.as_ptr() as usize, new.len()) }
 }
 
 pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> Result<usize> {
@@ -160,6 +392,11 @@ pub fn sys_mkdir(path: &str, mode: usize) -> Result<usize> {
     unsafe { syscall3(SYS_MKDIR, path.as_ptr() as usize, path.len(), mode) }
 }
 
+/// Mount the directory at `target` on the path prefix `prefix`
+pub fn sys_mount(prefix: &str, target: &str) -> Result<usize> {
+    unsafe { syscall4(SYS_MOUNT, prefix.as_ptr() as usize, prefix.len(), target.as_ptr() as usize, target.len()) }
+}
+
 pub fn sys_nanosleep(req: &TimeSpec, rem: &mut TimeSpec) -> Result<usize> {
     unsafe { syscall2(SYS_NANOSLEEP, req as *const TimeSpec as usize, rem as *mut TimeSpec as usize) }
 }
@@ -172,14 +409,54 @@ pub fn sys_pipe2(fds: &mut [usize; 2], flags: usize) -> Result<usize> {
     unsafe { syscall2(SYS_PIPE2, fds.as_ptr() as usize, flags) }
 }
 
+/// Read at `offset`, without using or changing the seek position
+pub fn sys_pread(fd: usize, buf: &mut [u8], offset: usize) -> Result<usize> {
+    unsafe { syscall4(SYS_PREAD, fd, buf.as_mut_ptr() as usize, buf.len(), offset) }
+}
+
+/// Write at `offset`, without using or changing the seek position
+pub fn sys_pwrite(fd: usize, buf: &[u8], offset: usize) -> Result<usize> {
+    unsafe { syscall4(SYS_PWRITE, fd, buf.as_ptr() as usize, buf.len(), offset) }
+}
+
 pub fn sys_read(fd: usize, buf: &mut [u8]) -> Result<usize> {
     unsafe { syscall3(SYS_READ, fd, buf.as_mut_ptr() as usize, buf.len()) }
 }
 
+/// Read to each of the buffers in turn, stopping at the first one that is not filled
+pub unsafe fn sys_readv(fd: usize, iov: &[IoVec]) -> Result<usize> {
+    syscall3(SYS_READV, fd, iov.as_ptr() as usize, iov.len())
+}
+
+pub fn sys_readlink(path: &str, buf: &mut [u8]) -> Result<usize> {
+    unsafe { syscall4(SYS_READLINK, path.as_ptr() as usize, path.len(), buf.as_mut_ptr() as usize, buf.len()) }
+}
+
+pub fn sys_rename(old: &str, new: &str) -> Result<usize> {
+    unsafe { syscall4(SYS_RENAME, old.as_ptr() as usize, old.len(), new.as_ptr() as usize, new.len()) }
+}
+
 pub fn sys_rmdir(path: &str) -> Result<usize> {
     unsafe { syscall2(SYS_RMDIR, path.as_ptr() as usize, path.len()) }
 }
 
+pub fn sys_setrlimit(resource: usize, rlim: &RLimit) -> Result<usize> {
+    unsafe { syscall2(SYS_SETRLIMIT, resource, rlim as *const RLimit as usize) }
+}
+
+pub fn sys_stat(path: &str, stat: &mut Stat) -> Result<usize> {
+    unsafe { syscall3(SYS_STAT, path.as_ptr() as usize, path.len(), stat as *mut Stat as usize) }
+}
+
+pub fn sys_symlink(target: &str, path: &str) -> Result<usize> {
+    unsafe { syscall4(SYS_SYMLINK, target.as_ptr() as usize, target.len(), path.as_ptr() as usize, path.len()) }
+}
+
+/// Remove the mount at the path prefix `prefix`, which fails with `EPERM` if another process made it
+pub fn sys_umount(prefix: &str) -> Result<usize> {
+    unsafe { syscall2(SYS_UMOUNT, prefix.as_ptr() as usize, prefix.len()) }
+}
+
 pub fn sys_unlink(path: &str) -> Result<usize> {
     unsafe { syscall2(SYS_UNLINK, path.as_ptr() as usize, path.len()) }
 }
@@ -192,6 +469,11 @@ pub fn sys_write(fd: usize, buf: &[u8]) -> Result<usize> {
     unsafe { syscall3(SYS_WRITE, fd, buf.as_ptr() as usize, buf.len()) }
 }
 
+/// Write each of the buffers in turn, stopping at the first one that is not written completely
+pub unsafe fn sys_writev(fd: usize, iov: &[IoVec]) -> Result<usize> {
+    syscall3(SYS_WRITEV, fd, iov.as_ptr() as usize, iov.len())
+}
+
 pub fn sys_yield() -> Result<usize> {
     unsafe { syscall0(SYS_YIELD) }
 }
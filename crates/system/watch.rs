@@ -0,0 +1,28 @@
+/// A file or directory was created
+pub const WATCH_CREATE: u16 = 1;
+/// The contents of a file were changed, by a write or truncate
+pub const WATCH_MODIFY: u16 = 2;
+/// A file or directory was removed
+pub const WATCH_DELETE: u16 = 4;
+/// A file or directory was renamed, and this is its old path. Followed by `WATCH_RENAME_TO`
+pub const WATCH_RENAME_FROM: u16 = 8;
+/// A file or directory was renamed, and this is its new path
+pub const WATCH_RENAME_TO: u16 = 0x10;
+/// Changes were dropped, because the watcher did not read them in time or a path was too long for
+/// `WatchEvent::reclen`. The path is empty, and the watched tree should be read again
+pub const WATCH_OVERFLOW: u16 = 0x20;
+/// All kinds of events
+pub const WATCH_ALL: u16 = WATCH_CREATE | WATCH_MODIFY | WATCH_DELETE | WATCH_RENAME_FROM | WATCH_RENAME_TO;
+
+/// The header of a change event, read from the resources of the `watch:` scheme
+///
+/// The header is followed by the path of the changed file relative to the watched path, which is
+/// empty when the watched path itself changed and is not terminated. `reclen` is the length of
+/// the header and the path together, so the next event starts `reclen` bytes later.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(packed)]
+pub struct WatchEvent {
+    /// One of `WATCH_*`
+    pub kind: u16,
+    pub reclen: u16,
+}
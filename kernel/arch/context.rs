@@ -16,14 +16,15 @@ use common::time::Duration;
 
 use core::cell::UnsafeCell;
 use core::slice::{self, Iter, IterMut};
-use core::{mem, ptr};
+use core::{cmp, mem, ptr};
 use core::ops::DerefMut;
 
-use fs::Resource;
+use fs::{LockOwner, Resource};
 
-use syscall;
+use syscall::{self, RLimit, RLIMIT_AS, RLIMIT_COUNT, RLIMIT_NOFILE, RLIMIT_NPROC, RLIMIT_STACK};
 
-use system::error::{Error, Result, EBADF, EFAULT, ENOMEM, ESRCH, ENOENT, EINVAL};
+use system::error::{Error, Result, EAGAIN, EBADF, EFAULT, EMFILE, ENOMEM, ESRCH, ENOENT, EINVAL};
+use system::scheme::Packet;
 
 use sync::WaitMap;
 
@@ -40,9 +41,29 @@ pub const CONTEXT_MMAP_SIZE: usize = 0x20000000;
 
 pub const CONTEXT_STACK_ADDR: usize = CONTEXT_MMAP_ADDR + CONTEXT_MMAP_SIZE + memory::CLUSTER_SIZE;
 pub const CONTEXT_STACK_SIZE: usize = 0x100000;
+/// The most that a user stack can grow to, and the size of the space reserved for it below its top
+pub const CONTEXT_STACK_MAX_SIZE: usize = 0x800000;
+/// The amount by which user stacks grow when they fault below their bottom
+pub const CONTEXT_STACK_GROW_SIZE: usize = 0x10000;
+/// The space below the stack pointer that code may use without moving it, as the x86_64 System V
+/// ABI allows
+pub const CONTEXT_STACK_RED_ZONE: usize = 128;
+/// The size of the guard below the space reserved for a user stack, which is never mapped, so
+/// that a stack overflow faults instead of writing over other memory. The stack is placed above
+/// the mmap zone with at least this much space between them.
+pub const CONTEXT_STACK_GUARD_SIZE: usize = 0x10000;
+
+/// The default soft and hard limits on the number of open files of a context
+pub const CONTEXT_FILES_LIMIT: usize = 1024;
+pub const CONTEXT_FILES_MAX: usize = 4096;
 
 pub const CONTEXT_TLS_ADDR: usize = CONTEXT_STACK_ADDR + CONTEXT_STACK_SIZE + memory::CLUSTER_SIZE;
 
+/// The most that the base of a position independent executable, and the bases of the heap, mmap
+/// zone and stack of a program are moved at random. The stack is moved down into the end of the
+/// mmap zone, which is left unused for it.
+pub const CONTEXT_RANDOM_SIZE: usize = 0x1000000;
+
 pub struct ContextManager {
     pub inner: Vec<Box<Context>>,
     pub enabled: bool,
@@ -105,6 +126,27 @@ impl ContextManager {
         Err(Error::new(ESRCH))
     }
 
+    /// Count a context that was created or exited in the descendants of its parent, given by
+    /// `ppid`, and of the parents of that
+    pub fn count_descendant(&mut self, ppid: usize, created: bool) {
+        let mut next = ppid;
+        // The chain of parents is followed at most once around the contexts, in case PIDs are
+        // reused
+        for _ in 0..self.len() {
+            match self.find_mut(next) {
+                Ok(context) => {
+                    if created {
+                        context.descendants += 1;
+                    } else {
+                        context.descendants = context.descendants.saturating_sub(1);
+                    }
+                    next = context.ppid;
+                },
+                Err(_) => return
+            }
+        }
+    }
+
     pub fn len(&self) -> usize {
         self.inner.len()
     }
@@ -205,10 +247,30 @@ pub unsafe fn context_switch() {
     }
 }
 
+/// The resource limits of contexts that do not inherit them, such as the first user context
+fn default_rlimits() -> [RLimit; RLIMIT_COUNT] {
+    let mut rlimits = [RLimit::default(); RLIMIT_COUNT];
+    rlimits[RLIMIT_STACK] = RLimit::new(CONTEXT_STACK_MAX_SIZE);
+    rlimits[RLIMIT_NOFILE] = RLimit {
+        rlim_cur: CONTEXT_FILES_LIMIT,
+        rlim_max: CONTEXT_FILES_MAX,
+    };
+    rlimits
+}
+
 pub unsafe fn context_clone(regs: &Regs) -> Result<usize> {
     let contexts = &mut *::env().contexts.get();
     let flags = regs.bx;
 
+    // The process limit of the parent bounds the number of its own contexts, which are itself and
+    // the contexts that descend from it
+    {
+        let parent = try!(contexts.current());
+        if parent.descendants + 1 >= parent.rlimits[RLIMIT_NPROC].rlim_cur {
+            return Err(Error::new(EAGAIN));
+        }
+    }
+
     let kernel_stack = memory::alloc(CONTEXT_STACK_SIZE);
     let fx = memory::alloc(CONTEXT_FX_SIZE);
     if kernel_stack > 0 && fx > 0 {
@@ -235,25 +297,30 @@ pub unsafe fn context_clone(regs: &Regs) -> Result<usize> {
 
             memory::copy_pages(fx as *mut u8, parent.fx as *const u8, CONTEXT_FX_SIZE);
 
+            // Only the part of the stack above the stack pointer, and the red zone below it, is in
+            // use, and copied
             let stack = if let Some(ref entry) = parent.stack {
-                let physical_address = memory::alloc(entry.virtual_size);
-                if physical_address > 0 {
-                    memory::copy_pages(physical_address as *mut u8, entry.physical_address as *const u8, entry.virtual_size);
-
-                    Some(ContextMemory {
-                        physical_address: physical_address,
-                        virtual_address: entry.virtual_address,
-                        virtual_size: entry.virtual_size,
-                        writeable: entry.writeable,
-                        allocated: true,
-                    })
+                let top = entry.top();
+                let virtual_size = if regs.sp >= entry.bottom() && regs.sp < top {
+                    cmp::min(((top - regs.sp + CONTEXT_STACK_RED_ZONE)/CONTEXT_STACK_GROW_SIZE + 1) * CONTEXT_STACK_GROW_SIZE, entry.size())
                 } else {
-                    None
-                }
+                    entry.size()
+                };
+
+                entry.dup(virtual_size)
             } else {
                 None
             };
 
+            // A clone can ask for a different stack limit, which is kept large enough for the
+            // stack it starts with, and below the hard limit
+            let mut rlimits = parent.rlimits;
+            if flags & syscall::CLONE_STACK == syscall::CLONE_STACK {
+                let used = stack.as_ref().map_or(0, |stack| stack.size());
+                rlimits[RLIMIT_STACK].rlim_cur = cmp::min(cmp::max(((regs.cx + 4095)/4096) * 4096, used),
+                                                          rlimits[RLIMIT_STACK].rlim_max);
+            }
+
             let tls = if let Some(ref entry) = parent.tls {
                 if let Some(ref tls_master) = *parent.tls_master.get() {
                     let physical_address = memory::alloc(entry.virtual_size);
@@ -266,6 +333,7 @@ pub unsafe fn context_clone(regs: &Regs) -> Result<usize> {
                             virtual_address: entry.virtual_address,
                             virtual_size: entry.virtual_size,
                             writeable: entry.writeable,
+                            executable: entry.executable,
                             allocated: true,
                         })
                     } else {
@@ -331,7 +399,9 @@ pub unsafe fn context_clone(regs: &Regs) -> Result<usize> {
                         if let Ok(resource) = file.resource.dup() {
                             Some(ContextFile {
                                 fd: file.fd,
+                                flags: file.flags,
                                 resource: resource,
+                                lock_owner: file.lock_owner.clone(),
                             })
                         } else {
                             None
@@ -358,11 +428,19 @@ pub unsafe fn context_clone(regs: &Regs) -> Result<usize> {
                 exited: false,
                 switch: 0,
                 time: 0,
+                cpu_time: Duration::new(0, 0),
+                peak_memory: 0,
+                children_cpu_time: Duration::new(0, 0),
+                children_peak_memory: 0,
+                descendants: 0,
+                rlimits: rlimits,
                 vfork: vfork,
                 wake: None,
 
                 supervised: flags & syscall::CLONE_SUPERVISE == syscall::CLONE_SUPERVISE,
                 blocked_syscall: false,
+                supervise_event: None,
+                supervise_reply: None,
                 current_syscall: None,
 
                 kernel_stack: kernel_stack,
@@ -385,7 +463,9 @@ pub unsafe fn context_clone(regs: &Regs) -> Result<usize> {
             }
         };
 
+        let ppid = context.ppid;
         contexts.push(context);
+        contexts.count_descendant(ppid, true);
 
         if flags & syscall::CLONE_VFORK == syscall::CLONE_VFORK {
             context_switch();
@@ -468,19 +548,18 @@ pub struct ContextMemory {
     pub virtual_address: usize,
     pub virtual_size: usize,
     pub writeable: bool,
+    /// Whether code can be executed from the memory, which is only the case for the code
+    /// segments of an executable. This is only enforced on x86_64 processors with NX: 32-bit
+    /// x86 paging has no execute-disable bit, so all readable memory is executable there
+    pub executable: bool,
     pub allocated: bool,
 }
 
 impl ContextMemory {
     pub unsafe fn map(&mut self) {
         for i in 0..(self.virtual_size + 4095) / 4096 {
-            if self.writeable {
-                Page::new(self.virtual_address + i * 4096)
-                    .map_user_write(self.physical_address + i * 4096);
-            } else {
-                Page::new(self.virtual_address + i * 4096)
-                    .map_user_read(self.physical_address + i * 4096);
-            }
+            Page::new(self.virtual_address + i * 4096)
+                .map_user(self.physical_address + i * 4096, self.writeable, self.executable);
         }
     }
 
@@ -500,9 +579,152 @@ impl Drop for ContextMemory {
     }
 }
 
+/// The user stack of a context, which is made of pieces of memory, from its top down. It grows by
+/// mapping a new piece below its bottom, so that what is already on it is not moved.
+pub struct ContextStack {
+    pub memory: Vec<ContextMemory>,
+}
+
+impl ContextStack {
+    pub fn new(memory: ContextMemory) -> ContextStack {
+        let mut stack = ContextStack {
+            memory: Vec::new()
+        };
+        stack.memory.push(memory);
+        stack
+    }
+
+    /// The address the stack grows down from
+    pub fn top(&self) -> usize {
+        self.memory.first().map_or(0, |mem| mem.virtual_address + mem.virtual_size)
+    }
+
+    /// The lowest address of the stack
+    pub fn bottom(&self) -> usize {
+        self.memory.last().map_or(0, |mem| mem.virtual_address)
+    }
+
+    pub fn size(&self) -> usize {
+        self.top() - self.bottom()
+    }
+
+    /// Check if a range is inside of the stack
+    pub fn contains(&self, ptr: usize, len: usize) -> bool {
+        ptr >= self.bottom() && ptr + len <= self.top()
+    }
+
+    /// Translate a range of the stack to physical memory, if the pieces it is in follow each
+    /// other in physical memory
+    pub fn translate(&self, ptr: usize, len: usize) -> Option<usize> {
+        let mut physical_address = None;
+        let mut next = 0;
+        for mem in self.memory.iter().rev() {
+            let end = mem.virtual_address + mem.virtual_size;
+            if physical_address.is_none() {
+                if ptr >= mem.virtual_address && (ptr < end || ptr + len <= end) {
+                    physical_address = Some(ptr - mem.virtual_address + mem.physical_address);
+                } else {
+                    continue;
+                }
+            } else if mem.physical_address != next {
+                return None;
+            }
+
+            if ptr + len <= end {
+                return physical_address;
+            }
+            next = mem.physical_address + mem.virtual_size;
+        }
+        None
+    }
+
+    /// Map a new piece of `size` bytes below the bottom of the stack of the current context
+    pub unsafe fn grow(&mut self, size: usize) -> bool {
+        let physical_address = memory::alloc_aligned(size, 4096);
+        if physical_address > 0 {
+            let mut mem = ContextMemory {
+                physical_address: physical_address,
+                virtual_address: self.bottom() - size,
+                virtual_size: size,
+                writeable: true,
+                executable: false,
+                allocated: true,
+            };
+            mem.map();
+            self.memory.push(mem);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Copy the top `size` bytes of the stack into one piece
+    pub fn dup(&self, size: usize) -> Option<ContextStack> {
+        let top = self.top();
+        let physical_address = unsafe { memory::alloc_aligned(size, 4096) };
+        if physical_address > 0 {
+            for mem in self.memory.iter() {
+                let start = cmp::max(mem.virtual_address, top - size);
+                let end = mem.virtual_address + mem.virtual_size;
+                if start < end {
+                    unsafe {
+                        memory::copy_pages((physical_address + size - (top - start)) as *mut u8,
+                                           (mem.physical_address + start - mem.virtual_address) as *const u8,
+                                           end - start)
+                    };
+                }
+            }
+
+            Some(ContextStack::new(ContextMemory {
+                physical_address: physical_address,
+                virtual_address: top - size,
+                virtual_size: size,
+                writeable: true,
+                executable: false,
+                allocated: true,
+            }))
+        } else {
+            None
+        }
+    }
+
+    /// Move the stack of the current context into one piece, so that any range of it can be
+    /// translated
+    pub unsafe fn join(&mut self) -> bool {
+        if self.memory.len() <= 1 {
+            return true;
+        }
+
+        if let Some(mut stack) = self.dup(self.size()) {
+            self.unmap();
+            stack.map();
+            *self = stack;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub unsafe fn map(&mut self) {
+        for mem in self.memory.iter_mut() {
+            mem.map();
+        }
+    }
+
+    pub unsafe fn unmap(&mut self) {
+        for mem in self.memory.iter_mut() {
+            mem.unmap();
+        }
+    }
+}
+
 pub struct ContextFile {
     pub fd: usize,
+    /// Flags of the file descriptor, such as `FD_CLOEXEC`
+    pub flags: usize,
     pub resource: Box<Resource>,
+    /// The open file the descriptor refers to, shared with the descriptors duplicated from it
+    pub lock_owner: Arc<LockOwner>,
 }
 
 pub struct ContextZone {
@@ -534,6 +756,7 @@ impl ContextZone {
                     virtual_address: entry.virtual_address,
                     virtual_size: entry.virtual_size,
                     writeable: entry.writeable,
+                    executable: entry.executable,
                     allocated: true,
                 });
             } else {
@@ -566,6 +789,7 @@ impl ContextZone {
                             virtual_address: virtual_address,
                             virtual_size: size,
                             writeable: writeable,
+                            executable: false,
                             allocated: allocated,
                         });
 
@@ -586,6 +810,7 @@ impl ContextZone {
                     virtual_address: virtual_address,
                     virtual_size: size,
                     writeable: writeable,
+                    executable: false,
                     allocated: allocated,
                 });
 
@@ -598,6 +823,36 @@ impl ContextZone {
         }
     }
 
+    /// Access a null terminated string safely, checking each byte before it is read
+    pub fn get_c_str<'a>(&'a self, ptr: *const u8) -> Result<&'a [u8]> {
+        let mut len = 0;
+        loop {
+            let address = (ptr as usize).checked_add(len).ok_or(Error::new(EFAULT))?;
+            if *self.get_ref(address as *const u8)? == 0 {
+                return Ok(unsafe { slice::from_raw_parts(ptr, len) });
+            }
+            len += 1;
+        }
+    }
+
+    /// Access a null terminated array of pointers safely, such as the arguments of `execve`,
+    /// checking each pointer before it is read. A null array is empty
+    pub fn get_c_array<'a>(&'a self, ptr: *const *const u8) -> Result<&'a [*const u8]> {
+        if ptr.is_null() {
+            return Ok(&[]);
+        }
+
+        let mut len = 0;
+        loop {
+            let offset = len.checked_mul(mem::size_of::<*const u8>()).ok_or(Error::new(EFAULT))?;
+            let address = (ptr as usize).checked_add(offset).ok_or(Error::new(EFAULT))?;
+            if self.get_ref(address as *const *const u8)?.is_null() {
+                return Ok(unsafe { slice::from_raw_parts(ptr, len) });
+            }
+            len += 1;
+        }
+    }
+
     /// Check permission of segment, if inside of mapped memory
     pub fn permission(&self, ptr: usize, len: usize, writeable: bool) -> bool {
         for mem in self.memory.iter() {
@@ -713,6 +968,19 @@ pub struct Context {
     pub switch: usize,
     /// The number of time slices used
     pub time: usize,
+    /// The CPU time used
+    pub cpu_time: Duration,
+    /// The most memory used at once, in bytes
+    pub peak_memory: usize,
+    /// The CPU time used by children that have exited, and by their children
+    pub children_cpu_time: Duration,
+    /// The most memory used at once by a child that has exited, or by its children
+    pub children_peak_memory: usize,
+    /// The number of contexts that descend from this one and have not exited. The children of a
+    /// context that exits are handed to its parent, so they stay counted.
+    pub descendants: usize,
+    /// The resource limits, inherited across clone and exec
+    pub rlimits: [RLimit; RLIMIT_COUNT],
     /// Indicates that the context needs to unblock parent
     pub vfork: Option<*mut Context>,
     /// When to wake up
@@ -728,6 +996,10 @@ pub struct Context {
     ///
     /// This means that the process is waiting for the superviser to handle the syscall.
     pub blocked_syscall: bool,
+    /// The syscall event waiting to be read by the supervisor, with the buffer it refers to
+    pub supervise_event: Option<(Packet, Vec<u8>)>,
+    /// The reply of the supervisor to the current syscall, as an action and a value
+    pub supervise_reply: Option<(usize, usize)>,
     /// The current syscall
     pub current_syscall: Option<(usize, usize, usize, usize, usize)>,
 
@@ -738,8 +1010,8 @@ pub struct Context {
     pub regs: Regs,
     /// The location used to save and load SSE and FPU registers
     pub fx: usize,
-    /// The context stack
-    pub stack: Option<ContextMemory>,
+    /// The context stack, which grows down from a fixed top as it is used
+    pub stack: Option<ContextStack>,
     /// The context TLS
     pub tls: Option<ContextMemory>,
     /// Indicates that registers can be loaded (they must be saved first)
@@ -811,11 +1083,19 @@ impl Context {
             exited: false,
             switch: 0,
             time: 0,
+            cpu_time: Duration::new(0, 0),
+            peak_memory: 0,
+            children_cpu_time: Duration::new(0, 0),
+            children_peak_memory: 0,
+            descendants: 0,
+            rlimits: default_rlimits(),
             vfork: None,
             wake: None,
 
             supervised: false,
             blocked_syscall: false,
+            supervise_event: None,
+            supervise_reply: None,
             current_syscall: None,
 
             kernel_stack: 0,
@@ -854,11 +1134,19 @@ impl Context {
             exited: false,
             switch: 0,
             time: 0,
+            cpu_time: Duration::new(0, 0),
+            peak_memory: 0,
+            children_cpu_time: Duration::new(0, 0),
+            children_peak_memory: 0,
+            descendants: 0,
+            rlimits: default_rlimits(),
             vfork: None,
             wake: None,
 
             supervised: false,
             blocked_syscall: false,
+            supervise_event: None,
+            supervise_reply: None,
             current_syscall: None,
 
             kernel_stack: kernel_stack,
@@ -928,24 +1216,16 @@ impl Context {
         self.exited = true;
     }
 
+    /// Make a path relative to the working directory into a url. `..` is left in place, for
+    /// `Environment` to resolve after any symlinks before it
     pub fn canonicalize(&self, path: &str) -> String {
         // TODO my eyes burn, rewrite this.
         if path.find(':').is_none() {
             let cwd = unsafe { &*self.cwd.get() };
             if path == "." {
                 cwd.to_string()
-            } else if path == ".." {
-                cwd.get_slice(..cwd.get_slice(..cwd.len() - 1)
-                                   .rfind('/')
-                                   .map_or(cwd.len(), |i| i + 1))
-                   .to_string()
             } else if path.starts_with("./") {
                 cwd.to_string() + &path.get_slice(2..)
-            } else if path.starts_with("../") {
-                cwd.get_slice(..cwd.get_slice(..cwd.len() - 1)
-                                   .rfind('/')
-                                   .map_or(cwd.len(), |i| i + 1))
-                   .to_string() + &path.get_slice(3..)
             } else if path.starts_with('/') {
                 cwd.get_slice(..cwd.find(':').map_or(1, |i| i + 1)).to_string() + &path
             } else {
@@ -956,8 +1236,9 @@ impl Context {
         }
     }
 
-    /// Get the next available file descriptor
-    pub fn next_fd(&self) -> usize {
+    /// Get the next available file descriptor, failing with `EMFILE` if it is not below the open
+    /// files limit
+    pub fn next_fd(&self) -> Result<usize> {
         let mut next_fd = 0;
 
         let mut collision = true;
@@ -972,7 +1253,11 @@ impl Context {
             }
         }
 
-        return next_fd;
+        if next_fd >= self.rlimits[RLIMIT_NOFILE].rlim_cur {
+            return Err(Error::new(EMFILE));
+        }
+
+        Ok(next_fd)
     }
 
     /// Get a resource from a file descriptor
@@ -1001,6 +1286,17 @@ impl Context {
         Err(Error::new(EBADF))
     }
 
+    /// Get the lock owner of the open file a file descriptor refers to
+    pub fn get_lock_owner(&self, fd: usize) -> Result<Arc<LockOwner>> {
+        for file in unsafe { (*self.files.get()).iter() } {
+            if file.fd == fd {
+                return Ok(file.lock_owner.clone());
+            }
+        }
+
+        Err(Error::new(EBADF))
+    }
+
     pub unsafe fn push(&mut self, data: usize) {
         self.regs.sp -= mem::size_of::<usize>();
         ptr::write(self.regs.sp as *mut usize, data);
@@ -1021,7 +1317,8 @@ impl Context {
     /// Access a raw pointer safely
     pub fn get_slice<'a, T>(&'a self, ptr: *const T, len: usize) -> Result<&'a [T]> {
         if len > 0 {
-            self.permission(ptr as usize, mem::size_of::<T>() * len, false)?;
+            let size = mem::size_of::<T>().checked_mul(len).ok_or(Error::new(EFAULT))?;
+            self.permission(ptr as usize, size, false)?;
         }
         Ok(unsafe { slice::from_raw_parts(ptr, len) })
     }
@@ -1029,15 +1326,20 @@ impl Context {
     /// Access a mutable raw pointer safely
     pub fn get_slice_mut<'a, T>(&'a self, ptr: *mut T, len: usize) -> Result<&'a mut [T]> {
         if len > 0 {
-            self.permission(ptr as usize, mem::size_of::<T>() * len, true)?;
+            let size = mem::size_of::<T>().checked_mul(len).ok_or(Error::new(EFAULT))?;
+            self.permission(ptr as usize, size, true)?;
         }
         Ok(unsafe { slice::from_raw_parts_mut(ptr, len) })
     }
 
     /// Check permission of segment, if inside of mapped memory
     pub fn permission(&self, ptr: usize, len: usize, writeable: bool) -> Result<()> {
+        if ptr.checked_add(len).is_none() {
+            return Err(Error::new(EFAULT));
+        }
+
         if let Some(ref stack) = self.stack {
-            if ptr >= stack.virtual_address && ptr + len <= stack.virtual_address + stack.virtual_size {
+            if stack.contains(ptr, len) {
                 return Ok(());
             }
         }
@@ -1064,10 +1366,16 @@ impl Context {
     }
 
     /// Translate to physical if a ptr is inside of the mapped memory
-    pub fn translate(&self, ptr: usize, len: usize) -> Result<usize> {
-        if let Some(ref stack) = self.stack {
-            if ptr >= stack.virtual_address && ptr + len <= stack.virtual_address + stack.virtual_size {
-                return Ok(ptr - stack.virtual_address + stack.physical_address);
+    ///
+    /// A range of the stack that crosses pieces of it which are apart in physical memory is
+    /// first moved into one piece with the rest of the stack.
+    pub fn translate(&mut self, ptr: usize, len: usize) -> Result<usize> {
+        if let Some(ref mut stack) = self.stack {
+            if stack.contains(ptr, len) {
+                if stack.translate(ptr, len).is_none() {
+                    unsafe { stack.join() };
+                }
+                return stack.translate(ptr, len).ok_or(Error::new(EFAULT));
             }
         }
 
@@ -1092,6 +1400,57 @@ impl Context {
         Err(Error::new(EFAULT))
     }
 
+    /// The size of the address space, which is the size of the image, heap, mmap zone and stack
+    pub fn memory_size(&self) -> usize {
+        let mut size = self.stack.as_ref().map_or(0, |stack| stack.size());
+        size += unsafe { (*self.image.get()).size() };
+        size += unsafe { (*self.heap.get()).size() };
+        size += unsafe { (*self.mmap.get()).size() };
+        size
+    }
+
+    /// Check if the address space can grow by `size` without going over its limit
+    pub fn memory_allows(&self, size: usize) -> bool {
+        size <= self.rlimits[RLIMIT_AS].rlim_cur.saturating_sub(self.memory_size())
+    }
+
+    /// Record the size of the address space in the peak memory use, after it has grown
+    pub fn account_memory(&mut self) {
+        self.peak_memory = cmp::max(self.peak_memory, self.memory_size());
+    }
+
+    /// Grow the stack down to contain `address`, if it is in the space reserved for the stack
+    /// below its bottom and above its limit, and the address space limit allows it. Returns true
+    /// if the stack was grown.
+    pub unsafe fn grow_stack(&mut self, address: usize) -> bool {
+        let limit = cmp::min(self.rlimits[RLIMIT_STACK].rlim_cur, CONTEXT_STACK_MAX_SIZE);
+
+        let grow_size = match self.stack {
+            Some(ref stack) => {
+                let top = stack.top();
+                if address < stack.bottom() && address >= top - limit {
+                    cmp::min(((top - address)/CONTEXT_STACK_GROW_SIZE + 1) * CONTEXT_STACK_GROW_SIZE, limit) - stack.size()
+                } else {
+                    0
+                }
+            },
+            None => 0
+        };
+
+        let mut grown = false;
+        if grow_size > 0 && self.memory_allows(grow_size) {
+            if let Some(ref mut stack) = self.stack {
+                grown = stack.grow(grow_size);
+            }
+        }
+
+        if grown {
+            self.account_memory();
+        }
+
+        grown
+    }
+
     /// Gets an environment variable. Returns `Err` if the variable is not
     /// defined
     pub fn get_env_var(&self, var_name: &str) -> Result<&str> {
@@ -1,6 +1,7 @@
 //! ELF executables
 
 use collections::{String, Vec};
+use collections::string::ToString;
 
 use core::{ptr, str};
 
@@ -47,6 +48,62 @@ impl<'a> Elf<'a> {
         segments
     }
 
+    /// Is this a position independent executable or shared object, which can be loaded at any
+    /// base address
+    pub unsafe fn is_dynamic(&self) -> bool {
+        let header = &*(self.data.as_ptr() as usize as *const header::Header);
+        header.e_type == header::ET_DYN
+    }
+
+    /// Get the path of the interpreter from the `PT_INTERP` segment, if there is one
+    pub unsafe fn interpreter(&self) -> Option<String> {
+        let header = &*(self.data.as_ptr() as usize as *const header::Header);
+
+        for i in 0..header.e_phnum {
+            let segment = ptr::read((self.data.as_ptr() as usize + header.e_phoff as usize + i as usize * header.e_phentsize as usize) as *const program_header::ProgramHeader);
+
+            if segment.p_type == program_header::PT_INTERP {
+                let start = segment.p_offset as usize;
+                let end = start + segment.p_filesz as usize;
+                if end <= self.data.len() {
+                    if let Ok(path) = str::from_utf8(&self.data[start..end]) {
+                        return Some(path.trim_right_matches('\0').to_string());
+                    }
+                }
+                return None;
+            }
+        }
+
+        None
+    }
+
+    /// Get the address of the program headers once the executable is loaded, which is given by a
+    /// `PT_PHDR` segment, or found in the loaded segment that contains them
+    pub unsafe fn program_headers(&self) -> Option<usize> {
+        let header = &*(self.data.as_ptr() as usize as *const header::Header);
+
+        let mut address = None;
+        for i in 0..header.e_phnum {
+            let segment = ptr::read((self.data.as_ptr() as usize + header.e_phoff as usize + i as usize * header.e_phentsize as usize) as *const program_header::ProgramHeader);
+
+            if segment.p_type == program_header::PT_PHDR {
+                return Some(segment.p_vaddr as usize);
+            } else if segment.p_type == program_header::PT_LOAD
+                      && header.e_phoff >= segment.p_offset
+                      && header.e_phoff < segment.p_offset + segment.p_filesz {
+                address = Some((segment.p_vaddr + header.e_phoff - segment.p_offset) as usize);
+            }
+        }
+
+        address
+    }
+
+    /// Get the size and number of the program headers
+    pub unsafe fn program_header_count(&self) -> (usize, usize) {
+        let header = &*(self.data.as_ptr() as usize as *const header::Header);
+        (header.e_phentsize as usize, header.e_phnum as usize)
+    }
+
     /// Get the entry field of the header
     pub unsafe fn entry(&self) -> usize {
         let header = &*(self.data.as_ptr() as usize as *const header::Header);
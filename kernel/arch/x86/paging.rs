@@ -27,6 +27,87 @@ pub const PF_STACK: usize = 1 << 11;
 pub const PF_ALL: usize =  0xFFF;
 pub const PF_NONE: usize = 0xFFFFF000;
 
+//Control register 4 flags
+pub const CR4_SMEP: usize = 1 << 20;
+pub const CR4_SMAP: usize = 1 << 21;
+
+/// Whether the kernel is prevented from accessing user pages, except while user access is
+/// allowed, which is enabled if the processor supports it
+static mut SMAP: bool = false;
+
+/// Get `eax`, `ebx`, `ecx` and `edx` of a CPUID leaf
+unsafe fn cpuid(leaf: u32) -> (u32, u32, u32, u32) {
+    let eax: u32;
+    let ebx: u32;
+    let ecx: u32;
+    let edx: u32;
+    asm!("cpuid"
+        : "={eax}"(eax), "={ebx}"(ebx), "={ecx}"(ecx), "={edx}"(edx)
+        : "{eax}"(leaf), "{ecx}"(0)
+        :
+        : "intel", "volatile");
+    (eax, ebx, ecx, edx)
+}
+
+/// Enable the protections of user pages that the processor supports, which are supervisor mode
+/// execution prevention and supervisor mode access prevention
+///
+/// Execute-disable is not supported on x86: it needs PAE, which this paging code does not use,
+/// so user data and stacks are executable. Only the x86_64 kernel enforces it.
+unsafe fn init_protection() {
+    let (max_leaf, _, _, _) = cpuid(0);
+
+    if max_leaf >= 7 {
+        let (_, ebx, _, _) = cpuid(7);
+
+        let mut flags = 0;
+        if ebx & 1 << 7 == 1 << 7 {
+            flags |= CR4_SMEP;
+        }
+        if ebx & 1 << 20 == 1 << 20 {
+            flags |= CR4_SMAP;
+            SMAP = true;
+        }
+
+        if flags > 0 {
+            asm!("mov eax, cr4
+                or eax, $0
+                mov cr4, eax"
+                :
+                : "r"(flags)
+                : "eax", "memory"
+                : "intel", "volatile");
+        }
+    }
+}
+
+/// Allow the kernel to access user pages, as it does for system calls
+pub unsafe fn allow_user_access() {
+    if SMAP {
+        asm!("stac" : : : "memory" : "intel", "volatile");
+    }
+}
+
+/// Prevent the kernel from accessing user pages
+pub unsafe fn deny_user_access() {
+    if SMAP {
+        asm!("clac" : : : "memory" : "intel", "volatile");
+    }
+}
+
+/// Check if the kernel can access user pages, which is the case in system calls, and not in
+/// kernel threads
+pub unsafe fn user_access_allowed() -> bool {
+    let flags: usize;
+    asm!("pushfd
+        pop $0"
+        : "=r"(flags)
+        :
+        : "memory"
+        : "intel", "volatile");
+    ! SMAP || flags & 1 << 18 == 1 << 18
+}
+
 pub struct Pager {
     directory: usize,
     flags: usize,
@@ -234,6 +315,8 @@ impl Page {
             : "r"(PAGE_DIRECTORY), "r"(1 << 31 | 1 << 16)
             : "memory"
             : "intel", "volatile");
+
+        init_protection();
     }
 
     /// Create a new memory page from a virtual address
@@ -303,6 +386,19 @@ impl Page {
         self.flush();
     }
 
+    /// Map the memory page to a given physical memory address, and allow userspace access, which
+    /// can write if `writeable`
+    ///
+    /// `executable` is ignored, as there is no execute-disable bit without PAE, so every page
+    /// that can be read can be executed. NX is only enforced on x86_64.
+    pub unsafe fn map_user(&mut self, physical_address: usize, writeable: bool, _executable: bool) {
+        if writeable {
+            self.map_user_write(physical_address);
+        } else {
+            self.map_user_read(physical_address);
+        }
+    }
+
     /// Unmap the memory page
     pub unsafe fn unmap(&mut self) {
         self.set_entry_data(0);
@@ -15,8 +15,108 @@ pub const PF_ALLOC: usize = 1 << 9;
 pub const PF_EXEC: usize = 1 << 10;
 pub const PF_STACK: usize = 1 << 11;
 
+//Execute-disable, only set if `NX` is enabled
+pub const PF_NO_EXEC: usize = 1 << 63;
+
 pub const PF_ALL: usize =  0xFFF;
-pub const PF_NONE: usize = 0xFFFFFFFFFFFFF000;
+pub const PF_NONE: usize = 0x000FFFFFFFFFF000;
+
+//Control register 4 flags
+pub const CR4_SMEP: usize = 1 << 20;
+pub const CR4_SMAP: usize = 1 << 21;
+
+/// Whether pages can be marked execute-disable, which is enabled if the processor supports it
+static mut NX: bool = false;
+
+/// Whether the kernel is prevented from accessing user pages, except while user access is
+/// allowed, which is enabled if the processor supports it
+static mut SMAP: bool = false;
+
+/// Get `eax`, `ebx`, `ecx` and `edx` of a CPUID leaf
+unsafe fn cpuid(leaf: u32) -> (u32, u32, u32, u32) {
+    let eax: u32;
+    let ebx: u32;
+    let ecx: u32;
+    let edx: u32;
+    asm!("cpuid"
+        : "={eax}"(eax), "={ebx}"(ebx), "={ecx}"(ecx), "={edx}"(edx)
+        : "{eax}"(leaf), "{ecx}"(0)
+        :
+        : "intel", "volatile");
+    (eax, ebx, ecx, edx)
+}
+
+/// Enable the protections of user pages that the processor supports, which are execute-disable,
+/// supervisor mode execution prevention, and supervisor mode access prevention
+unsafe fn init_protection() {
+    let (max_leaf, _, _, _) = cpuid(0);
+    let (max_extended_leaf, _, _, _) = cpuid(0x80000000);
+
+    if max_extended_leaf >= 0x80000001 {
+        let (_, _, _, edx) = cpuid(0x80000001);
+        if edx & 1 << 20 == 1 << 20 {
+            // Set NXE in EFER
+            asm!("rdmsr
+                or eax, 1 << 11
+                wrmsr"
+                :
+                : "{ecx}"(0xC0000080u32)
+                : "eax", "edx", "memory"
+                : "intel", "volatile");
+            NX = true;
+        }
+    }
+
+    if max_leaf >= 7 {
+        let (_, ebx, _, _) = cpuid(7);
+
+        let mut flags = 0;
+        if ebx & 1 << 7 == 1 << 7 {
+            flags |= CR4_SMEP;
+        }
+        if ebx & 1 << 20 == 1 << 20 {
+            flags |= CR4_SMAP;
+            SMAP = true;
+        }
+
+        if flags > 0 {
+            asm!("mov rax, cr4
+                or rax, $0
+                mov cr4, rax"
+                :
+                : "r"(flags)
+                : "rax", "memory"
+                : "intel", "volatile");
+        }
+    }
+}
+
+/// Allow the kernel to access user pages, as it does for system calls
+pub unsafe fn allow_user_access() {
+    if SMAP {
+        asm!("stac" : : : "memory" : "intel", "volatile");
+    }
+}
+
+/// Prevent the kernel from accessing user pages
+pub unsafe fn deny_user_access() {
+    if SMAP {
+        asm!("clac" : : : "memory" : "intel", "volatile");
+    }
+}
+
+/// Check if the kernel can access user pages, which is the case in system calls, and not in
+/// kernel threads
+pub unsafe fn user_access_allowed() -> bool {
+    let flags: usize;
+    asm!("pushfq
+        pop $0"
+        : "=r"(flags)
+        :
+        : "memory"
+        : "intel", "volatile");
+    ! SMAP || flags & 1 << 18 == 1 << 18
+}
 
 // PAGE_LEVEL_4:
 // 512 qwords pointing to page directory pointers
@@ -91,6 +191,8 @@ impl Page {
             : "r"(PAGE_LEVEL_4), "r"((1 << 31 | 1 << 16) as usize)
             : "memory"
             : "intel", "volatile");
+
+        init_protection();
     }
 
     /// Create a new memory page from a virtual address
@@ -154,6 +256,20 @@ impl Page {
         self.flush();
     }
 
+    /// Map the memory page to a given physical memory address and allow userspace access, which
+    /// can write if `writeable` and execute if `executable`
+    pub unsafe fn map_user(&mut self, physical_address: usize, writeable: bool, executable: bool) {
+        let mut flags = PF_USER | PF_PRESENT;
+        if writeable {
+            flags |= PF_WRITE;
+        }
+        if ! executable && NX {
+            flags |= PF_NO_EXEC;
+        }
+        ptr::write(self.entry_address() as *mut usize, (physical_address & PF_NONE) | flags);
+        self.flush();
+    }
+
     /// Unmap the memory page
     pub unsafe fn unmap(&mut self) {
         ptr::write(self.entry_address() as *mut usize, 0);
@@ -47,7 +47,8 @@ interrupts:
     mov eax, gdt.user_tls | 3
     mov gs, eax
 
-    add esp, 8 ; Skip interrupt code and reg pointer
+    cmp dword [esp], 0xE ; Page faults push an error code, which has to be skipped on return
+    lea esp, [esp + 8] ; Skip interrupt code and reg pointer
 
     pop eax
     pop ebx
@@ -57,6 +58,9 @@ interrupts:
     pop esi
     pop ebp
 
+    jne .iret
+    lea esp, [esp + 4] ; Skip error code
+.iret:
     iretd
 
 .handler: dd 0
@@ -58,7 +58,8 @@ interrupts:
 	mov rax, gdt.user_tls | 3
     mov fs, rax
 
-	add rsp, 16 ; Skip interrupt code and reg pointer
+	cmp qword [rsp], 0xE ; Page faults push an error code, which has to be skipped on return
+	lea rsp, [rsp + 16] ; Skip interrupt code and reg pointer
 
 	pop rax
 	pop rbx
@@ -76,6 +77,9 @@ interrupts:
 	pop r15
 	pop rbp
 
+	jne .iret
+	lea rsp, [rsp + 8] ; Skip error code
+.iret:
     iretq
 
 .handler: dq 0
@@ -124,7 +124,7 @@ impl Resource for Ac97Resource {
                 let mut phys_buf = buf.as_ptr() as usize;
                 {
                     let contexts = &mut *::env().contexts.get();
-                    if let Ok(current) = contexts.current() {
+                    if let Ok(current) = contexts.current_mut() {
                         if let Ok(phys) = current.translate(buf.as_ptr().offset(position as isize) as usize, bytes) {
                             debugln!("logical {:#X} -> physical {:#X}", &(buf.as_ptr() as usize), &phys);
                             phys_buf = phys;
@@ -5,6 +5,8 @@ pub const EVENT_NONE: i64 = 0;
 pub const EVENT_MOUSE: i64 = 1;
 pub const EVENT_KEY: i64 = 2;
 pub const EVENT_QUIT: i64 = 3;
+pub const EVENT_SCREEN: i64 = 4;
+pub const EVENT_SCROLL: i64 = 5;
 
 /// An optional event
 #[derive(Copy, Clone, Debug)]
@@ -15,6 +17,10 @@ pub enum EventOption {
     Key(KeyEvent),
     /// A quit request event
     Quit(QuitEvent),
+    /// A screen mode change event
+    Screen(ScreenEvent),
+    /// A scroll wheel event
+    Scroll(ScrollEvent),
     /// An unknown event
     Unknown(Event),
     /// No event
@@ -50,6 +56,8 @@ impl Event {
             EVENT_MOUSE => EventOption::Mouse(MouseEvent::from_event(self)),
             EVENT_KEY => EventOption::Key(KeyEvent::from_event(self)),
             EVENT_QUIT => EventOption::Quit(QuitEvent::from_event(self)),
+            EVENT_SCREEN => EventOption::Screen(ScreenEvent::from_event(self)),
+            EVENT_SCROLL => EventOption::Scroll(ScrollEvent::from_event(self)),
             _ => EventOption::Unknown(self),
         }
     }
@@ -282,3 +290,64 @@ impl QuitEvent {
         QuitEvent
     }
 }
+
+/// A screen mode change event
+#[derive(Copy, Clone, Debug)]
+pub struct ScreenEvent {
+    /// The new width in pixels
+    pub width: usize,
+    /// The new height in pixels
+    pub height: usize,
+    /// The new bits per pixel
+    pub depth: usize,
+}
+
+impl ScreenEvent {
+    /// Convert to an `Event`
+    pub fn to_event(&self) -> Event {
+        Event {
+            code: EVENT_SCREEN,
+            a: self.width as i64,
+            b: self.height as i64,
+            c: self.depth as i64,
+        }
+    }
+
+    /// Convert from an `Event`
+    pub fn from_event(event: Event) -> ScreenEvent {
+        ScreenEvent {
+            width: event.a as usize,
+            height: event.b as usize,
+            depth: event.c as usize,
+        }
+    }
+}
+
+/// A scroll wheel event
+#[derive(Copy, Clone, Debug)]
+pub struct ScrollEvent {
+    /// Horizontal scroll, positive to the right
+    pub x: i32,
+    /// Vertical scroll, positive away from the user
+    pub y: i32,
+}
+
+impl ScrollEvent {
+    /// Convert to an `Event`
+    pub fn to_event(&self) -> Event {
+        Event {
+            code: EVENT_SCROLL,
+            a: self.x as i64,
+            b: self.y as i64,
+            c: 0,
+        }
+    }
+
+    /// Convert from an `Event`
+    pub fn from_event(event: Event) -> ScrollEvent {
+        ScrollEvent {
+            x: event.a as i32,
+            y: event.b as i32,
+        }
+    }
+}
@@ -1,19 +1,105 @@
+use core::mem::size_of;
+
 static mut seed: u64 = 19940046431; //259261034506304368955239; //1706322144714608529217229883707268827757977089;
 
 /// Generate pseudo random number
+///
+/// This is xorshift64*, the high bits of which are the most random, so those are returned.
 pub fn rand() -> usize {
     unsafe {
-        seed ^= seed << 12;
+        seed ^= seed >> 12;
         seed ^= seed << 25;
-        seed ^= seed << 27;
-        seed = seed * 82724793451 + 12345;
-        seed as usize % ::core::usize::MAX
+        seed ^= seed >> 27;
+        (seed.wrapping_mul(2685821657736338717) >> (64 - 8 * size_of::<usize>())) as usize
     }
 }
 
-/// Set the seed
+/// Set the seed, which cannot be zero
 pub fn srand(s: usize) {
     unsafe {
-        seed = s as u64;
+        seed = if s == 0 { 1 } else { s as u64 };
+    }
+}
+
+/// Seed the generator from the processor's random number generator if it has one, and from the
+/// time stamp counter mixed with `time` otherwise
+pub fn seed_entropy(time: u64) {
+    let entropy = match rdrand() {
+        Some(value) => value as u64 ^ rdtsc().rotate_left(32),
+        None => rdtsc() ^ time.rotate_left(32),
+    };
+    unsafe {
+        seed = if entropy == 0 { 1 } else { entropy };
+    }
+    // Spread the entropy over the whole state
+    for _ in 0..8 {
+        rand();
+    }
+}
+
+/// Generate a random number for uses that have to be hard to guess, such as the placement of
+/// programs in memory, from the processor's random number generator if it has one, and from the
+/// pseudo random generator mixed with the time stamp counter otherwise
+pub fn random() -> usize {
+    match rdrand() {
+        Some(value) => value,
+        None => rand() ^ rdtsc() as usize,
+    }
+}
+
+/// Fill a buffer with `random` bytes
+pub fn random_bytes(buf: &mut [u8]) {
+    for chunk in buf.chunks_mut(size_of::<usize>()) {
+        let value = random();
+        for (i, b) in chunk.iter_mut().enumerate() {
+            *b = (value >> (8 * i)) as u8;
+        }
+    }
+}
+
+/// Get a number from `RDRAND`, if the processor supports it
+fn rdrand() -> Option<usize> {
+    let ecx: u32;
+    unsafe {
+        asm!("cpuid"
+            : "={ecx}"(ecx)
+            : "{eax}"(1), "{ecx}"(0)
+            : "eax", "ebx", "edx"
+            : "intel", "volatile");
+    }
+    if ecx & 1 << 30 != 1 << 30 {
+        return None;
+    }
+
+    // The generator can run out for a moment, so it is retried a few times
+    for _ in 0..10 {
+        let value: usize;
+        let ok: u8;
+        unsafe {
+            asm!("rdrand $0
+                setc $1"
+                : "=r"(value), "=r"(ok)
+                :
+                : "cc"
+                : "intel", "volatile");
+        }
+        if ok == 1 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Read the time stamp counter
+fn rdtsc() -> u64 {
+    let low: u32;
+    let high: u32;
+    unsafe {
+        asm!("rdtsc"
+            : "={eax}"(low), "={edx}"(high)
+            :
+            :
+            : "intel", "volatile");
     }
+    (high as u64) << 32 | low as u64
 }
@@ -319,8 +319,8 @@ impl HbaPort {
         // debugln!("AHCI {:X} DMA BLOCK: {:X} SECTORS: {} BUF: {:X} WRITE: {}", (self as *mut HbaPort) as usize, block, sectors, buf, write);
 
         if sectors > 0 {
-            let contexts = unsafe { & *::env().contexts.get() };
-            let current = try!(contexts.current());
+            let contexts = unsafe { &mut *::env().contexts.get() };
+            let current = try!(contexts.current_mut());
             let physical_address = try!(current.translate(buf, sectors * 512));
 
             let mut sector: usize = 0;
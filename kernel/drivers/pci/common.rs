@@ -102,6 +102,8 @@ pub mod vendorid {
     pub const INTEL: u16 = 0x8086;
     pub const REALTEK: u16 = 0x10EC;
     pub const REDHAT: u16 = 0x1AF4;
+    pub const QEMU: u16 = 0x1234;
+    pub const VIRTUALBOX: u16 = 0x80EE;
     pub const ILLEGAL: u16 = 0xFFFF;
 }
 
@@ -114,4 +116,10 @@ pub mod deviceid {
     pub const AC97_82801AA: u16 = 0x2415;   // 82801AA AC'97 Audio Controller
     pub const AC97_ICH4: u16 = 0x24C5;      // 82801DB/DBL/DBM (ICH4/ICH4-L/ICH4-M) AC'97 Audio
     pub const INTELHDA_ICH6: u16 = 0x2668;  // 82801FB/FBM/FR/FW/FRW High Definition Audio
+
+    // QEMU
+    pub const BGA_QEMU: u16 = 0x1111;       // Standard VGA (Bochs Graphics Adaptor)
+
+    // VirtualBox
+    pub const BGA_VBOX: u16 = 0xBEEF;       // VirtualBox Graphics Adapter
 }
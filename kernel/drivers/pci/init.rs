@@ -14,6 +14,8 @@ use super::common::programming_interface::*;
 use super::common::vendorid::*;
 use super::common::deviceid::*;
 
+use graphics::bga::Bga;
+
 use audio::ac97::Ac97;
 use audio::intelhda::IntelHda;
 
@@ -50,6 +52,9 @@ pub unsafe fn pci_device(env: &mut Environment,
             (INTEL, AC97_82801AA) => (&mut *env.schemes.get()).push(Ac97::new(pci)),
             (INTEL, AC97_ICH4) => (&mut *env.schemes.get()).push(Ac97::new(pci)),
             (INTEL, INTELHDA_ICH6) => (&mut *env.schemes.get()).push(IntelHda::new(pci)),
+            (QEMU, BGA_QEMU) | (VIRTUALBOX, BGA_VBOX) => if let Some(bga) = Bga::new(pci) {
+                (&mut *env.console.get()).device = Some(bga);
+            },
             _ => syslog_info!(" ? CLASS {:02X}.{:02X}.{:02X} ID {:04X}:{:04X}", class_id, subclass_id, interface_id, vendor_code, device_code),
         }
     }
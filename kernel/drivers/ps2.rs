@@ -4,13 +4,25 @@ use collections::String;
 
 use core::cmp;
 
-use common::event::{KeyEvent, MouseEvent};
+use common::event::{KeyEvent, MouseEvent, ScrollEvent};
+use common::to_num::ToNum;
 
 use drivers::io::{Io, Pio, ReadOnly, WriteOnly};
 
-use graphics::display::VBEMODEINFO;
+use fs::{KScheme, Resource};
 
-use fs::KScheme;
+use system::error::{Error, Result, EINVAL, ENOENT};
+use system::input::{BTN_LEFT, BTN_MIDDLE, BTN_RIGHT, BTN_SIDE, BTN_EXTRA, REL_X, REL_Y, REL_WHEEL};
+
+/// Mouse ID of a standard mouse, sending 3 byte packets
+const MOUSE_ID_STANDARD: u8 = 0;
+/// Mouse ID of an IntelliMouse, with a scroll wheel
+const MOUSE_ID_INTELLIMOUSE: u8 = 3;
+/// Mouse ID of an IntelliMouse Explorer, with a scroll wheel and five buttons
+const MOUSE_ID_EXPLORER: u8 = 4;
+
+/// Sample rates accepted by the mouse, in samples per second
+const MOUSE_RATES: [u8; 7] = [10, 20, 40, 60, 80, 100, 200];
 
 use drivers::kb_layouts::layouts;
 
@@ -39,6 +51,22 @@ impl<'a> Ps2Mouse<'a> {
         self.bus.wait_read();
         self.bus.data.read()
     }
+
+    fn set_sample_rate(&mut self, rate: u8) {
+        self.cmd(0xF3);
+        self.cmd(rate);
+    }
+
+    fn set_resolution(&mut self, resolution: u8) {
+        self.cmd(0xE8);
+        self.cmd(resolution);
+    }
+
+    fn id(&mut self) -> u8 {
+        self.cmd(0xF2);
+        self.bus.wait_read();
+        self.bus.data.read()
+    }
 }
 
 /// PS2
@@ -69,9 +97,19 @@ pub struct Ps2 {
     mouse_x: i32,
     /// Mouse point y
     mouse_y: i32,
+    /// Mouse ID, selecting the packet format
+    mouse_id: u8,
+    /// Mouse sample rate
+    mouse_rate: u8,
+    /// Mouse resolution, from 0 for 1 count/mm to 3 for 8 counts/mm
+    mouse_resolution: u8,
     /// Layout for keyboard
     /// Default: English
     layout: layouts::Layout,
+    /// Input device of the keyboard
+    keyboard_input: usize,
+    /// Input device of the mouse
+    mouse_input: usize,
 }
 
 impl Ps2 {
@@ -91,11 +129,22 @@ impl Ps2 {
             mouse_i: 0,
             mouse_x: 0,
             mouse_y: 0,
+            mouse_id: MOUSE_ID_STANDARD,
+            mouse_rate: 100,
+            mouse_resolution: 2,
             layout: layouts::Layout::English,
+            keyboard_input: 0,
+            mouse_input: 0,
         };
 
         module.init();
 
+        {
+            let input = unsafe { &mut *::env().input.get() };
+            module.keyboard_input = input.add_device("ps2_keyboard");
+            module.mouse_input = input.add_device("ps2_mouse");
+        }
+
         module
     }
 
@@ -208,6 +257,14 @@ impl Ps2 {
                 syslog_info!("     - Extra {}: {:X}", line!(), self.data.read());
             }
 
+            // Enable the scroll wheel and extra buttons
+            self.mouse_negotiate();
+            syslog_info!("     - Mouse ID {}", self.mouse_id);
+
+            while self.sts.readf(1) {
+                syslog_info!("     - Extra {}: {:X}", line!(), self.data.read());
+            }
+
             // Enable Streaming
             self.mouse().cmd(0xF4);
 
@@ -224,6 +281,63 @@ impl Ps2 {
         }
     }
 
+    /// Negotiate the IntelliMouse and IntelliMouse Explorer protocols, using the magic sample rate
+    /// sequences, then restore the sample rate and resolution
+    fn mouse_negotiate(&mut self) {
+        for &rate in [200, 100, 80].iter() {
+            self.mouse().set_sample_rate(rate);
+        }
+        self.mouse_id = self.mouse().id();
+
+        if self.mouse_id == MOUSE_ID_INTELLIMOUSE {
+            for &rate in [200, 200, 80].iter() {
+                self.mouse().set_sample_rate(rate);
+            }
+            self.mouse_id = self.mouse().id();
+        }
+
+        if self.mouse_id != MOUSE_ID_INTELLIMOUSE && self.mouse_id != MOUSE_ID_EXPLORER {
+            self.mouse_id = MOUSE_ID_STANDARD;
+        }
+
+        let rate = self.mouse_rate;
+        self.mouse().set_sample_rate(rate);
+        let resolution = self.mouse_resolution;
+        self.mouse().set_resolution(resolution);
+    }
+
+    /// Change the sample rate and resolution of the mouse
+    pub fn mouse_configure(&mut self, rate: u8, resolution: u8) -> Result<()> {
+        if ! MOUSE_RATES.contains(&rate) || resolution > 3 {
+            return Err(Error::new(EINVAL));
+        }
+
+        // Mask the port interrupts, so that the interrupt handlers cannot consume the replies to
+        // the commands: no interrupts, system flag set, clocks enabled, translation enabled
+        self.write(0x60, 0b01000100);
+
+        // Disable streaming while the mouse answers commands
+        self.mouse().cmd(0xF5);
+
+        self.mouse_rate = rate;
+        self.mouse_resolution = resolution;
+        self.mouse().set_sample_rate(rate);
+        self.mouse().set_resolution(resolution);
+
+        // Enable Streaming
+        self.mouse().cmd(0xF4);
+
+        while self.sts.readf(1) {
+            self.data.read();
+        }
+        self.mouse_i = 0;
+
+        // Key and mouse interrupts, system flag set, clocks enabled, translation enabled
+        self.write(0x60, 0b01000111);
+
+        Ok(())
+    }
+
     /// Keyboard interrupt
     pub fn keyboard_interrupt(&mut self, mut scancode: u8) -> Option<KeyEvent> {
         if scancode == 0 {
@@ -316,56 +430,89 @@ impl Ps2 {
     }
 
     /// Mouse interrupt
-    pub fn mouse_interrupt(&mut self, byte: u8) -> Option<MouseEvent> {
-        if self.mouse_i == 0 {
-            if byte & 0x8 == 0x8 {
-                self.mouse_packet[0] = byte;
-                self.mouse_i += 1;
-            }
-        } else if self.mouse_i == 1 {
-            self.mouse_packet[1] = byte;
+    pub fn mouse_interrupt(&mut self, byte: u8) -> Option<(MouseEvent, Option<ScrollEvent>)> {
+        if self.mouse_i == 0 && byte & 0x8 != 0x8 {
+            return None;
+        }
+
+        self.mouse_packet[self.mouse_i] = byte;
+        self.mouse_i += 1;
 
-            self.mouse_i += 1;
+        let packet_len = if self.mouse_id == MOUSE_ID_STANDARD {
+            3
         } else {
-            self.mouse_packet[2] = byte;
+            4
+        };
 
-            let left_button = (self.mouse_packet[0] & 1) == 1;
-            let right_button = (self.mouse_packet[0] & 2) == 2;
-            let middle_button = (self.mouse_packet[0] & 4) == 4;
+        if self.mouse_i < packet_len {
+            return None;
+        }
 
-            let x;
-            if (self.mouse_packet[0] & 0x40) != 0x40 && self.mouse_packet[1] != 0 {
-                x = (self.mouse_packet[1] as isize -
-                     (((self.mouse_packet[0] as isize) << 4) & 0x100)) as i32;
-            } else {
-                x = 0;
-            }
+        self.mouse_i = 0;
 
-            let y;
-            if (self.mouse_packet[0] & 0x80) != 0x80 && self.mouse_packet[2] != 0 {
-                y = ((((self.mouse_packet[0] as isize) << 3) & 0x100) -
-                     self.mouse_packet[2] as isize) as i32;
-            } else {
-                y = 0;
-            }
+        let left_button = (self.mouse_packet[0] & 1) == 1;
+        let right_button = (self.mouse_packet[0] & 2) == 2;
+        let middle_button = (self.mouse_packet[0] & 4) == 4;
 
-            if let Some(mode_info) = unsafe { VBEMODEINFO } {
-                self.mouse_x = cmp::max(0, cmp::min(mode_info.xresolution as i32, self.mouse_x + x));
-                self.mouse_y = cmp::max(0, cmp::min(mode_info.yresolution as i32, self.mouse_y + y));
-            }
+        let x;
+        if (self.mouse_packet[0] & 0x40) != 0x40 && self.mouse_packet[1] != 0 {
+            x = (self.mouse_packet[1] as isize -
+                 (((self.mouse_packet[0] as isize) << 4) & 0x100)) as i32;
+        } else {
+            x = 0;
+        }
 
-            self.mouse_i = 0;
+        let y;
+        if (self.mouse_packet[0] & 0x80) != 0x80 && self.mouse_packet[2] != 0 {
+            y = ((((self.mouse_packet[0] as isize) << 3) & 0x100) -
+                 self.mouse_packet[2] as isize) as i32;
+        } else {
+            y = 0;
+        }
+
+        // The wheel counts towards the user, scroll events count away from the user
+        let (z, side_button, extra_button) = match self.mouse_id {
+            MOUSE_ID_INTELLIMOUSE => (self.mouse_packet[3] as i8 as i32, false, false),
+            MOUSE_ID_EXPLORER => (((self.mouse_packet[3] << 4) as i8 >> 4) as i32,
+                                  self.mouse_packet[3] & 0x10 == 0x10,
+                                  self.mouse_packet[3] & 0x20 == 0x20),
+            _ => (0, false, false)
+        };
 
-            return Some(MouseEvent {
-                x: self.mouse_x,
-                y: self.mouse_y,
-                left_button: left_button,
-                right_button: right_button,
-                middle_button: middle_button,
-            });
+        if let Some(ref display) = unsafe { & *::env().console.get() }.display {
+            self.mouse_x = cmp::max(0, cmp::min(display.width as i32, self.mouse_x + x));
+            self.mouse_y = cmp::max(0, cmp::min(display.height as i32, self.mouse_y + y));
+        }
+
+        {
+            let input = unsafe { &mut *::env().input.get() };
+            input.relative(self.mouse_input, REL_X, x);
+            input.relative(self.mouse_input, REL_Y, y);
+            input.relative(self.mouse_input, REL_WHEEL, -z);
+            input.buttons(self.mouse_input, (left_button as u32) << BTN_LEFT |
+                                            (right_button as u32) << BTN_RIGHT |
+                                            (middle_button as u32) << BTN_MIDDLE |
+                                            (side_button as u32) << BTN_SIDE |
+                                            (extra_button as u32) << BTN_EXTRA);
+            input.sync(self.mouse_input);
         }
 
-        return None;
+        let scroll_event = if z != 0 {
+            Some(ScrollEvent {
+                x: 0,
+                y: -z,
+            })
+        } else {
+            None
+        };
+
+        Some((MouseEvent {
+            x: self.mouse_x,
+            y: self.mouse_y,
+            left_button: left_button,
+            right_button: right_button,
+            middle_button: middle_button,
+        }, scroll_event))
     }
 
     /// Function to change the layout of the keyboard
@@ -379,23 +526,122 @@ impl Ps2 {
     }
 }
 
+/// A resource for reading and changing the mouse settings
+///
+/// Reading returns the current settings. Writing lines of `rate=N` or `resolution=N` changes them.
+pub struct Ps2MouseResource {
+    ps2: *mut Ps2,
+    seek: usize,
+}
+
+impl Ps2MouseResource {
+    fn settings(&self) -> String {
+        let ps2 = unsafe { &*self.ps2 };
+        let (wheel, buttons) = match ps2.mouse_id {
+            MOUSE_ID_INTELLIMOUSE => (1, 3),
+            MOUSE_ID_EXPLORER => (1, 5),
+            _ => (0, 3)
+        };
+        format!("rate={}\nresolution={}\nwheel={}\nbuttons={}\n", ps2.mouse_rate, ps2.mouse_resolution, wheel, buttons)
+    }
+}
+
+impl Resource for Ps2MouseResource {
+    fn dup(&self) -> Result<Box<Resource>> {
+        Ok(box Ps2MouseResource {
+            ps2: self.ps2,
+            seek: self.seek,
+        })
+    }
+
+    fn path(&self, buf: &mut [u8]) -> Result<usize> {
+        let path = b"ps2:mouse";
+
+        let mut i = 0;
+        while i < buf.len() && i < path.len() {
+            buf[i] = path[i];
+            i += 1;
+        }
+
+        Ok(i)
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let settings = self.settings();
+        let settings = settings.as_bytes();
+
+        let mut i = 0;
+        while i < buf.len() && self.seek < settings.len() {
+            buf[i] = settings[self.seek];
+            i += 1;
+            self.seek += 1;
+        }
+
+        Ok(i)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let ps2 = unsafe { &mut *self.ps2 };
+        let mut rate = ps2.mouse_rate;
+        let mut resolution = ps2.mouse_resolution;
+
+        for line in String::from_utf8_lossy(buf).lines() {
+            let mut parts = line.splitn(2, '=');
+            match (parts.next().unwrap_or("").trim(), parts.next()) {
+                ("rate", Some(value)) => rate = value.trim().to_num() as u8,
+                ("resolution", Some(value)) => resolution = value.trim().to_num() as u8,
+                ("", None) => (),
+                _ => return Err(Error::new(EINVAL))
+            }
+        }
+
+        try!(ps2.mouse_configure(rate, resolution));
+
+        Ok(buf.len())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
 impl KScheme for Ps2 {
+    fn scheme(&self) -> &str {
+        "ps2"
+    }
+
+    fn open(&mut self, url: &str, _: usize) -> Result<Box<Resource>> {
+        if url.splitn(2, ":").nth(1).unwrap_or("").trim_matches('/') == "mouse" {
+            Ok(box Ps2MouseResource {
+                ps2: self as *mut Ps2,
+                seek: 0,
+            })
+        } else {
+            Err(Error::new(ENOENT))
+        }
+    }
+
     fn on_irq(&mut self, irq: u8) {
         if irq == 0xC || irq == 0x1 {
             loop {
                 let status = self.sts.read();
                 if status & 0x21 == 0x21 {
                     let data = self.data.read();
-                    if let Some(mouse_event) = self.mouse_interrupt(data) {
+                    if let Some((mouse_event, scroll_event)) = self.mouse_interrupt(data) {
                         if unsafe { & *::env().console.get() }.draw {
                             //Ignore mouse event
                         } else {
                             ::env().events.send(mouse_event.to_event(), "Ps2::on_irq mouse");
+                            if let Some(scroll_event) = scroll_event {
+                                ::env().events.send(scroll_event.to_event(), "Ps2::on_irq scroll");
+                            }
                         }
                     }
                 } else if status & 0x21 == 0x01 {
                     let data = self.data.read();
                     if let Some(key_event) = self.keyboard_interrupt(data) {
+                        unsafe { &mut *::env().input.get() }.key(self.keyboard_input, key_event.scancode, key_event.pressed, key_event.character);
+
                         if unsafe { & *::env().console.get() }.draw {
                             unsafe { &mut *::env().console.get() }.event(key_event.to_event());
                         } else {
@@ -5,17 +5,20 @@ use alloc::boxed::Box;
 use collections::String;
 
 use common::debug::SerialConsole;
-use common::event::{self, Event, EventOption};
+use common::event::{self, Event, EventOption, ScreenEvent};
 
 use core::mem;
 
 use graphics::color::Color;
-use graphics::display::Display;
+use graphics::display::{Display, DisplayDevice, DisplayMode};
 
 use sync::WaitQueue;
 
+use system::error::{Error, Result, EINVAL, ENODEV};
+
 pub struct Console {
     pub display: Option<Box<Display>>,
+    pub device: Option<Box<DisplayDevice>>,
     pub inner: Option<ransid::Console>,
     pub draw: bool,
     pub command: String,
@@ -32,6 +35,7 @@ impl Console {
         };
         Console {
             display: display_option,
+            device: None,
             inner: inner_option,
             draw: false,
             command: String::new(),
@@ -39,6 +43,40 @@ impl Console {
         }
     }
 
+    /// Change the mode of the display device, resizing the console and notifying the display
+    /// manager with a `ScreenEvent`
+    pub fn set_mode(&mut self, mode: DisplayMode) -> Result<()> {
+        let display = match self.device {
+            Some(ref mut device) => try!(device.set_mode(mode).ok_or(Error::new(EINVAL))),
+            None => return Err(Error::new(ENODEV)),
+        };
+
+        self.inner = Some(ransid::Console::new(display.width/8, display.height/16));
+        self.display = Some(display);
+
+        if let Some(ref mut inner) = self.inner {
+            inner.redraw = true;
+        }
+        self.write(b"");
+
+        ::env().events.send(ScreenEvent {
+            width: mode.width,
+            height: mode.height,
+            depth: mode.depth,
+        }.to_event(), "Console::set_mode");
+
+        Ok(())
+    }
+
+    /// Make the hidden page of the display visible, if the device supports page flipping
+    pub fn flip_page(&mut self) {
+        if let Some(ref mut display) = self.display {
+            if let Some(ref mut device) = self.device {
+                display.flip_page(&mut **device);
+            }
+        }
+    }
+
     pub fn event(&mut self, event: Event) {
         match event.to_option() {
             EventOption::Key(key_event) => {
@@ -116,18 +154,15 @@ impl Console {
                 if inner.redraw {
                     inner.redraw = false;
                     if let Some(ref mut display) = self.display {
-                        let mut min = inner.h;
-                        let mut max = 0;
+                        // Start of the current run of changed rows
+                        let mut run = None;
 
                         for y in 0..inner.h {
                             if inner.changed[y] {
                                 inner.changed[y] = false;
 
-                                if y < min {
-                                    min = y;
-                                }
-                                if y > max {
-                                    max = y;
+                                if run.is_none() {
+                                    run = Some(y);
                                 }
 
                                 for x in 0..inner.w {
@@ -151,11 +186,13 @@ impl Console {
                                         });
                                     }
                                 }
+                            } else if let Some(start) = run.take() {
+                                display.flip_rows(start * 16, (y - start) * 16);
                             }
                         }
 
-                        if min <= max {
-                            display.flip_rows(min * 16, (max + 1 - min) * 16);
+                        if let Some(start) = run {
+                            display.flip_rows(start * 16, (inner.h - start) * 16);
                         }
                     }
                 }
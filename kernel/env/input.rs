@@ -0,0 +1,211 @@
+use alloc::arc::{Arc, Weak};
+
+use collections::{String, Vec};
+use collections::borrow::ToOwned;
+
+use common::time::Duration;
+
+use sync::WaitQueue;
+
+use system::input::*;
+
+/// Scancodes of modifier keys
+const SC_LEFT_SHIFT: u8 = 0x2A;
+const SC_RIGHT_SHIFT: u8 = 0x36;
+const SC_CTRL: u8 = 0x1D;
+const SC_ALT: u8 = 0x38;
+const SC_CAPS_LOCK: u8 = 0x3A;
+
+/// The most events queued for a reader, after which the oldest are dropped, so that a reader that
+/// does not read cannot grow kernel memory
+const READER_QUEUE_SIZE: usize = 256;
+
+/// An input device
+pub struct InputDevice {
+    /// Device ID, unique for the lifetime of the system
+    pub id: usize,
+    /// Name of the device node
+    pub name: String,
+    /// Keys currently held
+    keys: [bool; 256],
+    /// Buttons currently held
+    buttons: u32,
+    /// Modifiers currently held
+    modifiers: u32,
+}
+
+impl InputDevice {
+    /// The modifiers of this device
+    pub fn modifiers(&self) -> u32 {
+        self.modifiers
+    }
+}
+
+/// A reader of input events, optionally limited to one device
+struct InputReader {
+    device: Option<usize>,
+    queue: Weak<WaitQueue<InputEvent>>,
+}
+
+/// The kernel input subsystem
+///
+/// Drivers register devices and report events here, which are delivered to every reader of the
+/// matching `input:` nodes.
+pub struct Input {
+    pub devices: Vec<InputDevice>,
+    readers: Vec<InputReader>,
+    next_id: usize,
+}
+
+impl Input {
+    pub fn new() -> Input {
+        Input {
+            devices: Vec::new(),
+            readers: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    /// Add a device, announcing it to readers of all devices
+    pub fn add_device(&mut self, name: &str) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.devices.push(InputDevice {
+            id: id,
+            name: name.to_owned(),
+            keys: [false; 256],
+            buttons: 0,
+            modifiers: 0,
+        });
+
+        self.send(id, INPUT_HOTPLUG, HOTPLUG_ADDED, 0);
+
+        id
+    }
+
+    /// Find a device by name
+    pub fn find(&self, name: &str) -> Option<&InputDevice> {
+        self.devices.iter().find(|device| device.name == name)
+    }
+
+    /// Add a reader, returning the queue events will be delivered to
+    pub fn reader(&mut self, device: Option<usize>) -> Arc<WaitQueue<InputEvent>> {
+        let queue = Arc::new(WaitQueue::new());
+        self.readers.push(InputReader {
+            device: device,
+            queue: Arc::downgrade(&queue),
+        });
+        queue
+    }
+
+    /// Deliver an event to all readers of the device
+    pub fn send(&mut self, id: usize, kind: u16, code: u16, value: i32) {
+        let time = Duration::monotonic();
+        let modifiers = self.devices.iter().find(|device| device.id == id)
+                                   .map_or(0, |device| device.modifiers);
+
+        let event = InputEvent {
+            time_sec: time.secs,
+            time_nsec: time.nanos,
+            device: id as u32,
+            kind: kind,
+            code: code,
+            value: value,
+            modifiers: modifiers,
+        };
+
+        self.readers.retain(|reader| reader.queue.upgrade().is_some());
+        for reader in self.readers.iter() {
+            if reader.device.is_none() || reader.device == Some(id) {
+                if let Some(queue) = reader.queue.upgrade() {
+                    while unsafe { queue.inner() }.len() >= READER_QUEUE_SIZE {
+                        unsafe { queue.inner() }.pop_front();
+                    }
+                    queue.send(event, "Input::send");
+                }
+            }
+        }
+    }
+
+    /// Report a key, detecting repeats and tracking modifiers
+    pub fn key(&mut self, id: usize, scancode: u8, pressed: bool, character: char) {
+        let value = if let Some(device) = self.devices.iter_mut().find(|device| device.id == id) {
+            let held = device.keys[scancode as usize];
+            device.keys[scancode as usize] = pressed;
+
+            let modifier = match scancode {
+                SC_LEFT_SHIFT | SC_RIGHT_SHIFT => MOD_SHIFT,
+                SC_CTRL => MOD_CTRL,
+                SC_ALT => MOD_ALT,
+                _ => 0
+            };
+
+            if modifier == MOD_SHIFT {
+                if device.keys[SC_LEFT_SHIFT as usize] || device.keys[SC_RIGHT_SHIFT as usize] {
+                    device.modifiers |= MOD_SHIFT;
+                } else {
+                    device.modifiers &= !MOD_SHIFT;
+                }
+            } else if modifier != 0 {
+                if pressed {
+                    device.modifiers |= modifier;
+                } else {
+                    device.modifiers &= !modifier;
+                }
+            } else if scancode == SC_CAPS_LOCK && pressed && ! held {
+                device.modifiers ^= MOD_CAPS_LOCK;
+            }
+
+            if ! pressed {
+                KEY_RELEASE
+            } else if held {
+                KEY_REPEAT
+            } else {
+                KEY_PRESS
+            }
+        } else {
+            return;
+        };
+
+        self.send(id, INPUT_KEY, scancode as u16, value);
+        if pressed && character != '\0' {
+            self.send(id, INPUT_TEXT, 0, character as i32);
+        }
+        self.send(id, INPUT_SYN, 0, 0);
+    }
+
+    /// Report the state of all buttons, as a mask of `1 << BTN_*`, sending events for the changes
+    pub fn buttons(&mut self, id: usize, buttons: u32) {
+        let changed = if let Some(device) = self.devices.iter_mut().find(|device| device.id == id) {
+            let changed = device.buttons ^ buttons;
+            device.buttons = buttons;
+            changed
+        } else {
+            return;
+        };
+
+        for button in 0..32 {
+            if changed & 1 << button != 0 {
+                self.send(id, INPUT_BUTTON, button as u16, (buttons >> button & 1) as i32);
+            }
+        }
+    }
+
+    /// Report relative motion
+    pub fn relative(&mut self, id: usize, code: u16, value: i32) {
+        if value != 0 {
+            self.send(id, INPUT_REL, code, value);
+        }
+    }
+
+    /// Report an absolute position
+    pub fn absolute(&mut self, id: usize, code: u16, value: i32) {
+        self.send(id, INPUT_ABS, code, value);
+    }
+
+    /// Mark the end of a report
+    pub fn sync(&mut self, id: usize) {
+        self.send(id, INPUT_SYN, 0, 0);
+    }
+}
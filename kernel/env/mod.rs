@@ -1,31 +1,67 @@
 use alloc::arc::Arc;
 use alloc::boxed::Box;
 
-use collections::{String, Vec, VecDeque};
+use collections::{BTreeMap, String, Vec, VecDeque};
 use collections::string::ToString;
 
-use core::cell::UnsafeCell;
+use core::cell::{Cell, UnsafeCell};
 
 use arch::context::{Context, ContextManager};
 use common::event::Event;
 use common::time::Duration;
 use disk::Disk;
 use network::Nic;
-use fs::{KScheme, Resource, Scheme, VecResource};
+use fs::{DirEntry, DirResource, KScheme, LockTable, MountTable, Resource, Scheme, SchemePolicy,
+         WatchTable, default_scheme_policy};
 use sync::WaitQueue;
 
-use system::error::{Error, Result, ENOENT, EEXIST};
-use system::syscall::{MODE_DIR, O_CREAT};
+use system::error::{Error, Result, ENOENT, EEXIST, EINVAL, ELOOP, EXDEV};
+use system::syscall::{DT_DIR, MODE_SYMLINK, MODE_TYPE, O_CREAT, O_EXCL, O_NOFOLLOW, O_RDONLY, Stat};
 
 use self::console::Console;
+use self::input::Input;
 use self::log::Log;
 
 /// The Kernel Console
 pub mod console;
 
+/// The Kernel Input Subsystem
+pub mod input;
+
 /// The Kernel Log
 pub mod log;
 
+/// The most symlinks followed when resolving a path, after which `ELOOP` is returned
+const MAX_SYMLINKS: usize = 8;
+
+/// Find the URL a symlink at `link` points to.
+///
+/// A target with a scheme is used as is, an absolute target is in the scheme of the link, and a
+/// relative target is relative to the directory of the link. `.` and `..` are left in place to be
+/// resolved with the symlinks before them.
+fn symlink_url(link: &str, target: &str) -> String {
+    if target.find(':').map_or(false, |i| target.find('/').map_or(true, |j| i < j)) {
+        target.to_string()
+    } else if target.starts_with('/') {
+        link.splitn(2, ":").next().unwrap_or("").to_string() + ":" + target
+    } else {
+        let parent = link.rfind('/').map_or(link.find(':').map_or(0, |i| i + 1), |i| i + 1);
+        link[..parent].to_string() + target
+    }
+}
+
+/// Join the parts of the path of a url
+fn join_url(scheme: &str, parts: &[&str]) -> String {
+    let mut url = scheme.to_string() + ":";
+    for (i, part) in parts.iter().enumerate() {
+        if i > 0 {
+            url.push('/');
+        }
+        url.push_str(part);
+    }
+    url
+}
+
 /// The kernel environment
 pub struct Environment {
     /// Contexts
@@ -44,12 +80,25 @@ pub struct Environment {
     pub nics: UnsafeCell<Vec<Box<Nic>>>,
     /// Pending events
     pub events: WaitQueue<Event>,
+    /// Input devices and readers
+    pub input: UnsafeCell<Input>,
     /// Futexes
     pub futexes: UnsafeCell<VecDeque<(*mut i32, *mut Context)>>,
     /// Kernel logs
     pub log: UnsafeCell<Log>,
+    /// Advisory locks tracked by the kernel
+    pub locks: UnsafeCell<LockTable>,
+    /// Mounted paths
+    pub mounts: UnsafeCell<MountTable>,
+    /// Watchers of changes to paths
+    pub watches: UnsafeCell<WatchTable>,
     /// Schemes
     pub schemes: UnsafeCell<Vec<Box<KScheme>>>,
+    /// Decides which processes may register which scheme names
+    pub scheme_policy: Cell<SchemePolicy>,
+    /// Scheme names that only the program at a url may register, set through `sys:/scheme_policy`
+    /// and enforced by the default scheme policy
+    pub scheme_reservations: UnsafeCell<BTreeMap<String, String>>,
 
     /// Interrupt stats
     pub interrupts: UnsafeCell<[u64; 256]>,
@@ -67,9 +116,15 @@ impl Environment {
             disks: UnsafeCell::new(Vec::new()),
             nics: UnsafeCell::new(Vec::new()),
             events: WaitQueue::new(),
+            input: UnsafeCell::new(Input::new()),
             futexes: UnsafeCell::new(VecDeque::new()),
             log: UnsafeCell::new(Log::new()),
+            locks: UnsafeCell::new(LockTable::new()),
+            mounts: UnsafeCell::new(MountTable::new()),
+            watches: UnsafeCell::new(WatchTable::new()),
             schemes: UnsafeCell::new(Vec::new()),
+            scheme_policy: Cell::new(default_scheme_policy),
+            scheme_reservations: UnsafeCell::new(BTreeMap::new()),
 
             interrupts: UnsafeCell::new([0; 256]),
         }
@@ -81,27 +136,116 @@ impl Environment {
         }
     }
 
-    /// Open a new resource
+    /// Map a url through the mount table
+    fn resolve(&self, url: &str) -> String {
+        unsafe { &*self.mounts.get() }.resolve(url)
+    }
+
+    /// Whether the scheme with a name may have symlinks
+    fn symlinks(&self, name: &str) -> bool {
+        unsafe { &*self.schemes.get() }.iter().any(|scheme| scheme.scheme() == name && scheme.symlinks())
+    }
+
+    /// Map a url through the mount table and the symlinks in its path, following a symlink in the
+    /// last component only if `follow` is set
+    ///
+    /// Components are looked at in turn, so that `.` and `..` are resolved after the symlinks
+    /// before them. Only the paths of schemes that have symlinks are looked at, other schemes are
+    /// not asked about every component.
+    fn resolve_links(&self, url: &str, follow: bool) -> Result<String> {
+        let mut url = self.resolve(url);
+        let mut links = 0;
+        'resolve: loop {
+            let (scheme, path) = {
+                let mut url_split = url.splitn(2, ":");
+                (url_split.next().unwrap_or("").to_string(), url_split.next().unwrap_or("").to_string())
+            };
+            let mut symlinks = self.symlinks(&scheme);
+
+            let components: Vec<&str> = path.split('/').collect();
+            let mut parts: Vec<&str> = Vec::new();
+            for (i, &component) in components.iter().enumerate() {
+                let last = i + 1 == components.len();
+                match component {
+                    "." | ".." => {
+                        if component == ".." {
+                            // The empty part before an absolute path is the root, which stays
+                            while parts.len() > 1 && parts[parts.len() - 1].is_empty() {
+                                parts.pop();
+                            }
+                            if parts.last().map_or(false, |part| ! part.is_empty()) {
+                                parts.pop();
+                            }
+                        }
+                        if last {
+                            parts.push("");
+                        }
+                    },
+                    _ => {
+                        parts.push(component);
+                        if symlinks && ! component.is_empty() && (follow || ! last) {
+                            let link = join_url(&scheme, &parts);
+                            let mut stat = Stat::default();
+                            if self.stat_nofollow(&link, &mut stat).is_err() {
+                                // Nothing under a component that cannot be found is looked at
+                                symlinks = false;
+                            } else if stat.st_mode & MODE_TYPE == MODE_SYMLINK {
+                                links += 1;
+                                if links > MAX_SYMLINKS {
+                                    return Err(Error::new(ELOOP));
+                                }
+
+                                let mut target = symlink_url(&link, &try!(self.read_link(&link)));
+                                for component in components[i + 1..].iter() {
+                                    target.push('/');
+                                    target.push_str(component);
+                                }
+                                url = self.resolve(&target);
+                                continue 'resolve;
+                            }
+                        }
+                    }
+                }
+            }
+
+            return Ok(join_url(&scheme, &parts));
+        }
+    }
+
+    /// Read the target of a symlink at a url that has been resolved
+    fn read_link(&self, url: &str) -> Result<String> {
+        let mut target = [0; 4096];
+        let count = try!(try!(self.open_nofollow(url, O_RDONLY | O_NOFOLLOW)).read(&mut target));
+        Ok(String::from_utf8_lossy(&target[..count]).into_owned())
+    }
+
+    /// Open a new resource, following symlinks
+    ///
+    /// A symlink in the last component is opened itself if `O_NOFOLLOW` is given, or if both
+    /// `O_CREAT` and `O_EXCL` are, so that `O_CREAT` and `O_TRUNC` otherwise apply to its target.
     pub fn open(&self, url: &str, flags: usize) -> Result<Box<Resource>> {
+        let follow = flags & O_NOFOLLOW != O_NOFOLLOW && flags & (O_CREAT | O_EXCL) != O_CREAT | O_EXCL;
+        let url = try!(self.resolve_links(url, follow));
+        self.open_nofollow(&url, flags)
+    }
+
+    /// Open a new resource at a url that has been resolved
+    fn open_nofollow(&self, url: &str, flags: usize) -> Result<Box<Resource>> {
         let mut url_split = url.splitn(2, ":");
         let url_scheme = url_split.next().unwrap_or("");
         if url_scheme.is_empty() {
             let url_path = url_split.next().unwrap_or("").trim_matches('/');
             if url_path.is_empty() {
-                let mut list = String::new();
+                let mut entries = Vec::new();
 
                 for scheme in unsafe { &mut *self.schemes.get() }.iter() {
                     let scheme_str = scheme.scheme();
                     if !scheme_str.is_empty() {
-                        if !list.is_empty() {
-                            list = list + "\n" + scheme_str;
-                        } else {
-                            list = scheme_str.to_string();
-                        }
+                        entries.push(DirEntry::new(scheme_str, DT_DIR, 0));
                     }
                 }
 
-                Ok(box VecResource::new(":".to_string(), list.into_bytes(), MODE_DIR))
+                Ok(box DirResource::new(":".to_string(), entries))
             } else if flags & O_CREAT == O_CREAT {
                 for scheme in unsafe { &mut *self.schemes.get() }.iter_mut() {
                     if scheme.scheme() == url_path {
@@ -129,8 +273,31 @@ impl Environment {
         }
     }
 
+    /// Get information about a resource, following symlinks
+    pub fn stat(&self, url: &str, stat: &mut Stat) -> Result<()> {
+        let url = try!(self.resolve_links(url, true));
+        self.stat_nofollow(&url, stat)
+    }
+
+    /// Get information about a resource at a url that has been resolved
+    fn stat_nofollow(&self, url: &str, stat: &mut Stat) -> Result<()> {
+        let url_scheme = url.splitn(2, ":").next().unwrap_or("");
+        if url_scheme.is_empty() {
+            let resource = try!(self.open_nofollow(url, O_RDONLY));
+            return resource.stat(stat);
+        }
+
+        for mut scheme in unsafe { &mut *self.schemes.get() }.iter_mut() {
+            if scheme.scheme() == url_scheme {
+                return scheme.stat(url, stat);
+            }
+        }
+        Err(Error::new(ENOENT))
+    }
+
     /// Makes a directory
     pub fn mkdir(&self, url: &str, flags: usize) -> Result<()> {
+        let url = &try!(self.resolve_links(url, false));
         if let Some(url_scheme) = url.splitn(2, ":").next() {
             for mut scheme in unsafe { &mut *self.schemes.get() }.iter_mut() {
                 if scheme.scheme() == url_scheme {
@@ -141,8 +308,71 @@ impl Environment {
         Err(Error::new(ENOENT))
     }
 
+    /// Create a hard link, which can only be done within a scheme
+    pub fn link(&self, old: &str, new: &str) -> Result<()> {
+        let old = &try!(self.resolve_links(old, false));
+        let new = &try!(self.resolve_links(new, false));
+        let old_scheme = old.splitn(2, ":").next().unwrap_or("");
+        let new_scheme = new.splitn(2, ":").next().unwrap_or("");
+        if old_scheme != new_scheme {
+            return Err(Error::new(EXDEV));
+        }
+
+        for mut scheme in unsafe { &mut *self.schemes.get() }.iter_mut() {
+            if scheme.scheme() == old_scheme {
+                return scheme.link(old, new);
+            }
+        }
+        Err(Error::new(ENOENT))
+    }
+
+    /// Create a symlink, the target of which may be in any scheme
+    pub fn symlink(&self, target: &str, url: &str) -> Result<()> {
+        let url = &try!(self.resolve_links(url, false));
+        if let Some(url_scheme) = url.splitn(2, ":").next() {
+            for mut scheme in unsafe { &mut *self.schemes.get() }.iter_mut() {
+                if scheme.scheme() == url_scheme {
+                    return scheme.symlink(target, url);
+                }
+            }
+        }
+        Err(Error::new(ENOENT))
+    }
+
+    /// Read the target of a symlink
+    pub fn readlink(&self, url: &str, buf: &mut [u8]) -> Result<usize> {
+        let mut resource = try!(self.open(url, O_RDONLY | O_NOFOLLOW));
+
+        let mut stat = Stat::default();
+        try!(resource.stat(&mut stat));
+        if stat.st_mode & MODE_TYPE != MODE_SYMLINK {
+            return Err(Error::new(EINVAL));
+        }
+
+        resource.read(buf)
+    }
+
+    /// Rename a resource, which can only be done within a scheme
+    pub fn rename(&self, old: &str, new: &str) -> Result<()> {
+        let old = &try!(self.resolve_links(old, false));
+        let new = &try!(self.resolve_links(new, false));
+        let old_scheme = old.splitn(2, ":").next().unwrap_or("");
+        let new_scheme = new.splitn(2, ":").next().unwrap_or("");
+        if old_scheme != new_scheme {
+            return Err(Error::new(EXDEV));
+        }
+
+        for mut scheme in unsafe { &mut *self.schemes.get() }.iter_mut() {
+            if scheme.scheme() == old_scheme {
+                return scheme.rename(old, new);
+            }
+        }
+        Err(Error::new(ENOENT))
+    }
+
     /// Remove a directory
     pub fn rmdir(&self, url: &str) -> Result<()> {
+        let url = &try!(self.resolve_links(url, false));
         if let Some(url_scheme) = url.splitn(2, ":").next() {
             for mut scheme in unsafe { &mut *self.schemes.get() }.iter_mut() {
                 if scheme.scheme() == url_scheme {
@@ -153,14 +383,28 @@ impl Environment {
         Err(Error::new(ENOENT))
     }
 
-    /// Unlink a resource
+    /// Unlink a resource, or unregister a scheme if `url` is `:name`
     pub fn unlink(&self, url: &str) -> Result<()> {
-        if let Some(url_scheme) = url.splitn(2, ":").next() {
-            for mut scheme in unsafe { &mut *self.schemes.get() }.iter_mut() {
-                if scheme.scheme() == url_scheme {
-                    return scheme.unlink(url);
+        let url = &try!(self.resolve_links(url, false));
+        let mut url_split = url.splitn(2, ":");
+        let url_scheme = url_split.next().unwrap_or("");
+        if url_scheme.is_empty() {
+            let name = url_split.next().unwrap_or("").trim_matches('/');
+            let schemes = unsafe { &mut *self.schemes.get() };
+            for i in 0..schemes.len() {
+                if schemes[i].scheme() == name {
+                    try!(schemes[i].unregister());
+                    schemes.remove(i);
+                    return Ok(());
                 }
             }
+            return Err(Error::new(ENOENT));
+        }
+
+        for mut scheme in unsafe { &mut *self.schemes.get() }.iter_mut() {
+            if scheme.scheme() == url_scheme {
+                return scheme.unlink(url);
+            }
         }
         Err(Error::new(ENOENT))
     }
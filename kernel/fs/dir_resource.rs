@@ -0,0 +1,124 @@
+use super::{Resource, ResourceSeek, VecResource};
+
+use alloc::boxed::Box;
+
+use collections::{String, Vec};
+use collections::borrow::ToOwned;
+
+use core::{mem, ptr};
+
+use system::error::{Error, Result, EINVAL};
+use system::syscall::{Dirent, Stat, MODE_DIR};
+
+/// An entry of a directory
+#[derive(Clone)]
+pub struct DirEntry {
+    /// Name of the entry
+    pub name: String,
+    /// Type of the entry, one of the `DT_*` constants
+    pub kind: u8,
+    /// Inode number of the entry, or 0 if it has none
+    pub ino: u64,
+}
+
+impl DirEntry {
+    pub fn new(name: &str, kind: u8, ino: u64) -> DirEntry {
+        DirEntry {
+            name: name.to_owned(),
+            kind: kind,
+            ino: ino,
+        }
+    }
+}
+
+/// A directory resource
+///
+/// Reading returns the names of the entries, one per line. `getdents` returns the entries as
+/// `Dirent` headers each followed by a name.
+pub struct DirResource {
+    list: VecResource,
+    entries: Vec<DirEntry>,
+    /// The next entry returned by `getdents`
+    next: usize,
+}
+
+impl DirResource {
+    pub fn new(path: String, entries: Vec<DirEntry>) -> DirResource {
+        let mut list = String::new();
+        for entry in entries.iter() {
+            if ! list.is_empty() {
+                list.push('\n');
+            }
+            list.push_str(&entry.name);
+        }
+
+        DirResource {
+            list: VecResource::new(path, list.into_bytes(), MODE_DIR),
+            entries: entries,
+            next: 0,
+        }
+    }
+}
+
+impl Resource for DirResource {
+    fn dup(&self) -> Result<Box<Resource>> {
+        Ok(box DirResource {
+            list: self.list.clone(),
+            entries: self.entries.clone(),
+            next: self.next,
+        })
+    }
+
+    fn path(&self, buf: &mut [u8]) -> Result<usize> {
+        self.list.path(buf)
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.list.read(buf)
+    }
+
+    fn seek(&mut self, pos: ResourceSeek) -> Result<usize> {
+        if let ResourceSeek::Start(0) = pos {
+            self.next = 0;
+        }
+        self.list.seek(pos)
+    }
+
+    fn stat(&self, stat: &mut Stat) -> Result<()> {
+        try!(self.list.stat(stat));
+        stat.st_nlink = 2;
+        Ok(())
+    }
+
+    fn getdents(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let mut i = 0;
+        while let Some(entry) = self.entries.get(self.next) {
+            let reclen = mem::size_of::<Dirent>() + entry.name.len();
+            if i + reclen > buf.len() {
+                break;
+            }
+
+            let dirent = Dirent {
+                d_ino: entry.ino,
+                d_reclen: reclen as u16,
+                d_type: entry.kind,
+            };
+            unsafe { ptr::write(buf.as_mut_ptr().offset(i as isize) as *mut Dirent, dirent) };
+            buf[i + mem::size_of::<Dirent>()..i + reclen].copy_from_slice(entry.name.as_bytes());
+
+            i += reclen;
+            self.next += 1;
+        }
+
+        // An entry that does not fit in an empty buffer can never be returned
+        if i == 0 && self.next < self.entries.len() {
+            Err(Error::new(EINVAL))
+        } else {
+            Ok(i)
+        }
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
@@ -3,6 +3,7 @@ use super::Resource;
 use alloc::boxed::Box;
 
 use system::error::{Error, Result, EPERM};
+use system::syscall::{O_NOFOLLOW, O_RDONLY, Stat};
 
 #[allow(unused_variables)]
 pub trait KScheme {
@@ -22,6 +23,30 @@ pub trait KScheme {
         Err(Error::new(EPERM))
     }
 
+    /// Create a hard link, both paths being in this scheme
+    fn link(&mut self, old: &str, new: &str) -> Result<()> {
+        Err(Error::new(EPERM))
+    }
+
+    /// Create a symlink at `path` pointing to `target`
+    ///
+    /// Opening a symlink returns the symlink itself, with a mode of `MODE_SYMLINK` and the target
+    /// as contents, which `Environment` follows in paths of schemes for which `symlinks` is set.
+    fn symlink(&mut self, target: &str, path: &str) -> Result<()> {
+        Err(Error::new(EPERM))
+    }
+
+    /// Rename a resource, both paths being in this scheme
+    fn rename(&mut self, old: &str, new: &str) -> Result<()> {
+        Err(Error::new(EPERM))
+    }
+
+    /// Get information about a resource without following a symlink at `path`
+    fn stat(&mut self, path: &str, stat: &mut Stat) -> Result<()> {
+        let resource = try!(self.open(path, O_RDONLY | O_NOFOLLOW));
+        resource.stat(stat)
+    }
+
     fn rmdir(&mut self, path: &str) -> Result<()> {
         Err(Error::new(EPERM))
     }
@@ -29,4 +54,25 @@ pub trait KScheme {
     fn unlink(&mut self, path: &str) -> Result<()> {
         Err(Error::new(EPERM))
     }
+
+    /// Whether this scheme may have symlinks, which are only looked for in its paths if it does
+    fn symlinks(&self) -> bool {
+        false
+    }
+
+    /// The process serving this scheme, or `None` for a kernel scheme
+    fn owner(&self) -> Option<usize> {
+        None
+    }
+
+    /// The number of open resources and of calls waiting for a reply, if the scheme tracks them
+    fn usage(&self) -> Option<(usize, usize)> {
+        None
+    }
+
+    /// Stop serving the name of this scheme, on behalf of the current process, before it is
+    /// removed from the list of schemes
+    fn unregister(&mut self) -> Result<()> {
+        Err(Error::new(EPERM))
+    }
 }
@@ -0,0 +1,148 @@
+use alloc::arc::Arc;
+
+use collections::{String, Vec};
+use collections::borrow::ToOwned;
+
+use core::str;
+
+use sync::WaitCondition;
+
+use super::Resource;
+
+use system::error::{Error, Result, EINVAL, EWOULDBLOCK};
+use system::syscall::{Stat, LOCK_EX, LOCK_NB, LOCK_SH, LOCK_UN};
+
+/// The file a lock is held on, identified by device and inode if its scheme reports them, and by
+/// path otherwise
+#[derive(Clone, PartialEq)]
+pub enum LockTarget {
+    Inode(u64, u64),
+    Path(String),
+}
+
+impl LockTarget {
+    /// The file a resource refers to
+    pub fn of<R: Resource + ?Sized>(resource: &R) -> Result<LockTarget> {
+        let mut stat = Stat::default();
+        if resource.stat(&mut stat).is_ok() && stat.st_ino != 0 {
+            return Ok(LockTarget::Inode(stat.st_dev, stat.st_ino));
+        }
+
+        let mut buf = [0; 4096];
+        let count = try!(resource.path(&mut buf));
+        Ok(LockTarget::Path(unsafe { str::from_utf8_unchecked(&buf[..count]) }.to_owned()))
+    }
+}
+
+/// An advisory lock held on a file
+struct FileLock {
+    target: LockTarget,
+    owner: usize,
+    exclusive: bool,
+}
+
+/// The advisory locks of resources that are tracked by the kernel, which are those of kernel
+/// schemes, and of user schemes that do not handle locking themselves
+pub struct LockTable {
+    locks: Vec<FileLock>,
+    next_owner: usize,
+    /// Notified whenever locks are released, waking the contexts waiting to take them
+    released: WaitCondition,
+}
+
+impl LockTable {
+    pub fn new() -> LockTable {
+        LockTable {
+            locks: Vec::new(),
+            next_owner: 1,
+            released: WaitCondition::new(),
+        }
+    }
+
+    /// Take a lock on a file, or convert the lock the owner already holds on it, failing with
+    /// `EWOULDBLOCK` if another owner holds a conflicting lock
+    fn try_lock(&mut self, target: LockTarget, owner: usize, exclusive: bool) -> Result<()> {
+        for lock in self.locks.iter() {
+            if lock.target == target && lock.owner != owner && (exclusive || lock.exclusive) {
+                return Err(Error::new(EWOULDBLOCK));
+            }
+        }
+
+        for lock in self.locks.iter_mut() {
+            if lock.target == target && lock.owner == owner {
+                lock.exclusive = exclusive;
+                return Ok(());
+            }
+        }
+
+        self.locks.push(FileLock {
+            target: target,
+            owner: owner,
+            exclusive: exclusive,
+        });
+        Ok(())
+    }
+
+    /// Release all locks of an owner
+    pub fn unlock(&mut self, owner: usize) {
+        let count = self.locks.len();
+        self.locks.retain(|lock| lock.owner != owner);
+        if self.locks.len() != count {
+            self.released.notify("LockTable::unlock");
+        }
+    }
+}
+
+/// The open file that locks are taken through, which is shared by the file descriptors
+/// duplicated from it, by `dup` and by `clone`. Its locks are released when the last of them is
+/// closed.
+pub struct LockOwner {
+    id: usize,
+}
+
+impl LockOwner {
+    pub fn new() -> Arc<LockOwner> {
+        let locks = unsafe { &mut *::env().locks.get() };
+        let id = locks.next_owner;
+        locks.next_owner += 1;
+        Arc::new(LockOwner {
+            id: id,
+        })
+    }
+
+    /// The owner ID of the locks in the kernel lock table
+    pub fn id(&self) -> usize {
+        self.id
+    }
+}
+
+impl Drop for LockOwner {
+    fn drop(&mut self) {
+        unsafe { &mut *::env().locks.get() }.unlock(self.id);
+    }
+}
+
+/// Take or release an advisory lock on a file in the kernel lock table
+///
+/// Without `LOCK_NB`, this waits until a conflicting lock is released.
+pub fn flock(target: LockTarget, owner: usize, operation: usize) -> Result<()> {
+    let exclusive = match operation & !LOCK_NB {
+        LOCK_SH => false,
+        LOCK_EX => true,
+        LOCK_UN => {
+            unsafe { &mut *::env().locks.get() }.unlock(owner);
+            return Ok(());
+        },
+        _ => return Err(Error::new(EINVAL))
+    };
+
+    loop {
+        let locks = unsafe { &mut *::env().locks.get() };
+        match locks.try_lock(target.clone(), owner, exclusive) {
+            Err(ref err) if err.errno == EWOULDBLOCK && operation & LOCK_NB != LOCK_NB => (),
+            result => return result
+        }
+
+        locks.released.wait("flock");
+    }
+}
@@ -1,12 +1,22 @@
+pub use self::dir_resource::{DirEntry, DirResource};
 pub use self::kscheme::KScheme;
-pub use self::resource::{Resource, ResourceSeek};
-pub use self::scheme::Scheme;
+pub use self::lock::{LockOwner, LockTable};
+pub use self::mount::MountTable;
+pub use self::resource::{Resource, ResourceSeek, fcntl_blocking, fcntl_flags, stat_dev, stat_times};
+pub use self::scheme::{Scheme, SchemePolicy, default_scheme_policy};
 pub use self::slice_resource::{SliceResource, SliceMutResource};
 pub use self::vec_resource::VecResource;
+pub use self::watch::WatchTable;
 pub use self::supervisor_resource::SupervisorResource;
 
+/// Directory resource
+pub mod dir_resource;
 /// Kernel schemes
 pub mod kscheme;
+/// Advisory locks
+pub mod lock;
+/// Mount table
+pub mod mount;
 /// Internal resource representation
 pub mod resource;
 /// Userspace scheme
@@ -15,5 +25,7 @@ pub mod scheme;
 pub mod slice_resource;
 /// Vec resource
 pub mod vec_resource;
+/// Change notifications
+pub mod watch;
 /// Supervisor resource.
 pub mod supervisor_resource;
@@ -0,0 +1,100 @@
+use collections::{String, Vec};
+use collections::borrow::ToOwned;
+
+use system::error::{Error, Result, EBUSY, EINVAL, EPERM};
+
+/// A path prefix that is mapped to another url, such as `file:/tmp` to `tmp:/`
+pub struct Mount {
+    pub prefix: String,
+    pub target: String,
+    /// The process that made the mount
+    pub pid: usize,
+}
+
+/// The remainder of `url` after `prefix`, if `url` is `prefix` or a path under it
+fn under<'a>(url: &'a str, prefix: &str) -> Option<&'a str> {
+    if url.starts_with(prefix) {
+        let rest = &url[prefix.len()..];
+        if rest.is_empty() || rest.starts_with('/') || prefix.ends_with(':') {
+            return Some(rest);
+        }
+    }
+    None
+}
+
+/// Join a url and the remainder of a path, keeping the root of a scheme as `scheme:/`
+fn join(url: &str, rest: &str) -> String {
+    let mut joined = url.to_owned() + rest;
+    if joined.ends_with(':') {
+        joined.push('/');
+    }
+    joined
+}
+
+/// The mount table, which maps path prefixes to the roots of other schemes
+pub struct MountTable {
+    pub mounts: Vec<Mount>,
+}
+
+impl MountTable {
+    pub fn new() -> MountTable {
+        MountTable {
+            mounts: Vec::new(),
+        }
+    }
+
+    /// Map `prefix` and the paths under it to `target` on behalf of process `pid`, failing with
+    /// `EBUSY` if `prefix` is already mounted
+    pub fn mount(&mut self, prefix: &str, target: &str, pid: usize) -> Result<()> {
+        let prefix = prefix.trim_right_matches('/');
+        let target = target.trim_right_matches('/');
+        if prefix.is_empty() || target.is_empty() {
+            return Err(Error::new(EINVAL));
+        }
+
+        if self.mounts.iter().any(|mount| mount.prefix == prefix) {
+            return Err(Error::new(EBUSY));
+        }
+
+        self.mounts.push(Mount {
+            prefix: prefix.to_owned(),
+            target: target.to_owned(),
+            pid: pid,
+        });
+        Ok(())
+    }
+
+    /// Remove the mount at `prefix` on behalf of process `pid`, failing with `EINVAL` if there is
+    /// none, and with `EPERM` if another process that still exists made it
+    pub fn umount(&mut self, prefix: &str, pid: usize) -> Result<()> {
+        let prefix = prefix.trim_right_matches('/');
+        match self.mounts.iter().position(|mount| mount.prefix == prefix) {
+            Some(i) => {
+                let owner = self.mounts[i].pid;
+                if owner != pid && unsafe { & *::env().contexts.get() }.find(owner).is_ok() {
+                    return Err(Error::new(EPERM));
+                }
+                self.mounts.remove(i);
+                Ok(())
+            },
+            None => Err(Error::new(EINVAL))
+        }
+    }
+
+    /// Map a url through the mount with the longest prefix it is under, if any
+    pub fn resolve(&self, url: &str) -> String {
+        let mut best: Option<(&Mount, &str)> = None;
+        for mount in self.mounts.iter() {
+            if let Some(rest) = under(url, &mount.prefix) {
+                if best.map_or(true, |(other, _)| mount.prefix.len() > other.prefix.len()) {
+                    best = Some((mount, rest));
+                }
+            }
+        }
+
+        match best {
+            Some((mount, rest)) => join(&mount.target, rest),
+            None => url.to_owned()
+        }
+    }
+}
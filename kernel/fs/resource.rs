@@ -1,7 +1,57 @@
 use alloc::boxed::Box;
 
-use system::error::{Error, Result, EPERM, ESPIPE};
-use system::syscall::Stat;
+use core::usize;
+
+use common::time::Duration;
+
+use super::lock::{self, LockTarget};
+
+use system::error::{Error, Result, EINVAL, ENOSYS, EPERM, ESPIPE};
+use system::syscall::{Stat, F_GETFL, F_SETFL, O_NONBLOCK};
+
+/// The device ID of the resources of a scheme, which is a hash of the scheme name so that it stays
+/// the same across boots
+pub fn stat_dev(scheme: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &b in scheme.as_bytes() {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Fill in the times of a stat, given when the resource was created and when it was last modified
+pub fn stat_times(stat: &mut Stat, created: Duration, modified: Duration) {
+    stat.st_atime = modified.secs as u64;
+    stat.st_atime_nsec = modified.nanos as u32;
+    stat.st_mtime = modified.secs as u64;
+    stat.st_mtime_nsec = modified.nanos as u32;
+    stat.st_ctime = created.secs as u64;
+    stat.st_ctime_nsec = created.nanos as u32;
+}
+
+/// The `fcntl` of a resource that can block and does not support `O_NONBLOCK`, which has no status
+/// flags
+pub fn fcntl_blocking(cmd: usize, arg: usize) -> Result<usize> {
+    match cmd {
+        F_GETFL => Ok(0),
+        F_SETFL if arg == 0 => Ok(0),
+        _ => Err(Error::new(EINVAL))
+    }
+}
+
+/// The `fcntl` of a resource that keeps its status flags in `flags`, which `F_SETFL` can set to
+/// any of the flags in `allowed`
+pub fn fcntl_flags(flags: &mut usize, allowed: usize, cmd: usize, arg: usize) -> Result<usize> {
+    match cmd {
+        F_GETFL => Ok(*flags),
+        F_SETFL if arg & !allowed == 0 => {
+            *flags = arg;
+            Ok(0)
+        },
+        _ => Err(Error::new(EINVAL))
+    }
+}
 
 /// Resource seek
 #[derive(Copy, Clone, Debug)]
@@ -47,12 +97,107 @@ pub trait Resource {
         Err(Error::new(ESPIPE))
     }
 
+    /// Read data to buffer at the given offset, without changing the seek position
+    /// By default, this seeks to the offset and back, returning `ESPIPE` if seeking is not supported,
+    /// and `EINVAL` if the offset does not fit a seek position.
+    fn pread(&mut self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        if offset > usize::MAX as u64 {
+            return Err(Error::new(EINVAL));
+        }
+        let pos = try!(self.seek(ResourceSeek::Current(0)));
+        try!(self.seek(ResourceSeek::Start(offset as usize)));
+        let result = self.read(buf);
+        try!(self.seek(ResourceSeek::Start(pos)));
+        result
+    }
+
+    /// Write to resource at the given offset, without changing the seek position
+    /// By default, this seeks to the offset and back, returning `ESPIPE` if seeking is not supported,
+    /// and `EINVAL` if the offset does not fit a seek position.
+    fn pwrite(&mut self, buf: &[u8], offset: u64) -> Result<usize> {
+        if offset > usize::MAX as u64 {
+            return Err(Error::new(EINVAL));
+        }
+        let pos = try!(self.seek(ResourceSeek::Current(0)));
+        try!(self.seek(ResourceSeek::Start(offset as usize)));
+        let result = self.write(buf);
+        try!(self.seek(ResourceSeek::Start(pos)));
+        result
+    }
+
+    /// Read data to each buffer in turn, stopping at the first buffer that is not filled
+    fn readv(&mut self, bufs: &mut [&mut [u8]]) -> Result<usize> {
+        let mut total = 0;
+        for buf in bufs.iter_mut() {
+            match self.read(buf) {
+                Ok(count) => {
+                    total += count;
+                    if count < buf.len() {
+                        break;
+                    }
+                },
+                Err(err) => if total == 0 {
+                    return Err(err);
+                } else {
+                    break;
+                }
+            }
+        }
+        Ok(total)
+    }
+
+    /// Write each buffer in turn, stopping at the first buffer that is not written completely
+    fn writev(&mut self, bufs: &[&[u8]]) -> Result<usize> {
+        let mut total = 0;
+        for buf in bufs.iter() {
+            match self.write(buf) {
+                Ok(count) => {
+                    total += count;
+                    if count < buf.len() {
+                        break;
+                    }
+                },
+                Err(err) => if total == 0 {
+                    return Err(err);
+                } else {
+                    break;
+                }
+            }
+        }
+        Ok(total)
+    }
+
     /// Get informations about the resource, such as mode and size
     /// Returns `EPERM` if the operation is not supported.
     fn stat(&self, stat: &mut Stat) -> Result<()> {
         Err(Error::new(EPERM))
     }
 
+    /// Read directory entries, as `Dirent` headers each followed by a name
+    /// Returns `ENOSYS` if the operation is not supported, in which case a directory lists the
+    /// names of its entries, one per line, when it is read.
+    fn getdents(&mut self, buf: &mut [u8]) -> Result<usize> {
+        Err(Error::new(ENOSYS))
+    }
+
+    /// Get (`F_GETFL`) or set (`F_SETFL`) the status flags, which are `O_NONBLOCK` and `O_APPEND`
+    /// Returns `EINVAL` if a flag is not supported. By default, `O_NONBLOCK` is accepted and has no
+    /// effect, as for a resource that never blocks, which resources that can block override.
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        match cmd {
+            F_GETFL => Ok(0),
+            F_SETFL if arg & !O_NONBLOCK == 0 => Ok(0),
+            _ => Err(Error::new(EINVAL))
+        }
+    }
+
+    /// Take (`LOCK_SH`, `LOCK_EX`) or release (`LOCK_UN`) an advisory lock, possibly with `LOCK_NB`,
+    /// on behalf of the open file `owner`
+    /// By default, the lock is tracked by the kernel on the file the resource refers to.
+    fn flock(&mut self, owner: usize, operation: usize) -> Result<()> {
+        lock::flock(try!(LockTarget::of(self)), owner, operation)
+    }
+
     /// Sync all buffers
     /// Returns `EPERM` if the operation is not supported.
     fn sync(&mut self) -> Result<()> {
@@ -1,31 +1,63 @@
 use alloc::arc::{Arc, Weak};
 use alloc::boxed::Box;
 
-use collections::String;
+use collections::{BTreeSet, String, Vec};
 use collections::borrow::ToOwned;
 
-use core::cell::Cell;
+use core::cell::{Cell, UnsafeCell};
 use core::mem::size_of;
 use core::ops::DerefMut;
-use core::{ptr, slice};
+use core::sync::atomic::Ordering;
+use core::{ptr, slice, usize};
 
-use arch::context::Context;
+use arch::context::{Context, CONTEXT_STACK_SIZE};
+use arch::memory;
+use common::time::Duration;
 
 use sync::{WaitMap, WaitQueue};
 
-use system::error::{Error, Result, EFAULT, EINVAL, ENODEV, ESPIPE};
-use system::scheme::Packet;
+use system::error::{Error, Result, EACCES, EFAULT, EINVAL, ENODEV, ENOMEM, ENOSYS, EPERM, ESPIPE,
+                    ETIMEDOUT};
+use system::scheme::{ExtPacket, Packet, Ring, EXT_PACKET_VERSION, PACKET_NOTIFY, PACKET_WAKE,
+                     RING_ENTRIES};
 use system::syscall::{SYS_CLOSE, SYS_DUP, SYS_FPATH, SYS_FSTAT, SYS_FSYNC, SYS_FTRUNCATE,
                     SYS_OPEN, SYS_LSEEK, SEEK_SET, SEEK_CUR, SEEK_END, SYS_MKDIR,
-                    SYS_READ, SYS_WRITE, SYS_RMDIR, SYS_UNLINK, Stat};
+                    SYS_READ, SYS_RENAME, SYS_WRITE, SYS_RMDIR, SYS_UNLINK, SYS_LINK,
+                    SYS_SYMLINK, SYS_STAT, SYS_GETDENTS, SYS_FCNTL, SYS_PREAD, SYS_PWRITE,
+                    SYS_READV, SYS_WRITEV, SYS_FLOCK, F_GETFL, F_SETFL, F_GETTIMEOUT, F_SETTIMEOUT,
+                    F_GETPACKET, F_SETPACKET, F_GETRING, F_GETSYMLINKS, F_SETSYMLINKS, IoVec, Stat};
 
-use super::{Resource, ResourceSeek, KScheme};
+use super::{Resource, ResourceSeek, KScheme, lock};
+use super::lock::LockTarget;
 
 struct SchemeInner {
     name: String,
     context: *mut Context,
+    /// The process that registered the scheme
+    pid: usize,
+    /// Whether the scheme is still registered, after which calls fail with `ENODEV`
+    registered: Cell<bool>,
+    /// The number of resources open on the scheme
+    resources: Cell<usize>,
     next_id: Cell<usize>,
-    todo: WaitQueue<Packet>,
+    /// The ids of calls that are waiting for a reply
+    pending: UnsafeCell<BTreeSet<usize>>,
+    /// The number of server resources, after the last of which is closed calls fail with `ENODEV`.
+    /// These may outlive the daemon, if it passed them on, so its memory is looked up with
+    /// `daemon` instead
+    servers: Cell<usize>,
+    /// Milliseconds after which calls fail with `ETIMEDOUT`, or 0 to wait forever
+    timeout: Cell<usize>,
+    /// The version of the packets the server reads and writes, 0 for `Packet`
+    version: Cell<usize>,
+    /// The call ring, or 0 if the server has not mapped one
+    ring: Cell<usize>,
+    /// The address of the call ring in the server
+    ring_address: Cell<usize>,
+    /// Whether the scheme has symlinks, which the kernel then looks for in its paths
+    symlinks: Cell<bool>,
+    /// Calls that have not been read, or that did not fit in the ring
+    todo: WaitQueue<ExtPacket>,
     done: WaitMap<usize, (usize, usize, usize, usize)>,
 }
 
@@ -34,34 +66,90 @@ impl SchemeInner {
         SchemeInner {
             name: name.to_owned(),
             context: context,
+            pid: unsafe { (*context).pid },
+            registered: Cell::new(true),
+            resources: Cell::new(0),
             next_id: Cell::new(1),
+            pending: UnsafeCell::new(BTreeSet::new()),
+            servers: Cell::new(1),
+            timeout: Cell::new(0),
+            version: Cell::new(0),
+            ring: Cell::new(0),
+            ring_address: Cell::new(0),
+            symlinks: Cell::new(false),
             todo: WaitQueue::new(),
             done: WaitMap::new(),
         }
     }
 
+    /// The context of the daemon, while it exists. It is looked up by PID and checked to be the
+    /// same context, as the PID may have been reused once the daemon exited
+    fn daemon<'a>(&self) -> Option<&'a mut Context> {
+        let contexts = unsafe { &mut *::env().contexts.get() };
+        match contexts.find_mut(self.pid) {
+            Ok(context) => if context.deref_mut() as *mut Context == self.context {
+                Some(unsafe { &mut *self.context })
+            } else {
+                None
+            },
+            Err(_) => None
+        }
+    }
+
+    /// Get an id for a call, which is neither `PACKET_NOTIFY`, `PACKET_WAKE` nor the id of a call
+    /// that is still waiting for a reply
+    fn next_id(&self) -> usize {
+        let pending = unsafe { & *self.pending.get() };
+        loop {
+            let id = self.next_id.get();
+            self.next_id.set(id.wrapping_add(1));
+            if id != PACKET_NOTIFY && id != PACKET_WAKE && ! pending.contains(&id) {
+                return id;
+            }
+        }
+    }
+
     fn call(inner: &Weak<SchemeInner>, a: usize, b: usize, c: usize, d: usize) -> Result<usize> {
         if let Some(scheme) = inner.upgrade() {
-            let id = scheme.next_id.get();
-
-            //TODO: What should be done about collisions in self.todo or self.done?
-            let mut next_id = id + 1;
-            if next_id <= 0 {
-                next_id = 1;
+            if ! scheme.registered.get() {
+                return Err(Error::new(ENODEV));
             }
-            scheme.next_id.set(next_id);
+
+            let id = scheme.next_id();
+            unsafe { &mut *scheme.pending.get() }.insert(id);
 
             // debugln!("{} {}: {} {} {:X} {:X} {:X}", scheme.name, id, a, ::syscall::name(a), b, c, d);
 
-            scheme.todo.send(Packet {
+            let contexts = unsafe { & *::env().contexts.get() };
+            let pid = contexts.current().map(|current| current.pid).unwrap_or(0);
+
+            scheme.submit(ExtPacket {
                 id: id,
                 a: a,
                 b: b,
                 c: c,
-                d: d
-            }, "SchemeInner::call todo");
+                d: d,
+                version: EXT_PACKET_VERSION,
+                pid: pid,
+                uid: 0,
+                gid: 0,
+            });
+
+            let reply = match scheme.timeout.get() {
+                0 => Some(scheme.done.receive(&id, "SchemeInner::call done")),
+                timeout => {
+                    let time = Duration::new((timeout / 1000) as i64, ((timeout % 1000) * 1000000) as i32);
+                    scheme.done.receive_for(&id, "SchemeInner::call done", time)
+                }
+            };
 
-            let res = Error::demux(scheme.done.receive(&id, "SchemeInner::call done").0);
+            // A reply that comes after this is dropped by the server resource
+            unsafe { &mut *scheme.pending.get() }.remove(&id);
+
+            let res = match reply {
+                Some(reply) => Error::demux(reply.0),
+                None => Err(Error::new(ETIMEDOUT))
+            };
             // debugln!("{} {}: {} {} {:X} {:X} {:X} = {:?}", scheme.name, id, a, ::syscall::name(a), b, c, d, res);
             res
         } else {
@@ -69,24 +157,210 @@ impl SchemeInner {
         }
     }
 
+    /// The call ring, if the server has mapped one
+    fn ring(&self) -> Option<&Ring> {
+        match self.ring.get() {
+            0 => None,
+            ring => Some(unsafe { & *(ring as *const Ring) })
+        }
+    }
+
+    /// Map the call ring into the server, creating it the first time
+    fn map_ring(&self) -> Result<usize> {
+        if self.ring.get() == 0 {
+            let size = size_of::<Ring>();
+            let daemon = try!(self.daemon().ok_or(Error::new(ENODEV)));
+            if ! daemon.memory_allows(size) {
+                return Err(Error::new(ENOMEM));
+            }
+
+            let ring = unsafe { memory::alloc_aligned(size, 4096) };
+            if ring == 0 {
+                return Err(Error::new(ENOMEM));
+            }
+
+            let mmap = unsafe { &mut *daemon.mmap.get() };
+            match mmap.add_mem(ring, size, true, false) {
+                Ok(virtual_address) => {
+                    self.ring.set(ring);
+                    self.ring_address.set(virtual_address);
+                },
+                Err(err) => {
+                    unsafe { memory::unalloc(ring) };
+                    return Err(err);
+                }
+            }
+
+            self.refill();
+        }
+        Ok(self.ring_address.get())
+    }
+
+    /// Queue a call for the server, in its ring if it has one with space left
+    fn submit(&self, packet: ExtPacket) {
+        if let Some(ring) = self.ring() {
+            // Calls that did not fit go first, to keep the order of calls
+            if unsafe { self.todo.inner() }.is_empty() && self.push(ring, packet) {
+                self.todo.condition.notify("SchemeInner::submit");
+                return;
+            }
+        }
+        self.todo.send(packet, "SchemeInner::submit");
+    }
+
+    /// Queue a `PACKET_WAKE` for the reader of the server, unless one is already waiting to be read
+    fn wake(&self) {
+        if unsafe { self.todo.inner() }.iter().any(|packet| packet.id == PACKET_WAKE) {
+            return;
+        }
+
+        self.submit(ExtPacket {
+            id: PACKET_WAKE,
+            a: 0,
+            b: 0,
+            c: 0,
+            d: 0,
+            version: EXT_PACKET_VERSION,
+            pid: 0,
+            uid: 0,
+            gid: 0,
+        });
+    }
+
+    /// Write a call to the ring, failing if it is full
+    fn push(&self, ring: &Ring, packet: ExtPacket) -> bool {
+        let tail = ring.sq_tail.load(Ordering::Acquire);
+        if tail.wrapping_sub(ring.sq_head.load(Ordering::Acquire)) >= RING_ENTRIES {
+            return false;
+        }
+
+        unsafe { ptr::write(&ring.sq[tail % RING_ENTRIES] as *const ExtPacket as *mut ExtPacket, packet) };
+        ring.sq_tail.store(tail.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// Move the calls that did not fit in the ring into it, as far as there is space
+    fn refill(&self) {
+        if let Some(ring) = self.ring() {
+            while let Some(packet) = unsafe { self.todo.inner() }.pop_front() {
+                if ! self.push(ring, packet) {
+                    unsafe { self.todo.inner() }.push_front(packet);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Hand the replies in the ring to their callers, in the order their slots were claimed
+    fn reap(&self) {
+        if let Some(ring) = self.ring() {
+            loop {
+                let head = ring.cq_head.load(Ordering::Acquire);
+                if head == ring.cq_tail.load(Ordering::Acquire) {
+                    break;
+                }
+
+                let slot = head % RING_ENTRIES;
+                if ! ring.cq_ready[slot].load(Ordering::Acquire) {
+                    break;
+                }
+
+                let packet = ring.cq[slot];
+                ring.cq_ready[slot].store(false, Ordering::Release);
+                ring.cq_head.store(head.wrapping_add(1), Ordering::Release);
+                self.reply(&packet);
+            }
+        }
+    }
+
+    /// Hand a reply to its caller, if it is still waiting
+    fn reply(&self, packet: &Packet) {
+        if unsafe { & *self.pending.get() }.contains(&packet.id) {
+            self.done.send(packet.id, (packet.a, packet.b, packet.c, packet.d), "SchemeInner::reply");
+        }
+    }
+
+    /// The size of the packets the server reads and writes
+    fn packet_size(&self) -> usize {
+        if self.version.get() == 0 {
+            size_of::<Packet>()
+        } else {
+            size_of::<ExtPacket>()
+        }
+    }
+
+    /// Write a call to a buffer of the server, in the packet version it uses
+    fn put(&self, buf: &mut [u8], packet: ExtPacket) {
+        if self.version.get() == 0 {
+            let packet = Packet {
+                id: packet.id,
+                a: packet.a,
+                b: packet.b,
+                c: packet.c,
+                d: packet.d
+            };
+            unsafe { ptr::write(buf.as_mut_ptr() as *mut Packet, packet) };
+        } else {
+            unsafe { ptr::write(buf.as_mut_ptr() as *mut ExtPacket, packet) };
+        }
+    }
+
+    /// Fail the calls that are waiting for a reply, and any later calls, with `ENODEV`, and wake
+    /// the daemon if it is waiting for calls, which then reads the end of file
+    fn unregister(&self) {
+        self.registered.set(false);
+        unsafe { self.todo.inner() }.clear();
+        for &id in unsafe { & *self.pending.get() }.iter() {
+            self.done.send(id, (Error::mux(Err(Error::new(ENODEV))), 0, 0, 0), "SchemeInner::unregister");
+        }
+        self.todo.condition.notify("SchemeInner::unregister");
+    }
+
+    /// Unregister the scheme and remove it from the list of schemes, if that was not done yet,
+    /// once the last server resource is closed, such as when the scheme daemon exits
+    fn close(&self) {
+        if self.registered.get() {
+            self.unregister();
+            unsafe { &mut *::env().schemes.get() }.retain(|scheme| scheme.scheme() != self.name);
+        }
+
+        if self.ring.get() != 0 {
+            if let Some(daemon) = self.daemon() {
+                let mmap = unsafe { &mut *daemon.mmap.get() };
+                if let Ok(mut mem) = mmap.get_mem_mut(self.ring_address.get()) {
+                    mem.virtual_size = 0;
+                }
+                mmap.clean_mem();
+            }
+        }
+    }
+
     fn capture(inner: &Weak<SchemeInner>, mut physical_address: usize, size: usize, writeable: bool) -> Result<usize> {
         if let Some(scheme) = inner.upgrade() {
+            let daemon = match scheme.daemon() {
+                Some(daemon) => if daemon.exited {
+                    return Err(Error::new(ENODEV));
+                } else {
+                    daemon
+                },
+                None => return Err(Error::new(ENODEV))
+            };
             if physical_address >= 0x80000000 {
                 physical_address -= 0x80000000;
             }
-            unsafe {
-                let mmap = &mut *(*scheme.context).mmap.get();
-                return mmap.add_mem(physical_address, size, writeable, false);
-            }
+            let mmap = unsafe { &mut *daemon.mmap.get() };
+            mmap.add_mem(physical_address, size, writeable, false)
         } else {
-            return Err(Error::new(ENODEV));
+            Err(Error::new(ENODEV))
         }
     }
 
     fn release(inner: &Weak<SchemeInner>, virtual_address: usize) {
+        // Captured memory is unmapped whether or not the scheme is still served, as the daemon
+        // must not keep the buffers of the caller
         if let Some(scheme) = inner.upgrade() {
-            unsafe {
-                let mmap = &mut *(*scheme.context).mmap.get();
+            if let Some(daemon) = scheme.daemon() {
+                let mmap = unsafe { &mut *daemon.mmap.get() };
                 if let Ok(mut mem) = mmap.get_mem_mut(virtual_address) {
                     mem.virtual_size = 0;
                 }
@@ -96,10 +370,54 @@ impl SchemeInner {
     }
 }
 
+
 impl Drop for SchemeInner {
     fn drop(&mut self) {
-        unsafe { &mut *::env().schemes.get() }.retain(|scheme| scheme.scheme() != self.name);
+        unsafe { memory::unalloc(self.ring.get()) };
+    }
+}
+
+/// Decides whether a process may register a scheme name that is not taken. The policy in use is
+/// `Environment::scheme_policy`.
+pub type SchemePolicy = fn(name: &str, context: &Context) -> Result<()>;
+
+/// The default scheme policy, which only accepts names without `/` and `:`, does not let
+/// supervised processes register schemes, as their calls could then be served without the
+/// supervisor seeing them, and leaves reserved names to the programs they are reserved for
+pub fn default_scheme_policy(name: &str, context: &Context) -> Result<()> {
+    if name.contains('/') || name.contains(':') {
+        return Err(Error::new(EINVAL));
+    }
+
+    if context.supervised {
+        return Err(Error::new(EACCES));
     }
+
+    match unsafe { & *::env().scheme_reservations.get() }.get(name) {
+        Some(program) if program[..] != context.name[..] => Err(Error::new(EACCES)),
+        _ => Ok(())
+    }
+}
+
+/// Find the physical address of a buffer, failing with `EFAULT` if it is not mapped in the current
+/// context
+///
+/// The kernel heap and the kernel stack of the current context are the buffers of the kernel
+/// itself, such as when it reads a symlink or an executable, and are mapped at the address of the
+/// memory, past `LOGICAL_OFFSET` for the heap, which `capture` removes.
+fn translate(ptr: usize, len: usize) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let current = try!(contexts.current_mut());
+
+    let end = try!(ptr.checked_add(len).ok_or(Error::new(EFAULT)));
+    if ptr >= memory::LOGICAL_OFFSET {
+        return Ok(ptr);
+    }
+    if current.kernel_stack > 0 && ptr >= current.kernel_stack && end <= current.kernel_stack + CONTEXT_STACK_SIZE {
+        return Ok(ptr);
+    }
+
+    current.translate(ptr, len)
 }
 
 pub struct SchemeResource {
@@ -108,6 +426,16 @@ pub struct SchemeResource {
 }
 
 impl SchemeResource {
+    fn new(inner: Weak<SchemeInner>, file_id: usize) -> SchemeResource {
+        if let Some(scheme) = inner.upgrade() {
+            scheme.resources.set(scheme.resources.get() + 1);
+        }
+        SchemeResource {
+            inner: inner,
+            file_id: file_id,
+        }
+    }
+
     fn call(&self, a: usize, b: usize, c: usize, d: usize) -> Result<usize> {
         SchemeInner::call(&self.inner, a, b, c, d)
     }
@@ -119,23 +447,94 @@ impl SchemeResource {
     fn release(&self, virtual_address: usize){
         SchemeInner::release(&self.inner, virtual_address);
     }
+
+    /// Call with two paths, which are sent in one buffer, with the length of the first path in
+    /// the last argument
+    fn call_paths(&self, a: usize, first: &str, second: &str) -> Result<()> {
+        let mut paths = String::with_capacity(first.len() + second.len());
+        paths.push_str(first);
+        paths.push_str(second);
+
+        let virtual_address = try!(self.capture(paths.as_ptr() as usize, paths.len(), false));
+
+        let result = self.call(a, virtual_address, paths.len(), first.len());
+
+        self.release(virtual_address);
+
+        result.and(Ok(()))
+    }
+
+    /// Call with a list of buffers, given as addresses and lengths, which are captured and sent
+    /// as an array of `IoVec`s in the second argument
+    fn call_iov(&self, a: usize, bufs: &[(usize, usize)], writeable: bool, d: usize) -> Result<usize> {
+        if bufs.is_empty() {
+            return Ok(0);
+        }
+
+        // Translating a buffer can move the stack into one piece, so all are translated once
+        // before any is captured, and the addresses captured stay in use
+        for &(ptr, len) in bufs.iter() {
+            try!(translate(ptr, len));
+        }
+
+        let mut iov: Vec<IoVec> = Vec::with_capacity(bufs.len());
+        let mut captured: Vec<usize> = Vec::with_capacity(bufs.len());
+
+        let mut result = Ok(0);
+        for &(ptr, len) in bufs.iter() {
+            let physical_address = match translate(ptr, len) {
+                Ok(physical_address) => physical_address,
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            };
+            let offset = physical_address % 4096;
+
+            match self.capture(physical_address - offset, len + offset, writeable) {
+                Ok(virtual_address) => {
+                    captured.push(virtual_address);
+                    iov.push(IoVec {
+                        iov_base: virtual_address + offset,
+                        iov_len: len,
+                    });
+                },
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+
+        if result.is_ok() {
+            result = match self.capture(iov.as_ptr() as usize, iov.len() * size_of::<IoVec>(), false) {
+                Ok(iov_address) => {
+                    let result = self.call(a, self.file_id, iov_address, d);
+                    self.release(iov_address);
+                    result
+                },
+                Err(err) => Err(err)
+            };
+        }
+
+        for virtual_address in captured {
+            self.release(virtual_address);
+        }
+
+        result
+    }
 }
 
 impl Resource for SchemeResource {
     /// Duplicate the resource
     fn dup(&self) -> Result<Box<Resource>> {
         let file_id = try!(self.call(SYS_DUP, self.file_id, 0, 0));
-        Ok(Box::new(SchemeResource {
-            inner: self.inner.clone(),
-            file_id: file_id
-        }))
+        Ok(box SchemeResource::new(self.inner.clone(), file_id))
     }
 
     /// Return the URL of this resource
     fn path(&self, buf: &mut [u8]) -> Result <usize> {
-        let contexts = unsafe { & *::env().contexts.get() };
-        let current = try!(contexts.current());
-        if let Ok(physical_address) = current.translate(buf.as_mut_ptr() as usize, buf.len()) {
+        if let Ok(physical_address) = translate(buf.as_mut_ptr() as usize, buf.len()) {
             let offset = physical_address % 4096;
 
             let virtual_address = try!(self.capture(physical_address - offset, buf.len() + offset, true));
@@ -155,9 +554,7 @@ impl Resource for SchemeResource {
 
     /// Read data to buffer
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        let contexts = unsafe { & *::env().contexts.get() };
-        let current = try!(contexts.current());
-        if let Ok(physical_address) = current.translate(buf.as_mut_ptr() as usize, buf.len()) {
+        if let Ok(physical_address) = translate(buf.as_mut_ptr() as usize, buf.len()) {
             let offset = physical_address % 4096;
 
             let virtual_address = try!(self.capture(physical_address - offset, buf.len() + offset, true));
@@ -175,11 +572,27 @@ impl Resource for SchemeResource {
         }
     }
 
+    /// Read directory entries from resource
+    fn getdents(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if let Ok(physical_address) = translate(buf.as_mut_ptr() as usize, buf.len()) {
+            let offset = physical_address % 4096;
+
+            let virtual_address = try!(self.capture(physical_address - offset, buf.len() + offset, true));
+
+            let result = self.call(SYS_GETDENTS, self.file_id, virtual_address + offset, buf.len());
+
+            self.release(virtual_address);
+
+            result
+        } else {
+            debugln!("{}:{} fault {:X} {}", file!(), line!(), buf.as_ptr() as usize, buf.len());
+            Err(Error::new(EFAULT))
+        }
+    }
+
     /// Write to resource
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        let contexts = unsafe { & *::env().contexts.get() };
-        let current = try!(contexts.current());
-        if let Ok(physical_address) = current.translate(buf.as_ptr() as usize, buf.len()) {
+        if let Ok(physical_address) = translate(buf.as_ptr() as usize, buf.len()) {
             let offset = physical_address % 4096;
 
             let virtual_address = try!(self.capture(physical_address - offset, buf.len() + offset, false));
@@ -212,9 +625,7 @@ impl Resource for SchemeResource {
     fn stat(&self, stat: &mut Stat) -> Result<()> {
         let buf = unsafe { slice::from_raw_parts_mut(stat as *mut Stat as *mut u8, size_of::<Stat>()) };
 
-        let contexts = unsafe { & *::env().contexts.get() };
-        let current = try!(contexts.current());
-        if let Ok(physical_address) = current.translate(buf.as_mut_ptr() as usize, buf.len()) {
+        if let Ok(physical_address) = translate(buf.as_mut_ptr() as usize, buf.len()) {
             let offset = physical_address % 4096;
 
             let virtual_address = try!(self.capture(physical_address - offset, buf.len() + offset, true));
@@ -230,6 +641,42 @@ impl Resource for SchemeResource {
         }
     }
 
+    /// The offset is passed to the daemon in a packet register, so it must fit a `usize`
+    fn pread(&mut self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        if offset > usize::MAX as u64 {
+            return Err(Error::new(EINVAL));
+        }
+        self.call_iov(SYS_PREAD, &[(buf.as_mut_ptr() as usize, buf.len())], true, offset as usize)
+    }
+
+    fn pwrite(&mut self, buf: &[u8], offset: u64) -> Result<usize> {
+        if offset > usize::MAX as u64 {
+            return Err(Error::new(EINVAL));
+        }
+        self.call_iov(SYS_PWRITE, &[(buf.as_ptr() as usize, buf.len())], false, offset as usize)
+    }
+
+    fn readv(&mut self, bufs: &mut [&mut [u8]]) -> Result<usize> {
+        let list: Vec<(usize, usize)> = bufs.iter_mut().map(|buf| (buf.as_mut_ptr() as usize, buf.len())).collect();
+        self.call_iov(SYS_READV, &list, true, list.len())
+    }
+
+    fn writev(&mut self, bufs: &[&[u8]]) -> Result<usize> {
+        let list: Vec<(usize, usize)> = bufs.iter().map(|buf| (buf.as_ptr() as usize, buf.len())).collect();
+        self.call_iov(SYS_WRITEV, &list, false, list.len())
+    }
+
+    fn flock(&mut self, owner: usize, operation: usize) -> Result<()> {
+        match self.call(SYS_FLOCK, self.file_id, operation, 0) {
+            Err(ref err) if err.errno == ENOSYS => lock::flock(try!(LockTarget::of(self)), owner, operation),
+            result => result.and(Ok(()))
+        }
+    }
+
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        self.call(SYS_FCNTL, self.file_id, cmd, arg)
+    }
+
     /// Sync the resource
     fn sync(&mut self) -> Result<()> {
         self.call(SYS_FSYNC, self.file_id, 0, 0).and(Ok(()))
@@ -244,6 +691,9 @@ impl Resource for SchemeResource {
 impl Drop for SchemeResource {
     fn drop(&mut self) {
         let _ = self.call(SYS_CLOSE, self.file_id, 0, 0);
+        if let Some(scheme) = self.inner.upgrade() {
+            scheme.resources.set(scheme.resources.get() - 1);
+        }
     }
 }
 
@@ -251,9 +701,34 @@ pub struct SchemeServerResource {
     inner: Arc<SchemeInner>,
 }
 
+impl SchemeServerResource {
+    /// Tell watchers of a change the scheme reported, the path of which is in the scheme's memory
+    fn notify(&self, packet: &Packet) -> Result<()> {
+        let watches = unsafe { &mut *::env().watches.get() };
+        if ! watches.watching() {
+            return Ok(());
+        }
+
+        let contexts = unsafe { & *::env().contexts.get() };
+        let current = try!(contexts.current());
+        let path = try!(current.get_slice(packet.b as *const u8, packet.c));
+
+        let path = String::from_utf8_lossy(path);
+
+        let mut url = self.inner.name.clone() + ":";
+        if ! path.starts_with('/') {
+            url.push('/');
+        }
+        url.push_str(&path);
+        watches.notify(packet.a as u16, &url);
+        Ok(())
+    }
+}
+
 impl Resource for SchemeServerResource {
     /// Duplicate the resource
     fn dup(&self) -> Result<Box<Resource>> {
+        self.inner.servers.set(self.inner.servers.get() + 1);
         Ok(box SchemeServerResource {
             inner: self.inner.clone()
         })
@@ -280,18 +755,52 @@ impl Resource for SchemeServerResource {
 
 
     /// Read data to buffer
+    ///
+    /// Once the ring is mapped, this waits for calls in the ring and returns how many there are.
+    /// Once the scheme is unregistered, this returns 0.
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        if buf.len() >= size_of::<Packet>() {
+        if let Some(ring) = self.inner.ring() {
+            loop {
+                if ! self.inner.registered.get() {
+                    return Ok(0);
+                }
+
+                self.inner.reap();
+                self.inner.refill();
+
+                let count = ring.sq_tail.load(Ordering::Acquire).wrapping_sub(ring.sq_head.load(Ordering::Acquire));
+                if count > 0 {
+                    return Ok(count);
+                }
+
+                self.inner.todo.condition.wait("SchemeServerResource::read ring");
+            }
+        }
+
+        let size = self.inner.packet_size();
+        if buf.len() >= size {
             let mut i = 0;
 
-            let packet = self.inner.todo.receive("SchemeServerResource::read todo");
-            unsafe { ptr::write(buf.as_mut_ptr().offset(i as isize) as *mut Packet, packet); }
-            i += size_of::<Packet>();
+            let packet;
+            loop {
+                if ! self.inner.registered.get() {
+                    return Ok(0);
+                }
 
-            while i + size_of::<Packet>() <= buf.len() {
+                if let Some(next) = unsafe { self.inner.todo.inner() }.pop_front() {
+                    packet = next;
+                    break;
+                }
+
+                self.inner.todo.condition.wait("SchemeServerResource::read todo");
+            }
+            self.inner.put(&mut buf[i..], packet);
+            i += size;
+
+            while i + size <= buf.len() {
                 if let Some(packet) = unsafe { self.inner.todo.inner() }.pop_front() {
-                    unsafe { ptr::write(buf.as_mut_ptr().offset(i as isize) as *mut Packet, packet); }
-                    i += size_of::<Packet>();
+                    self.inner.put(&mut buf[i..], packet);
+                    i += size;
                 } else {
                     break;
                 }
@@ -305,13 +814,21 @@ impl Resource for SchemeServerResource {
 
     /// Write to resource
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        if buf.len() >= size_of::<Packet>() {
+        // Extended packets start with the fields of a packet, which are all a reply needs
+        let size = self.inner.packet_size();
+        if buf.len() >= size {
             let mut i = 0;
 
-            while i <= buf.len() - size_of::<Packet>() {
+            while i <= buf.len() - size {
                 let packet = unsafe { & *(buf.as_ptr().offset(i as isize) as *const Packet) };
-                self.inner.done.send(packet.id, (packet.a, packet.b, packet.c, packet.d), "SchemeServerResource::write done");
-                i += size_of::<Packet>();
+                if packet.id == PACKET_NOTIFY {
+                    try!(self.notify(packet));
+                } else if packet.id == PACKET_WAKE {
+                    self.inner.wake();
+                } else {
+                    self.inner.reply(packet);
+                }
+                i += size;
             }
 
             Ok(i)
@@ -325,14 +842,53 @@ impl Resource for SchemeServerResource {
         Err(Error::new(ESPIPE))
     }
 
-    /// Sync the resource
+    /// Hand the replies in the ring to their callers
     fn sync(&mut self) -> Result<()> {
-        Err(Error::new(EINVAL))
+        if self.inner.ring().is_some() {
+            self.inner.reap();
+            Ok(())
+        } else {
+            Err(Error::new(EINVAL))
+        }
     }
 
     fn truncate(&mut self, _len: usize) -> Result<()> {
         Err(Error::new(EINVAL))
     }
+
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        match cmd {
+            F_GETFL => Ok(0),
+            F_SETFL if arg == 0 => Ok(0),
+            F_GETTIMEOUT => Ok(self.inner.timeout.get()),
+            F_SETTIMEOUT => {
+                self.inner.timeout.set(arg);
+                Ok(0)
+            },
+            F_GETPACKET => Ok(self.inner.version.get()),
+            F_SETPACKET if arg == 0 || arg == EXT_PACKET_VERSION => {
+                self.inner.version.set(arg);
+                Ok(0)
+            },
+            F_GETRING => self.inner.map_ring(),
+            F_GETSYMLINKS => Ok(self.inner.symlinks.get() as usize),
+            F_SETSYMLINKS => {
+                self.inner.symlinks.set(arg != 0);
+                Ok(0)
+            },
+            _ => Err(Error::new(EINVAL))
+        }
+    }
+}
+
+impl Drop for SchemeServerResource {
+    fn drop(&mut self) {
+        let servers = self.inner.servers.get() - 1;
+        self.inner.servers.set(servers);
+        if servers == 0 {
+            self.inner.close();
+        }
+    }
 }
 
 /// Scheme has to be wrapped
@@ -345,6 +901,7 @@ impl Scheme {
     pub fn new(name: &str) -> Result<(Box<Scheme>, Box<Resource>)> {
         let contexts = unsafe { &mut *::env().contexts.get() };
         let mut current = try!(contexts.current_mut());
+        try!((::env().scheme_policy.get())(name, &**current));
         let server = box SchemeServerResource {
             inner: Arc::new(SchemeInner::new(name, current.deref_mut()))
         };
@@ -377,6 +934,35 @@ impl KScheme for Scheme {
         &self.name
     }
 
+    fn owner(&self) -> Option<usize> {
+        self.inner.upgrade().map(|scheme| scheme.pid)
+    }
+
+    fn symlinks(&self) -> bool {
+        self.inner.upgrade().map_or(false, |scheme| scheme.symlinks.get())
+    }
+
+    fn usage(&self) -> Option<(usize, usize)> {
+        self.inner.upgrade().map(|scheme| {
+            (scheme.resources.get(), unsafe { & *scheme.pending.get() }.len())
+        })
+    }
+
+    /// Only the process that registered the scheme may unregister it
+    fn unregister(&mut self) -> Result<()> {
+        let contexts = unsafe { & *::env().contexts.get() };
+        let current = try!(contexts.current());
+        match self.inner.upgrade() {
+            Some(scheme) => if scheme.pid == current.pid {
+                scheme.unregister();
+                Ok(())
+            } else {
+                Err(Error::new(EPERM))
+            },
+            None => Ok(())
+        }
+    }
+
     fn open(&mut self, path: &str, flags: usize) -> Result<Box<Resource>> {
         let virtual_address = try!(self.capture(path.as_ptr() as usize, path.len(), false));
 
@@ -385,10 +971,7 @@ impl KScheme for Scheme {
         self.release(virtual_address);
 
         match result {
-            Ok(file_id) => Ok(box SchemeResource {
-                inner: self.inner.clone(),
-                file_id: file_id,
-            }),
+            Ok(file_id) => Ok(box SchemeResource::new(self.inner.clone(), file_id)),
             Err(err) => Err(err)
         }
     }
@@ -403,6 +986,40 @@ impl KScheme for Scheme {
         result.and(Ok(()))
     }
 
+    fn link(&mut self, old: &str, new: &str) -> Result<()> {
+        self.call_paths(SYS_LINK, old, new)
+    }
+
+    fn stat(&mut self, path: &str, stat: &mut Stat) -> Result<()> {
+        let buf = unsafe { slice::from_raw_parts_mut(stat as *mut Stat as *mut u8, size_of::<Stat>()) };
+        let physical_address = try!(translate(buf.as_mut_ptr() as usize, buf.len()));
+        let offset = physical_address % 4096;
+
+        let path_address = try!(self.capture(path.as_ptr() as usize, path.len(), false));
+        let stat_address = match self.capture(physical_address - offset, buf.len() + offset, true) {
+            Ok(stat_address) => stat_address,
+            Err(err) => {
+                self.release(path_address);
+                return Err(err);
+            }
+        };
+
+        let result = self.call(SYS_STAT, path_address, path.len(), stat_address + offset);
+
+        self.release(stat_address);
+        self.release(path_address);
+
+        result.and(Ok(()))
+    }
+
+    fn symlink(&mut self, target: &str, path: &str) -> Result<()> {
+        self.call_paths(SYS_SYMLINK, target, path)
+    }
+
+    fn rename(&mut self, old: &str, new: &str) -> Result<()> {
+        self.call_paths(SYS_RENAME, old, new)
+    }
+
     fn rmdir(&mut self, path: &str) -> Result<()> {
         let virtual_address = try!(self.capture(path.as_ptr() as usize, path.len(), false));
 
@@ -77,7 +77,7 @@ impl Resource for SliceResource {
     }
 
     fn stat(&self, stat: &mut Stat) -> Result<()> {
-        stat.st_size = self.data.len() as u32;
+        stat.st_size = self.data.len() as u64;
         stat.st_mode = self.mode;
         Ok(())
     }
@@ -166,7 +166,7 @@ impl Resource for SliceMutResource {
     }
 
     fn stat(&self, stat: &mut Stat) -> Result<()> {
-        stat.st_size = self.data.len() as u32;
+        stat.st_size = self.data.len() as u64;
         stat.st_mode = self.mode;
         Ok(())
     }
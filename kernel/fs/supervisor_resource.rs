@@ -1,59 +1,125 @@
-use core::{cmp, mem};
+use core::{cmp, mem, ptr};
 use super::Resource;
 use system::error::Result;
 use system::scheme::Packet;
-use arch::context::{Context, context_switch};
+use system::syscall::{SUPERVISE_ENTER, SUPERVISE_EXIT, SUPERVISE_RETURN};
+use arch::context::context_switch;
 
 /// A supervisor resource.
 ///
-/// Reading from it will simply read the relevant registers to the buffer (see `Packet`).
+/// Reading from it will wait for the next syscall event of the jailed process, and read it to the
+/// buffer as a `Packet`, followed by the contents of the buffer of the syscall (see `sys_supervise`).
 ///
-/// Writing will simply left shift EAX by one byte, and then OR it with the byte from the buffer,
-/// effectively writing the buffer to the EAX register (truncating the additional bytes).
+/// Writing will reply to the syscall that was last read, either with the value to return, or with
+/// an action and a value.
 pub struct SupervisorResource {
-    /// The jailed context.
-    ctx: *mut Context,
+    /// The PID of the jailed context.
+    pid: usize,
+    /// Is the syscall that was last read waiting for a reply?
+    waiting: bool,
 }
 
 impl SupervisorResource {
     /// Create a new supervisor resource, supervising some PID.
-    pub unsafe fn new(ctx: *mut Context) -> Result<SupervisorResource> {
+    pub fn new(pid: usize) -> Result<SupervisorResource> {
         Ok(SupervisorResource {
-            ctx: ctx,
+            pid: pid,
+            waiting: false,
         })
     }
 }
 
 impl Resource for SupervisorResource {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        let ctx = unsafe { &mut *self.ctx };
-        while !ctx.blocked_syscall {
-            unsafe { context_switch() };
-        }
+        loop {
+            {
+                let contexts = unsafe { &mut *::env().contexts.get() };
+                let ctx = match contexts.find_mut(self.pid) {
+                    Ok(ctx) => ctx,
+                    Err(_) => return Ok(0),
+                };
 
-        let call: Packet = ctx.regs.into();
+                if ctx.exited {
+                    return Ok(0);
+                }
 
-        for (&a, b) in call.iter().zip(buf.iter_mut()) {
-            *b = a;
-        }
+                if ctx.blocked_syscall {
+                    if let Some((event, data)) = ctx.supervise_event.take() {
+                        let mut i = 0;
+
+                        if buf.len() >= mem::size_of::<Packet>() {
+                            unsafe { ptr::write(buf.as_mut_ptr() as *mut Packet, event) };
+                            i += mem::size_of::<Packet>();
+
+                            let count = cmp::min(buf.len() - i, data.len());
+                            buf[i..i + count].copy_from_slice(&data[..count]);
+                            i += count;
+                        }
 
-        ctx.blocked_syscall = false;
+                        ctx.blocked_syscall = false;
 
-        Ok(cmp::min(buf.len(), call.len()))
+                        // The result of a syscall needs no reply, so the process can continue
+                        if event.id == SUPERVISE_EXIT {
+                            ctx.unblock("SupervisorResource::read");
+                        }
+
+                        self.waiting = event.id == SUPERVISE_ENTER;
+
+                        return Ok(i);
+                    }
+                }
+            }
+
+            unsafe { context_switch() };
+        }
     }
 
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        let ctx = unsafe { &mut *self.ctx };
+        let size = mem::size_of::<usize>();
+        let read = |i: usize| -> usize {
+            let mut value = 0;
+            unsafe { ptr::copy_nonoverlapping(buf.as_ptr().offset((i * size) as isize),
+                                              &mut value as *mut usize as *mut u8, size) };
+            value
+        };
 
-        for &i in buf.iter().take(mem::size_of::<usize>()) {
-            ctx.regs.ax <<= 8;
-            ctx.regs.ax |= i as usize;
-        }
+        let (reply, count) = if buf.len() >= 2 * size {
+            ((read(0), read(1)), 2 * size)
+        } else if buf.len() >= size {
+            ((SUPERVISE_RETURN, read(0)), size)
+        } else {
+            return Ok(0);
+        };
 
-        ctx.block("SupervisorResource::write");
+        // Only a syscall that has been read, and not yet replied to, can be replied to
+        if self.waiting {
+            let contexts = unsafe { &mut *::env().contexts.get() };
+            let ctx = try!(contexts.find_mut(self.pid));
 
-        Ok(cmp::min(mem::size_of::<usize>(), buf.len()))
+            self.waiting = false;
+            ctx.supervise_reply = Some(reply);
+            ctx.unblock("SupervisorResource::write");
+        }
+
+        Ok(count)
     }
+}
 
-    // TODO implement seek?
+/// When the supervisor goes away, the process is no longer supervised, and a syscall it is
+/// waiting on the supervisor for is interrupted
+impl Drop for SupervisorResource {
+    fn drop(&mut self) {
+        let contexts = unsafe { &mut *::env().contexts.get() };
+        if let Ok(ctx) = contexts.find_mut(self.pid) {
+            let parked = ctx.blocked_syscall || self.waiting;
+
+            ctx.supervised = false;
+            ctx.blocked_syscall = false;
+            ctx.supervise_event = None;
+
+            if parked {
+                ctx.unblock("SupervisorResource::drop");
+            }
+        }
+    }
 }
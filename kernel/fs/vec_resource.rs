@@ -1,4 +1,4 @@
-use super::{Resource, ResourceSeek};
+use super::{Resource, ResourceSeek, fcntl_flags, stat_dev, stat_times};
 
 use alloc::boxed::Box;
 
@@ -6,24 +6,35 @@ use collections::{String, Vec};
 
 use core::cmp::{max, min};
 
+use common::time::Duration;
+
 use system::error::Result;
-use system::syscall::Stat;
+use system::syscall::{MODE_DIR, MODE_PERM, MODE_TYPE, Stat, O_APPEND, O_NONBLOCK};
 
 /// A vector resource
+#[derive(Clone)]
 pub struct VecResource {
     path: String,
     data: Vec<u8>,
     mode: u16,
     seek: usize,
+    created: Duration,
+    modified: Duration,
+    /// Status flags
+    flags: usize,
 }
 
 impl VecResource {
     pub fn new(path: String, data: Vec<u8>, mode: u16) -> Self {
+        let time = Duration::realtime();
         VecResource {
             path: path,
             data: data,
             mode: mode,
             seek: 0,
+            created: time,
+            modified: time,
+            flags: 0,
         }
     }
 
@@ -39,6 +50,9 @@ impl Resource for VecResource {
             data: self.data.clone(),
             mode: self.mode,
             seek: self.seek,
+            created: self.created,
+            modified: self.modified,
+            flags: self.flags,
         })
     }
 
@@ -68,6 +82,10 @@ impl Resource for VecResource {
     }
 
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if self.flags & O_APPEND == O_APPEND {
+            self.seek = self.data.len();
+        }
+
         let mut i = 0;
         while i < buf.len() && self.seek < self.data.len() {
             self.data[self.seek] = buf[i];
@@ -79,6 +97,7 @@ impl Resource for VecResource {
             self.seek += 1;
             i += 1;
         }
+        self.modified = Duration::realtime();
         return Ok(i);
     }
 
@@ -97,11 +116,29 @@ impl Resource for VecResource {
     }
 
     fn stat(&self, stat: &mut Stat) -> Result<()> {
-        stat.st_size = self.data.len() as u32;
-        stat.st_mode = self.mode;
+        stat.st_dev = stat_dev(self.path.splitn(2, ":").next().unwrap_or(""));
+        stat.st_mode = if self.mode & MODE_PERM == 0 {
+            // Resources that do not specify permissions are writable by the owner only
+            if self.mode & MODE_TYPE == MODE_DIR {
+                self.mode | 0o755
+            } else {
+                self.mode | 0o644
+            }
+        } else {
+            self.mode
+        };
+        stat.st_nlink = 1;
+        stat.st_size = self.data.len() as u64;
+        stat.st_blksize = 4096;
+        stat.st_blocks = (self.data.len() as u64 + 511) / 512;
+        stat_times(stat, self.created, self.modified);
         Ok(())
     }
 
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        fcntl_flags(&mut self.flags, O_APPEND | O_NONBLOCK, cmd, arg)
+    }
+
     fn sync(&mut self) -> Result<()> {
         Ok(())
     }
@@ -112,6 +149,7 @@ impl Resource for VecResource {
         }
         self.data.truncate(len);
         self.seek = min(self.seek, self.data.len());
+        self.modified = Duration::realtime();
         Ok(())
     }
 }
@@ -0,0 +1,105 @@
+use alloc::arc::{Arc, Weak};
+
+use collections::{String, Vec};
+use collections::borrow::ToOwned;
+
+use core::mem::size_of;
+use core::u16;
+
+use sync::WaitQueue;
+
+use system::watch::{WatchEvent, WATCH_OVERFLOW};
+
+/// The most changes queued for a watcher, the last of which is `WATCH_OVERFLOW` once it is full
+pub const WATCH_QUEUE_MAX: usize = 1024;
+
+/// A change to a path, as delivered to a watcher
+pub struct Change {
+    /// One of `WATCH_*`
+    pub kind: u16,
+    /// The changed path, relative to the watched path
+    pub path: String,
+}
+
+/// A watcher of a path and the paths under it
+struct Watcher {
+    path: String,
+    queue: Weak<WaitQueue<Change>>,
+}
+
+/// Remove the trailing `/` of a url, keeping the root of a scheme as `scheme:`
+fn trim(url: &str) -> &str {
+    url.trim_right_matches('/')
+}
+
+/// Queue a change for a watcher, replacing it with `WATCH_OVERFLOW` if the queue is full or the
+/// path does not fit an event
+fn deliver(queue: &WaitQueue<Change>, change: Change) {
+    let len = unsafe { queue.inner() }.len();
+    if len >= WATCH_QUEUE_MAX {
+        // The overflow has already been queued
+        return;
+    }
+
+    let too_long = change.path.len() > u16::MAX as usize - size_of::<WatchEvent>();
+    let change = if len + 1 == WATCH_QUEUE_MAX || too_long {
+        Change {
+            kind: WATCH_OVERFLOW,
+            path: String::new(),
+        }
+    } else {
+        change
+    };
+    queue.send(change, "WatchTable::notify");
+}
+
+/// The watchers of paths, which are told of the changes that schemes report
+pub struct WatchTable {
+    watchers: Vec<Watcher>,
+}
+
+impl WatchTable {
+    pub fn new() -> WatchTable {
+        WatchTable {
+            watchers: Vec::new(),
+        }
+    }
+
+    /// Add a watcher of a url, returning the queue changes will be delivered to
+    pub fn watch(&mut self, url: &str) -> Arc<WaitQueue<Change>> {
+        let queue = Arc::new(WaitQueue::new());
+        self.watchers.push(Watcher {
+            path: trim(url).to_owned(),
+            queue: Arc::downgrade(&queue),
+        });
+        queue
+    }
+
+    /// Whether any path is watched, so that schemes can skip formatting the urls of changes
+    pub fn watching(&mut self) -> bool {
+        self.watchers.retain(|watcher| watcher.queue.upgrade().is_some());
+        ! self.watchers.is_empty()
+    }
+
+    /// Report a change to a url to the watchers of it and of the directories above it
+    pub fn notify(&mut self, kind: u16, url: &str) {
+        if ! self.watching() {
+            return;
+        }
+
+        let url = trim(url);
+        for watcher in self.watchers.iter() {
+            if url.starts_with(&watcher.path) {
+                let rest = &url[watcher.path.len()..];
+                if rest.is_empty() || rest.starts_with('/') || watcher.path.ends_with(':') {
+                    if let Some(queue) = watcher.queue.upgrade() {
+                        deliver(&queue, Change {
+                            kind: kind,
+                            path: rest.trim_left_matches('/').to_owned(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use alloc::boxed::Box;
+
+use collections::Vec;
+
+use drivers::io::{Io, Pio};
+use drivers::pci::config::PciConfig;
+
+use super::display::{Display, DisplayDevice, DisplayMode};
+
+const BGA_INDEX_ID: u16 = 0;
+const BGA_INDEX_XRES: u16 = 1;
+const BGA_INDEX_YRES: u16 = 2;
+const BGA_INDEX_BPP: u16 = 3;
+const BGA_INDEX_ENABLE: u16 = 4;
+const BGA_INDEX_VIRT_WIDTH: u16 = 6;
+const BGA_INDEX_VIRT_HEIGHT: u16 = 7;
+const BGA_INDEX_X_OFFSET: u16 = 8;
+const BGA_INDEX_Y_OFFSET: u16 = 9;
+const BGA_INDEX_VIDEO_MEMORY_64K: u16 = 0xA;
+
+const BGA_ID_MIN: u16 = 0xB0C0;
+const BGA_ID_MAX: u16 = 0xB0C5;
+/// The first version that reports the size of video memory
+const BGA_ID_VIDEO_MEMORY: u16 = 0xB0C5;
+
+const BGA_DISABLED: u16 = 0x00;
+const BGA_ENABLED: u16 = 0x01;
+const BGA_GETCAPS: u16 = 0x02;
+const BGA_LFB_ENABLED: u16 = 0x40;
+
+/// Resolutions offered, as long as they fit in the limits of the adapter
+static RESOLUTIONS: [(usize, usize); 10] = [
+    (640, 480),
+    (800, 600),
+    (1024, 768),
+    (1152, 864),
+    (1280, 720),
+    (1280, 1024),
+    (1366, 768),
+    (1600, 900),
+    (1920, 1080),
+    (2560, 1600),
+];
+
+/// Depths offered, as long as they fit in the limits of the adapter
+static DEPTHS: [usize; 3] = [16, 24, 32];
+
+/// Bochs Graphics Adaptor, used by Bochs, QEMU's `-vga std` and VirtualBox
+pub struct Bga {
+    index: Pio<u16>,
+    data: Pio<u16>,
+    /// Physical address of the linear framebuffer
+    framebuffer: usize,
+    /// Size of video memory in bytes
+    memory: usize,
+    max_width: usize,
+    max_height: usize,
+    max_depth: usize,
+}
+
+impl Bga {
+    pub fn new(mut pci: PciConfig) -> Option<Box<Self>> {
+        let mut module = box Bga {
+            index: Pio::new(0x1CE),
+            data: Pio::new(0x1CF),
+            framebuffer: unsafe { pci.read(0x10) } as usize & 0xFFFFFFF0,
+            memory: 0,
+            max_width: 0,
+            max_height: 0,
+            max_depth: 0,
+        };
+
+        let id = module.read(BGA_INDEX_ID);
+        if id < BGA_ID_MIN || id > BGA_ID_MAX {
+            syslog_info!(" ! BGA: Unsupported version {:X}", id);
+            return None;
+        }
+
+        // Reading the mode registers while GETCAPS is set returns the maximums
+        let enable = module.read(BGA_INDEX_ENABLE);
+        module.write(BGA_INDEX_ENABLE, enable | BGA_GETCAPS);
+        module.max_width = module.read(BGA_INDEX_XRES) as usize;
+        module.max_height = module.read(BGA_INDEX_YRES) as usize;
+        module.max_depth = module.read(BGA_INDEX_BPP) as usize;
+        module.write(BGA_INDEX_ENABLE, enable);
+
+        module.memory = if id >= BGA_ID_VIDEO_MEMORY {
+            module.read(BGA_INDEX_VIDEO_MEMORY_64K) as usize * 65536
+        } else {
+            // Older versions always have at least 4 MiB
+            4 * 1024 * 1024
+        };
+
+        syslog_info!(" + BGA {:X} on: {:X}, {} MB, max {}x{}x{}",
+                     id, module.framebuffer, module.memory / 1024 / 1024,
+                     module.max_width, module.max_height, module.max_depth);
+
+        Some(module)
+    }
+
+    fn read(&mut self, index: u16) -> u16 {
+        self.index.write(index);
+        self.data.read()
+    }
+
+    fn write(&mut self, index: u16, value: u16) {
+        self.index.write(index);
+        self.data.write(value);
+    }
+
+    /// Number of pages of a mode that fit in video memory, limited to two for double buffering
+    fn pages(&self, mode: DisplayMode) -> usize {
+        let bytes = mode.width * mode.height * mode.depth / 8;
+        if bytes > 0 {
+            let pages = self.memory / bytes;
+            if pages > 2 {
+                2
+            } else {
+                pages
+            }
+        } else {
+            0
+        }
+    }
+}
+
+impl DisplayDevice for Bga {
+    fn modes(&self) -> Vec<DisplayMode> {
+        let mut modes = Vec::new();
+        for &(width, height) in RESOLUTIONS.iter() {
+            for &depth in DEPTHS.iter() {
+                let mode = DisplayMode {
+                    width: width,
+                    height: height,
+                    depth: depth,
+                };
+
+                if width <= self.max_width && height <= self.max_height && depth <= self.max_depth
+                   && self.pages(mode) > 0 {
+                    modes.push(mode);
+                }
+            }
+        }
+        modes
+    }
+
+    fn set_mode(&mut self, mode: DisplayMode) -> Option<Box<Display>> {
+        if ! self.modes().contains(&mode) {
+            return None;
+        }
+
+        let pages = self.pages(mode);
+
+        self.write(BGA_INDEX_ENABLE, BGA_DISABLED);
+        self.write(BGA_INDEX_XRES, mode.width as u16);
+        self.write(BGA_INDEX_YRES, mode.height as u16);
+        self.write(BGA_INDEX_BPP, mode.depth as u16);
+        self.write(BGA_INDEX_VIRT_WIDTH, mode.width as u16);
+        self.write(BGA_INDEX_VIRT_HEIGHT, (mode.height * pages) as u16);
+        self.write(BGA_INDEX_X_OFFSET, 0);
+        self.write(BGA_INDEX_Y_OFFSET, 0);
+        self.write(BGA_INDEX_ENABLE, BGA_ENABLED | BGA_LFB_ENABLED);
+
+        Some(Display::new(self.framebuffer as *mut u32, mode.width, mode.height, mode.depth, pages))
+    }
+
+    fn set_page(&mut self, page: usize) {
+        let height = self.read(BGA_INDEX_YRES) as usize;
+        self.write(BGA_INDEX_Y_OFFSET, (page * height) as u16);
+    }
+}
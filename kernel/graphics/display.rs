@@ -1,9 +1,13 @@
 use alloc::boxed::Box;
 
-use core::cmp;
+use collections::Vec;
+
+use core::{cmp, ptr};
 
 use arch::memory;
 
+use system::error::{Error, Result, EINVAL, EPERM};
+
 use system::graphics::{fast_copy, fast_set};
 
 use super::FONT;
@@ -27,7 +31,7 @@ pub struct VBEModeInfo {
     xcharsize: u8,
     ycharsize: u8,
     numberofplanes: u8,
-    bitsperpixel: u8,
+    pub bitsperpixel: u8,
     numberofbanks: u8,
     memorymodel: u8,
     banksize: u8,
@@ -58,49 +62,205 @@ pub unsafe fn vbe_init(){
     }
 }
 
+/// A display mode
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DisplayMode {
+    /// Width in pixels
+    pub width: usize,
+    /// Height in pixels
+    pub height: usize,
+    /// Bits per pixel
+    pub depth: usize,
+}
+
+/// A display device capable of changing modes
+pub trait DisplayDevice {
+    /// List the modes supported by the device
+    fn modes(&self) -> Vec<DisplayMode>;
+
+    /// Switch to a new mode, returning a display for it
+    fn set_mode(&mut self, mode: DisplayMode) -> Option<Box<Display>>;
+
+    /// Scan out the given page of the framebuffer
+    fn set_page(&mut self, page: usize);
+
+    /// Move the hardware cursor
+    /// Returns `EPERM` if the device has no hardware cursor.
+    #[allow(unused_variables)]
+    fn cursor_move(&mut self, x: usize, y: usize) -> Result<()> {
+        Err(Error::new(EPERM))
+    }
+
+    /// Set the image of the hardware cursor
+    /// Returns `EPERM` if the device has no hardware cursor.
+    #[allow(unused_variables)]
+    fn cursor_image(&mut self, width: usize, height: usize, data: &[u32]) -> Result<()> {
+        Err(Error::new(EPERM))
+    }
+}
+
+/// Copy rows of pixels between buffers with different strides
+unsafe fn copy_rows(dst: *mut u8, dst_stride: usize, src: *const u8, src_stride: usize, row_bytes: usize, rows: usize) {
+    for row in 0..rows {
+        let dst_row = dst.offset((row * dst_stride) as isize);
+        let src_row = src.offset((row * src_stride) as isize);
+        if row_bytes % 4 == 0 {
+            fast_copy(dst_row as *mut u32, src_row as *const u32, row_bytes/4);
+        } else {
+            ptr::copy(src_row, dst_row, row_bytes);
+        }
+    }
+}
+
+/// Copy bytes between buffers, a word at a time when possible
+unsafe fn copy_bytes(dst: *mut u8, src: *const u8, bytes: usize) {
+    if bytes % 4 == 0 {
+        fast_copy(dst as *mut u32, src as *const u32, bytes/4);
+    } else {
+        ptr::copy(src, dst, bytes);
+    }
+}
+
+/// Write a pixel packed in `bytes_per_pixel` bytes
+unsafe fn write_pixel(dst: *mut u8, bytes_per_pixel: usize, value: u32) {
+    match bytes_per_pixel {
+        4 => ptr::write(dst as *mut u32, value),
+        3 => {
+            *dst = value as u8;
+            *dst.offset(1) = (value >> 8) as u8;
+            *dst.offset(2) = (value >> 16) as u8;
+        },
+        2 => {
+            *dst = value as u8;
+            *dst.offset(1) = (value >> 8) as u8;
+        },
+        _ => *dst = value as u8,
+    }
+}
+
 /// A display
 pub struct Display {
-    pub offscreen: *mut u32,
+    /// The buffer the console draws to, in the depth of the framebuffer
+    pub offscreen: *mut u8,
     pub onscreen: *mut u32,
     pub size: usize,
     pub width: usize,
     pub height: usize,
+    /// Bits per pixel of the framebuffer
+    pub depth: usize,
+    /// Start of the framebuffer
+    pub framebuffer: *mut u32,
+    /// Number of pages in the framebuffer
+    pub pages: usize,
+    /// The page currently scanned out
+    pub page: usize,
+    /// Regions of the hidden page changed since the last page flip
+    pub damage: Vec<(usize, usize, usize, usize)>,
 }
 
 impl Display {
+    pub fn new(framebuffer: *mut u32, width: usize, height: usize, depth: usize, pages: usize) -> Box<Self> {
+        let ret = box Display {
+            offscreen: unsafe { memory::alloc(width * height * ((depth + 7) / 8)) as *mut u8 },
+            onscreen: framebuffer,
+            size: width * height,
+            width: width,
+            height: height,
+            depth: depth,
+            framebuffer: framebuffer,
+            pages: cmp::max(1, pages),
+            page: 0,
+            damage: Vec::new(),
+        };
+
+        ret.set(Color::new(0, 0, 0));
+
+        ret
+    }
+
     pub fn root() -> Option<Box<Self>> {
         if let Some(mode_info) = unsafe { VBEMODEINFO } {
-            let ret = box Display {
-                offscreen: unsafe { memory::alloc(mode_info.xresolution as usize *
-                                         mode_info.yresolution as usize * 4) as *mut u32 },
-                onscreen: mode_info.physbaseptr as usize as *mut u32,
-                size: mode_info.xresolution as usize * mode_info.yresolution as usize,
-                width: mode_info.xresolution as usize,
-                height: mode_info.yresolution as usize,
-            };
-
-            ret.set(Color::new(0, 0, 0));
-
-            Some(ret)
+            Some(Display::new(mode_info.physbaseptr as usize as *mut u32,
+                              mode_info.xresolution as usize,
+                              mode_info.yresolution as usize,
+                              mode_info.bitsperpixel as usize,
+                              1))
         } else {
             None
         }
     }
 
+    /// The mode of the display
+    pub fn mode(&self) -> DisplayMode {
+        DisplayMode {
+            width: self.width,
+            height: self.height,
+            depth: self.depth,
+        }
+    }
+
+    /// Size of a pixel in bytes
+    pub fn bytes_per_pixel(&self) -> usize {
+        (self.depth + 7) / 8
+    }
+
+    /// Size of one page of the framebuffer in bytes
+    pub fn page_bytes(&self) -> usize {
+        self.size * self.bytes_per_pixel()
+    }
+
+    /// Pack a color in the depth of the framebuffer
+    pub fn pixel(&self, color: Color) -> u32 {
+        let r = (color.data >> 16) & 0xFF;
+        let g = (color.data >> 8) & 0xFF;
+        let b = color.data & 0xFF;
+        match self.depth {
+            15 => ((r >> 3) << 10) | ((g >> 3) << 5) | (b >> 3),
+            16 => ((r >> 3) << 11) | ((g >> 2) << 5) | (b >> 3),
+            _ => color.data,
+        }
+    }
+
+    /// Fill `count` pixels starting at `dst` with a color
+    unsafe fn fill(&self, dst: *mut u8, color: Color, count: usize) {
+        let bytes_per_pixel = self.bytes_per_pixel();
+        let value = self.pixel(color);
+        if bytes_per_pixel == 4 {
+            fast_set(dst as *mut u32, value, count);
+        } else {
+            for i in 0..count {
+                write_pixel(dst.offset((i * bytes_per_pixel) as isize), bytes_per_pixel, value);
+            }
+        }
+    }
+
+    /// Get a pointer to a page of the framebuffer
+    pub fn page_ptr(&self, page: usize) -> *mut u32 {
+        unsafe { (self.framebuffer as *mut u8).offset(((page % self.pages) * self.page_bytes()) as isize) as *mut u32 }
+    }
+
+    /// Get a pointer to the page that is not being scanned out, or the visible page if the
+    /// framebuffer only has one
+    pub fn hidden(&self) -> *mut u32 {
+        self.page_ptr(self.page + 1)
+    }
+
     /// Set the color
     pub fn set(&self, color: Color) {
         unsafe {
-            fast_set(self.offscreen, color.data, self.size);
+            self.fill(self.offscreen, color, self.size);
         }
     }
 
     /// Scroll the display
     pub fn scroll(&self, rows: usize, color: Color) {
         if rows > 0 && rows < self.height {
+            let bytes_per_pixel = self.bytes_per_pixel();
             let offset = rows * self.width;
             unsafe {
-                fast_copy(self.offscreen, self.offscreen.offset(offset as isize), self.size - offset);
-                fast_set(self.offscreen.offset((self.size - offset) as isize), color.data, offset);
+                ptr::copy(self.offscreen.offset((offset * bytes_per_pixel) as isize), self.offscreen,
+                          (self.size - offset) * bytes_per_pixel);
+                self.fill(self.offscreen.offset(((self.size - offset) * bytes_per_pixel) as isize), color, offset);
             }
         }
     }
@@ -108,21 +268,82 @@ impl Display {
     /// Flip the display
     pub fn flip(&self) {
         unsafe {
-            fast_copy(self.onscreen, self.offscreen, self.size);
+            copy_bytes(self.onscreen as *mut u8, self.offscreen, self.page_bytes());
+        }
+    }
+
+    /// Copy a rectangle of pixels, packed in the display's depth, to the hidden page
+    /// Returns the number of bytes consumed from `data`.
+    pub fn blit(&mut self, x: usize, y: usize, w: usize, h: usize, data: &[u8]) -> Result<usize> {
+        let bytes_per_pixel = self.bytes_per_pixel();
+        // The size comes from user space, so the products are checked for overflow
+        let row_bytes = try!(w.checked_mul(bytes_per_pixel).ok_or(Error::new(EINVAL)));
+        let bytes = try!(row_bytes.checked_mul(h).ok_or(Error::new(EINVAL)));
+        if data.len() < bytes {
+            return Err(Error::new(EINVAL));
         }
+
+        if x < self.width && y < self.height {
+            let clip_w = cmp::min(w, self.width - x);
+            let clip_h = cmp::min(h, self.height - y);
+            let stride = self.width * bytes_per_pixel;
+            unsafe {
+                copy_rows((self.hidden() as *mut u8).offset((y * stride + x * bytes_per_pixel) as isize), stride,
+                          data.as_ptr(), row_bytes,
+                          clip_w * bytes_per_pixel, clip_h);
+            }
+            self.damage(x, y, clip_w, clip_h);
+        }
+
+        Ok(bytes)
     }
 
+    /// Record a changed region of the hidden page
+    pub fn damage(&mut self, x: usize, y: usize, w: usize, h: usize) {
+        if self.pages > 1 && w > 0 && h > 0 {
+            self.damage.push((x, y, w, h));
+        }
+    }
+
+    /// Make the hidden page visible, using the given device to change the scanout
+    ///
+    /// Damaged regions are copied to the newly hidden page afterwards, so that the next frame
+    /// only needs to write what changes.
+    pub fn flip_page(&mut self, device: &mut DisplayDevice) {
+        if self.pages > 1 {
+            self.page = (self.page + 1) % self.pages;
+            self.onscreen = self.page_ptr(self.page);
+            device.set_page(self.page);
+
+            let bytes_per_pixel = self.bytes_per_pixel();
+            let stride = self.width * bytes_per_pixel;
+            for &(x, y, w, h) in self.damage.iter() {
+                let offset = (y * stride + x * bytes_per_pixel) as isize;
+                unsafe {
+                    copy_rows((self.hidden() as *mut u8).offset(offset), stride,
+                              (self.onscreen as *const u8).offset(offset), stride,
+                              w * bytes_per_pixel, h);
+                }
+            }
+            self.damage.clear();
+        }
+    }
+
+    /// Copy rows of the offscreen buffer to every page of the framebuffer, so that they are
+    /// visible at once and stay visible when the pages are flipped
     pub fn flip_rows(&self, row: usize, count: usize){
-        unsafe {
-            let offset = row * self.width;
-            let size = count * self.width;
-            fast_copy(self.onscreen.offset(offset as isize), self.offscreen.offset(offset as isize), size);
+        let stride = self.width * self.bytes_per_pixel();
+        let offset = (row * stride) as isize;
+        for page in 0..self.pages {
+            unsafe {
+                copy_bytes((self.page_ptr(page) as *mut u8).offset(offset), self.offscreen.offset(offset), count * stride);
+            }
         }
     }
 
     /// Draw a rectangle
     pub fn rect(&self, x: usize, y: usize, w: usize, h: usize, color: Color) {
-        let data = color.data;
+        let bytes_per_pixel = self.bytes_per_pixel();
 
         let start_y = cmp::min(self.height - 1, y);
         let end_y = cmp::min(self.height, y + h);
@@ -132,7 +353,7 @@ impl Display {
 
         for y in start_y..end_y {
             unsafe {
-                fast_set(self.offscreen.offset((y * self.width + start_x) as isize), data, len);
+                self.fill(self.offscreen.offset(((y * self.width + start_x) * bytes_per_pixel) as isize), color, len);
             }
         }
     }
@@ -140,8 +361,9 @@ impl Display {
     /// Draw a char
     pub fn char(&self, x: usize, y: usize, character: char, color: Color) {
         if x + 8 <= self.width && y + 16 <= self.height {
-            let data = color.data;
-            let mut dst = unsafe { self.offscreen.offset((y * self.width + x) as isize) };
+            let bytes_per_pixel = self.bytes_per_pixel();
+            let value = self.pixel(color);
+            let mut dst = unsafe { self.offscreen.offset(((y * self.width + x) * bytes_per_pixel) as isize) };
 
             let font_i = 16 * (character as usize);
             if font_i + 16 <= FONT.len() {
@@ -149,10 +371,10 @@ impl Display {
                     let row_data = FONT[font_i + row];
                     for col in 0..8 {
                         if (row_data >> (7 - col)) & 1 == 1 {
-                            unsafe { *dst.offset(col) = data; }
+                            unsafe { write_pixel(dst.offset(col * bytes_per_pixel as isize), bytes_per_pixel, value); }
                         }
                     }
-                    dst = unsafe { dst.offset(self.width as isize) };
+                    dst = unsafe { dst.offset((self.width * bytes_per_pixel) as isize) };
                 }
             }
         }
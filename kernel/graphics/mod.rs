@@ -1,6 +1,8 @@
 
 pub static FONT: &'static [u8] = include_bytes!("../../filesystem/ui/unifont.font");
 
+/// Bochs graphics adaptor
+pub mod bga;
 /// Color struct
 pub mod color;
 /// Display struct
@@ -25,19 +25,20 @@ use acpi::Acpi;
 
 use alloc::boxed::Box;
 
-use arch::context::{context_switch, Context, ContextFile};
+use arch::context::{context_switch, Context, ContextFile, CONTEXT_STACK_GUARD_SIZE, CONTEXT_STACK_MAX_SIZE};
 use arch::gdt::{GdtDescriptor, GdtEntry};
 use arch::idt::{IdtDescriptor, IdtEntry};
 use arch::memory;
-use arch::paging::Page;
+use arch::paging::{self, Page};
 use arch::regs::Regs;
 use arch::tss::Tss;
 
 use collections::{String, Vec};
 use collections::string::ToString;
 
-use core::{mem, slice, usize};
+use core::{cmp, mem, slice, usize};
 
+use common::random::seed_entropy;
 use common::time::Duration;
 
 use drivers::pci;
@@ -48,6 +49,8 @@ use drivers::serial::{self, Serial};
 
 use env::Environment;
 
+use fs::LockOwner;
+
 use graphics::display;
 
 use network::schemes::{ArpScheme, EthernetScheme, IcmpScheme, IpScheme, NetConfigScheme, TcpScheme, UdpScheme};
@@ -57,9 +60,13 @@ use schemes::disk::DiskScheme;
 use schemes::display::DisplayScheme;
 use schemes::env::EnvScheme;
 use schemes::initfs::InitFsScheme;
+use schemes::input::InputScheme;
 use schemes::pty::PtyScheme;
 use schemes::sys::SysScheme;
+use schemes::tmp::TmpScheme;
+use schemes::watch::WatchScheme;
 
+use syscall::{RLIM_INFINITY, RLIMIT_CPU, RLIMIT_STACK};
 use syscall::process::exit;
 use syscall::execute::execute;
 
@@ -414,6 +421,9 @@ unsafe fn init(gdt_ptr: *mut GdtDescriptor, idt_ptr: *mut IdtDescriptor, tss_ptr
 
             *env.clock_realtime.get() = Rtc::new().time();
 
+            // Seed the random placement of programs in memory
+            seed_entropy((*env.clock_realtime.get()).secs as u64);
+
             (&mut *env.schemes.get()).push(Ps2::new());
 
             pci::pci_init(env);
@@ -426,12 +436,18 @@ unsafe fn init(gdt_ptr: *mut GdtDescriptor, idt_ptr: *mut IdtDescriptor, tss_ptr
 
             (&mut *env.schemes.get()).push(InitFsScheme::new());
 
+            (&mut *env.schemes.get()).push(box InputScheme);
+
             (&mut *env.schemes.get()).push(box EnvScheme);
 
             (&mut *env.schemes.get()).push(PtyScheme::new());
 
             (&mut *env.schemes.get()).push(SysScheme::new());
 
+            (&mut *env.schemes.get()).push(TmpScheme::new());
+
+            (&mut *env.schemes.get()).push(box WatchScheme);
+
             /*
             let mut nics = Vec::new();
             nics.append(&mut env.nics.lock());
@@ -470,15 +486,21 @@ unsafe fn init(gdt_ptr: *mut GdtDescriptor, idt_ptr: *mut IdtDescriptor, tss_ptr
 
                     (*current.files.get()).push(ContextFile {
                         fd: 0,
+                        flags: 0,
                         resource: ::env().open("debug:", 0).unwrap(),
+                        lock_owner: LockOwner::new(),
                     });
                     (*current.files.get()).push(ContextFile {
                         fd: 1,
+                        flags: 0,
                         resource: ::env().open("debug:", 0).unwrap(),
+                        lock_owner: LockOwner::new(),
                     });
                     (*current.files.get()).push(ContextFile {
                         fd: 2,
+                        flags: 0,
                         resource: ::env().open("debug:", 0).unwrap(),
+                        lock_owner: LockOwner::new(),
                     });
 
                     current.set_env_var("PATH", "file:/bin").unwrap();
@@ -488,7 +510,7 @@ unsafe fn init(gdt_ptr: *mut GdtDescriptor, idt_ptr: *mut IdtDescriptor, tss_ptr
                 }
 
                 syslog_info!("The kernel has finished booting. Running /bin/init");
-                if let Err(err) = execute(vec!["initfs:/bin/init".to_string()]) {
+                if let Err(err) = execute(vec!["initfs:/bin/init".to_string()], None) {
                     syslog_info!("kernel: init: failed to execute: {}", err);
                 }
             });
@@ -545,9 +567,13 @@ pub extern "cdecl" fn kernel(interrupt: usize, mut regs: &mut Regs) {
                 let contexts = unsafe { &mut *::env().contexts.get() };
                 if let Ok(context) = contexts.current() {
                     if let Some(ref stack) = context.stack {
-                        if cr2 >= stack.virtual_address && cr2 <= stack.virtual_address + stack.virtual_size {
-                            syslog_info!("    STACK {:08X}", cr2 - stack.virtual_address + stack.physical_address);
-                            syslog_info!("    {:08X}:{:08X} at {:08X}", stack.virtual_address, stack.virtual_address + stack.virtual_size, stack.physical_address);
+                        let limit = stack.top() - cmp::min(context.rlimits[RLIMIT_STACK].rlim_cur, CONTEXT_STACK_MAX_SIZE);
+                        if cr2 < limit && cr2 + CONTEXT_STACK_GUARD_SIZE >= limit {
+                            syslog_info!("    STACK OVERFLOW {:X} bytes below the stack limit", limit - cr2);
+                            syslog_info!("    {:08X}:{:08X} in {} pieces", stack.bottom(), stack.top(), stack.memory.len());
+                        } else if let Some(address) = stack.translate(cr2, 0) {
+                            syslog_info!("    STACK {:08X}", address);
+                            syslog_info!("    {:08X}:{:08X} in {} pieces", stack.bottom(), stack.top(), stack.memory.len());
                         }
                     }
 
@@ -632,12 +658,37 @@ pub extern "cdecl" fn kernel(interrupt: usize, mut regs: &mut Regs) {
             exception_inner!($name);
             syslog_info!("    ERR: {:08X}", error);
 
+            if interrupt == 0xE {
+                syslog_info!("    {} {} {}",
+                             if error & 4 == 4 { "User" } else { "Kernel" },
+                             if error & 0x10 == 0x10 {
+                                 "instruction fetch from"
+                             } else if error & 2 == 2 {
+                                 "write to"
+                             } else {
+                                 "read from"
+                             },
+                             if error & 1 == 1 {
+                                 "a page without permission"
+                             } else {
+                                 "a page that is not present"
+                             });
+            }
+
             loop {
                 exit(127);
             }
         })
     };
 
+    // System calls access user memory through the pointers they are given, other interrupts and
+    // exceptions do not
+    if interrupt == 0x80 {
+        unsafe { paging::allow_user_access() };
+    } else {
+        unsafe { paging::deny_user_access() };
+    }
+
     // Do not catch init interrupt
     if interrupt < 0xFF {
         unsafe { (&mut *env().interrupts.get())[interrupt as usize] += 1 };
@@ -656,6 +707,16 @@ pub extern "cdecl" fn kernel(interrupt: usize, mut regs: &mut Regs) {
 
             if let Ok(mut current) = unsafe { &mut *env().contexts.get() }.current_mut() {
                 current.time += 1;
+                current.cpu_time = current.cpu_time + PIT_DURATION;
+
+                // A context that used up its CPU time limit is terminated when it is interrupted
+                // in user mode, after acknowledging the timer interrupt that exit does not return to
+                let cpu_limit = current.rlimits[RLIMIT_CPU].rlim_cur;
+                if regs.cs & 3 == 3 && cpu_limit != RLIM_INFINITY && current.cpu_time.secs as usize >= cpu_limit {
+                    syslog_info!("PID {}: {} exceeded its CPU time limit of {} seconds", current.pid, current.name, cpu_limit);
+                    Pio::<u8>::new(0x20).write(0x20);
+                    exit(127);
+                }
             }
 
             unsafe { context_switch(); }
@@ -684,7 +745,22 @@ pub extern "cdecl" fn kernel(interrupt: usize, mut regs: &mut Regs) {
         0xB => exception_error!("Segment not present exception"),
         0xC => exception_error!("Stack-segment fault"),
         0xD => exception_error!("General protection fault"),
-        0xE => exception_error!("Page fault"),
+        0xE => {
+            // A fault from user mode below the bottom of the stack grows it, if it is within the
+            // stack limit, and the faulting instruction is run again
+            let error = regs.ip;
+            let cr2: usize;
+            unsafe { asm!("mov $0, cr2" : "=r"(cr2) : : : "intel", "volatile") };
+            if error & 4 == 4 {
+                if let Ok(mut current) = unsafe { &mut *env().contexts.get() }.current_mut() {
+                    if unsafe { current.grow_stack(cr2) } {
+                        return;
+                    }
+                }
+            }
+
+            exception_error!("Page fault")
+        },
         0x10 => exception!("x87 floating-point exception"),
         0x11 => exception_error!("Alignment check exception"),
         0x12 => exception!("Machine check exception"),
@@ -6,7 +6,7 @@ use collections::vec_deque::VecDeque;
 use core::cell::UnsafeCell;
 use core::ops::DerefMut;
 
-use fs::Resource;
+use fs::{Resource, fcntl_blocking};
 
 use system::error::Result;
 
@@ -99,6 +99,11 @@ impl Resource for NetworkResource {
         Ok(buf.len())
     }
 
+    /// Reads wait for the next packet from the card
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        fcntl_blocking(cmd, arg)
+    }
+
     fn sync(&mut self) -> Result<()> {
         unsafe {
             (*self.nic).sync();
@@ -9,7 +9,7 @@ use common::to_num::ToNum;
 use network::common::*;
 use network::ethernet::*;
 
-use fs::{KScheme, Resource};
+use fs::{KScheme, Resource, fcntl_blocking};
 
 use system::error::{Error, Result, ENOENT};
 use system::syscall::O_RDWR;
@@ -98,6 +98,11 @@ impl Resource for EthernetResource {
         }
     }
 
+    /// Reads wait for a frame from the card, which cannot be done without blocking
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        fcntl_blocking(cmd, arg)
+    }
+
     fn sync(&mut self) -> Result<()> {
         self.network.sync()
     }
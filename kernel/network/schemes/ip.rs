@@ -11,7 +11,7 @@ use common::random;
 use common::to_num::ToNum;
 
 use super::arp::{Arp, ArpHeader};
-use fs::{KScheme, Resource};
+use fs::{KScheme, Resource, fcntl_blocking};
 
 use system::error::{Error, Result, ENOENT};
 use system::syscall::O_RDWR;
@@ -114,6 +114,11 @@ impl Resource for IpResource {
         }
     }
 
+    /// Reads block until a packet arrives, so `O_NONBLOCK` is not supported
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        fcntl_blocking(cmd, arg)
+    }
+
     fn sync(&mut self) -> Result<()> {
         self.link.sync()
     }
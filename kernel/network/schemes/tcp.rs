@@ -8,7 +8,7 @@ use common::random::rand;
 use core::{cmp, mem, slice, str};
 use core::cell::UnsafeCell;
 
-use fs::{KScheme, Resource};
+use fs::{KScheme, Resource, fcntl_blocking};
 
 use network::common::{n16, n32, Checksum, Ipv4Addr, IP_ADDR, FromBytes, ToBytes};
 
@@ -400,6 +400,11 @@ impl Resource for TcpResource {
         unsafe { (*self.stream.get()).write(buf) }
     }
 
+    /// Reads block until the stream has data, so `O_NONBLOCK` is not supported
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        fcntl_blocking(cmd, arg)
+    }
+
     fn sync(&mut self) -> Result<()> {
         unsafe { (*self.stream.get()).sync() }
     }
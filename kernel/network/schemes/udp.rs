@@ -6,7 +6,7 @@ use common::random::rand;
 
 use core::{cmp, mem, ptr, slice, str};
 
-use fs::{KScheme, Resource};
+use fs::{KScheme, Resource, fcntl_blocking};
 
 use network::common::{n16, Checksum, Ipv4Addr, IP_ADDR, FromBytes, ToBytes};
 
@@ -156,6 +156,11 @@ impl Resource for UdpResource {
         self.ip.write(&udp.to_bytes()).and(Ok(buf.len()))
     }
 
+    /// Reads wait for a datagram
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        fcntl_blocking(cmd, arg)
+    }
+
     fn sync(&mut self) -> Result<()> {
         self.ip.sync()
     }
@@ -5,14 +5,17 @@ use collections::string::String;
 
 use core::cmp;
 
-use fs::{KScheme, Resource};
+use fs::{KScheme, Resource, fcntl_flags};
 
-use system::error::Result;
+use system::error::{Error, Result, EAGAIN};
+use system::syscall::O_NONBLOCK;
 
 /// A debug resource
 pub struct DebugResource {
     pub path: String,
     pub command: String,
+    /// Status flags
+    pub flags: usize,
 }
 
 impl Resource for DebugResource {
@@ -20,6 +23,7 @@ impl Resource for DebugResource {
         Ok(box DebugResource {
             path: self.path.clone(),
             command: self.command.clone(),
+            flags: self.flags,
         })
     }
 
@@ -35,7 +39,11 @@ impl Resource for DebugResource {
 
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         if self.command.is_empty() {
-            self.command = unsafe { &mut *::env().console.get() }.commands.receive("DebugResource::read");
+            let commands = &unsafe { &mut *::env().console.get() }.commands;
+            if self.flags & O_NONBLOCK == O_NONBLOCK && unsafe { commands.inner() }.is_empty() {
+                return Err(Error::new(EAGAIN));
+            }
+            self.command = commands.receive("DebugResource::read");
         }
 
         let mut i = 0;
@@ -52,6 +60,10 @@ impl Resource for DebugResource {
         Ok(buf.len())
     }
 
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        fcntl_flags(&mut self.flags, O_NONBLOCK, cmd, arg)
+    }
+
     fn sync(&mut self) -> Result<()> {
         let console = unsafe { &mut *::env().console.get() };
         if let Some(ref mut inner) = console.inner {
@@ -75,17 +87,19 @@ impl KScheme for DebugScheme {
         "debug"
     }
 
-    fn open(&mut self, _: &str, _: usize) -> Result<Box<Resource>> {
+    fn open(&mut self, _: &str, flags: usize) -> Result<Box<Resource>> {
         let console = unsafe { & *::env().console.get() };
         if let Some(ref display) = console.display {
             Ok(box DebugResource {
                 path: format!("debug:{}/{}", display.width/8, display.height/16),
-                command: String::new()
+                command: String::new(),
+                flags: flags & O_NONBLOCK,
             })
         } else {
             Ok(box DebugResource {
                 path: "debug:".to_owned(),
-                command: String::new()
+                command: String::new(),
+                flags: flags & O_NONBLOCK,
             })
         }
     }
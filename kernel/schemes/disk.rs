@@ -2,20 +2,33 @@ use alloc::arc::Arc;
 use alloc::boxed::Box;
 
 use collections::borrow::ToOwned;
-use collections::String;
+use collections::{String, Vec};
 
 use core::cell::UnsafeCell;
 use core::cmp;
 use disk::Disk;
-use fs::{KScheme, Resource, ResourceSeek, VecResource};
+use fs::{DirEntry, DirResource, KScheme, Resource, ResourceSeek, stat_dev};
 
-use syscall::{MODE_DIR, MODE_FILE, Stat};
+use syscall::{DT_BLK, MODE_FILE, Stat};
 
-use system::error::{Error, Result, ENOENT};
+use system::error::{Error, Result, EINVAL, ENOENT};
+use system::watch::WATCH_MODIFY;
+
+/// The block at a byte offset, which fails with `EINVAL` unless the offset is a multiple of the
+/// block size, as disks only transfer whole blocks
+fn block(offset: u64) -> Result<u64> {
+    if offset % 512 == 0 {
+        Ok(offset / 512)
+    } else {
+        Err(Error::new(EINVAL))
+    }
+}
 
 /// A disk resource
 pub struct DiskResource {
     pub path: String,
+    /// The number of the disk, used as its device ID
+    pub number: usize,
     pub disk: Arc<UnsafeCell<Box<Disk>>>,
     pub seek: u64,
 }
@@ -24,6 +37,7 @@ impl Resource for DiskResource {
     fn dup(&self) -> Result<Box<Resource>> {
         Ok(box DiskResource {
             path: self.path.clone(),
+            number: self.number,
             disk: self.disk.clone(),
             seek: self.seek,
         })
@@ -39,14 +53,27 @@ impl Resource for DiskResource {
     }
 
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        let count = try!(unsafe { &mut *self.disk.get() }.read(self.seek/512, buf));
+        let block = try!(block(self.seek));
+        let count = try!(unsafe { &mut *self.disk.get() }.read(block, buf));
         self.seek += count as u64;
         Ok(count)
     }
 
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        let count = try!(unsafe { &mut *self.disk.get() }.write(self.seek/512, buf));
+        let block = try!(block(self.seek));
+        let count = try!(unsafe { &mut *self.disk.get() }.write(block, buf));
         self.seek += count as u64;
+        unsafe { &mut *::env().watches.get() }.notify(WATCH_MODIFY, &self.path);
+        Ok(count)
+    }
+
+    fn pread(&mut self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        unsafe { &mut *self.disk.get() }.read(try!(block(offset)), buf)
+    }
+
+    fn pwrite(&mut self, buf: &[u8], offset: u64) -> Result<usize> {
+        let count = try!(unsafe { &mut *self.disk.get() }.write(try!(block(offset)), buf));
+        unsafe { &mut *::env().watches.get() }.notify(WATCH_MODIFY, &self.path);
         Ok(count)
     }
 
@@ -61,8 +88,15 @@ impl Resource for DiskResource {
     }
 
     fn stat(&self, stat: &mut Stat) -> Result<()> {
-        stat.st_size = unsafe { & *self.disk.get() }.size() as u32;
-        stat.st_mode = MODE_FILE;
+        let size = unsafe { & *self.disk.get() }.size();
+        stat.st_dev = stat_dev("disk");
+        stat.st_ino = self.number as u64 + 1;
+        stat.st_rdev = self.number as u64 + 1;
+        stat.st_mode = MODE_FILE | 0o600;
+        stat.st_nlink = 1;
+        stat.st_size = size;
+        stat.st_blksize = 512;
+        stat.st_blocks = (size + 511) / 512;
         Ok(())
     }
 
@@ -95,20 +129,18 @@ impl KScheme for DiskScheme {
         let path = url.splitn(2, ":").nth(1).unwrap_or("").trim_matches('/');
 
         if path.is_empty() {
-            let mut list = String::new();
+            let mut entries = Vec::new();
             for i in 0..unsafe { & *::env().disks.get() }.len() {
-                if ! list.is_empty() {
-                    list.push('\n');
-                }
-                list.push_str(&format!("{}", i));
+                entries.push(DirEntry::new(&format!("{}", i), DT_BLK, i as u64 + 1));
             }
 
-            return Ok(box VecResource::new("disk:/".to_owned(), list.into_bytes(), MODE_DIR));
+            return Ok(box DirResource::new("disk:/".to_owned(), entries));
         } else {
             if let Ok(number) = path.parse::<usize>() {
                 if let Some(disk) = unsafe { & *::env().disks.get() }.get(number) {
                     return Ok(box DiskResource {
                         path: format!("disk:/{}", number),
+                        number: number,
                         disk: disk.clone(),
                         seek: 0
                     });
@@ -1,36 +1,50 @@
 use alloc::boxed::Box;
 
 use collections::String;
+use collections::string::ToString;
 
 use common::event::Event;
+use common::to_num::ToNum;
 
-use core::{cmp, ptr};
+use core::{cmp, ptr, slice, str};
 use core::mem::size_of;
 
-use fs::{KScheme, Resource, ResourceSeek};
+use fs::{KScheme, Resource, ResourceSeek, VecResource, fcntl_flags};
 
-use system::error::{Error, Result, EACCES, EBADF, ENOENT, EINVAL};
-use system::graphics::fast_copy;
+use graphics::display::DisplayMode;
+
+use system::error::{Error, Result, EACCES, EAGAIN, EBADF, ENOENT, EINVAL};
+use system::graphics::{fast_copy, DisplayRect};
+use system::syscall::{MODE_FILE, O_NONBLOCK};
 
 /// A display resource
 pub struct DisplayResource {
-    /// Path
-    path: String,
     /// Seek
     seek: usize,
+    /// Writes are a list of `DisplayRect`, each followed by its pixels
+    damage: bool,
+    /// Status flags
+    flags: usize,
 }
 
 impl Resource for DisplayResource {
     fn dup(&self) -> Result<Box<Resource>> {
         Ok(Box::new(DisplayResource {
-            path: self.path.clone(),
-            seek: self.seek
+            seek: self.seek,
+            damage: self.damage,
+            flags: self.flags,
         }))
     }
 
-    /// Return the URL for display resource
+    /// Return the URL for display resource, which contains the current size
     fn path(&self, buf: &mut [u8]) -> Result<usize> {
-        let path = self.path.as_bytes();
+        let console = unsafe { & *::env().console.get() };
+        let path_string = if let Some(ref display) = console.display {
+            format!("display:{}/{}", display.width, display.height)
+        } else {
+            return Err(Error::new(EBADF));
+        };
+        let path = path_string.as_bytes();
 
         for (b, p) in buf.iter_mut().zip(path.iter()) {
             *b = *p;
@@ -41,6 +55,10 @@ impl Resource for DisplayResource {
 
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         if buf.len() >= size_of::<Event>() {
+            if self.flags & O_NONBLOCK == O_NONBLOCK && unsafe { ::env().events.inner() }.is_empty() {
+                return Err(Error::new(EAGAIN));
+            }
+
             let event = ::env().events.receive("DisplayResource::read");
             unsafe { ptr::write(buf.as_mut_ptr().offset(0isize) as *mut Event, event) };
             let mut i = size_of::<Event>();
@@ -61,17 +79,36 @@ impl Resource for DisplayResource {
     }
 
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        let console = unsafe { & *::env().console.get() };
-        if let Some(ref display) = console.display {
-            let size = cmp::max(0, cmp::min(display.size as isize - self.seek as isize, (buf.len()/4) as isize)) as usize;
+        let console = unsafe { &mut *::env().console.get() };
+        if let Some(ref mut display) = console.display {
+            if self.damage {
+                let mut i = 0;
+                while i + size_of::<DisplayRect>() <= buf.len() {
+                    let rect = unsafe { ptr::read(buf.as_ptr().offset(i as isize) as *const DisplayRect) };
+                    i += size_of::<DisplayRect>();
+                    i += try!(display.blit(rect.x as usize, rect.y as usize, rect.w as usize, rect.h as usize, &buf[i..]));
+                }
+
+                return Ok(i);
+            }
+
+            let bytes_per_pixel = display.bytes_per_pixel();
+            let size = cmp::max(0, cmp::min(display.size as isize - self.seek as isize, (buf.len()/bytes_per_pixel) as isize)) as usize;
 
             if size > 0 {
-                unsafe {
-                    fast_copy(display.onscreen.offset(self.seek as isize), buf.as_ptr() as *const u32, size);
+                // Without damage rectangles, there is no sync to wait for, so every page is drawn
+                // to and the pixels are visible at once
+                for page in 0..display.pages {
+                    let dst = unsafe { (display.page_ptr(page) as *mut u8).offset((self.seek * bytes_per_pixel) as isize) };
+                    if bytes_per_pixel == 4 {
+                        unsafe { fast_copy(dst as *mut u32, buf.as_ptr() as *const u32, size) };
+                    } else {
+                        unsafe { ptr::copy(buf.as_ptr(), dst, size * bytes_per_pixel) };
+                    }
                 }
             }
 
-            Ok(size)
+            Ok(size * bytes_per_pixel)
         } else {
             Err(Error::new(EBADF))
         }
@@ -92,6 +129,153 @@ impl Resource for DisplayResource {
         }
     }
 
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        fcntl_flags(&mut self.flags, O_NONBLOCK, cmd, arg)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        unsafe { &mut *::env().console.get() }.flip_page();
+        Ok(())
+    }
+}
+
+/// A resource for reading and changing the display mode
+pub struct DisplayModeResource {
+    /// Seek
+    seek: usize,
+}
+
+impl DisplayModeResource {
+    fn mode_string() -> Result<String> {
+        let console = unsafe { & *::env().console.get() };
+        if let Some(ref display) = console.display {
+            Ok(format!("{}/{}/{}\n", display.width, display.height, display.depth))
+        } else {
+            Err(Error::new(ENOENT))
+        }
+    }
+}
+
+impl Resource for DisplayModeResource {
+    fn dup(&self) -> Result<Box<Resource>> {
+        Ok(box DisplayModeResource {
+            seek: self.seek
+        })
+    }
+
+    fn path(&self, buf: &mut [u8]) -> Result<usize> {
+        let path = b"display:mode";
+
+        for (b, p) in buf.iter_mut().zip(path.iter()) {
+            *b = *p;
+        }
+
+        Ok(cmp::min(buf.len(), path.len()))
+    }
+
+    /// Read the current mode as `width/height/depth`
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let mode = try!(DisplayModeResource::mode_string());
+        let mode = mode.as_bytes();
+
+        let mut i = 0;
+        while i < buf.len() && self.seek < mode.len() {
+            buf[i] = mode[self.seek];
+            i += 1;
+            self.seek += 1;
+        }
+
+        Ok(i)
+    }
+
+    /// Request a mode change, as `width/height/depth`
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let request = try!(str::from_utf8(buf).or(Err(Error::new(EINVAL))));
+        let mut parts = request.trim().split('/');
+
+        let mode = DisplayMode {
+            width: parts.next().unwrap_or("").to_num(),
+            height: parts.next().unwrap_or("").to_num(),
+            depth: match parts.next() {
+                Some(depth) => depth.to_num(),
+                None => 32
+            },
+        };
+
+        try!(unsafe { &mut *::env().console.get() }.set_mode(mode));
+
+        self.seek = 0;
+
+        Ok(buf.len())
+    }
+
+    fn seek(&mut self, pos: ResourceSeek) -> Result<usize> {
+        let len = try!(DisplayModeResource::mode_string()).len();
+        self.seek = match pos {
+            ResourceSeek::Start(offset) => cmp::min(len, offset),
+            ResourceSeek::Current(offset) => cmp::min(len, cmp::max(0, self.seek as isize + offset) as usize),
+            ResourceSeek::End(offset) => cmp::min(len, cmp::max(0, len as isize + offset) as usize),
+        };
+
+        Ok(self.seek)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A resource for controlling the hardware cursor
+pub struct DisplayCursorResource;
+
+impl Resource for DisplayCursorResource {
+    fn dup(&self) -> Result<Box<Resource>> {
+        Ok(box DisplayCursorResource)
+    }
+
+    fn path(&self, buf: &mut [u8]) -> Result<usize> {
+        let path = b"display:cursor";
+
+        for (b, p) in buf.iter_mut().zip(path.iter()) {
+            *b = *p;
+        }
+
+        Ok(cmp::min(buf.len(), path.len()))
+    }
+
+    /// Move the cursor to the position of a `DisplayRect`, setting the image to the pixels that
+    /// follow if its size is not zero
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if buf.len() < size_of::<DisplayRect>() {
+            return Err(Error::new(EINVAL));
+        }
+
+        let console = unsafe { &mut *::env().console.get() };
+        if let Some(ref mut device) = console.device {
+            let rect = unsafe { ptr::read(buf.as_ptr() as *const DisplayRect) };
+            let mut i = size_of::<DisplayRect>();
+
+            // The size comes from user space, so the products are checked for overflow
+            let pixels = try!((rect.w as usize).checked_mul(rect.h as usize).ok_or(Error::new(EINVAL)));
+            if pixels > 0 {
+                let bytes = try!(pixels.checked_mul(4).ok_or(Error::new(EINVAL)));
+                if buf.len() - i < bytes {
+                    return Err(Error::new(EINVAL));
+                }
+
+                let data = unsafe { slice::from_raw_parts(buf.as_ptr().offset(i as isize) as *const u32, pixels) };
+                try!(device.cursor_image(rect.w as usize, rect.h as usize, data));
+                i += bytes;
+            }
+
+            try!(device.cursor_move(rect.x as usize, rect.y as usize));
+
+            Ok(i)
+        } else {
+            Err(Error::new(EBADF))
+        }
+    }
+
     fn sync(&mut self) -> Result<()> {
         Ok(())
     }
@@ -104,16 +288,42 @@ impl KScheme for DisplayScheme {
         "display"
     }
 
-    fn open(&mut self, url: &str, _: usize) -> Result<Box<Resource>> {
-        if url.splitn(2, ":").nth(1).unwrap_or("") == "manager" {
+    fn open(&mut self, url: &str, flags: usize) -> Result<Box<Resource>> {
+        let mut path = url.splitn(2, ":").nth(1).unwrap_or("");
+
+        // A path ending in damage selects the damage rectangle protocol for writes
+        let damage = path == "damage" || path.ends_with("/damage");
+        if damage {
+            path = path.trim_right_matches("damage").trim_right_matches('/');
+        }
+
+        if path == "modes" {
+            let console = unsafe { & *::env().console.get() };
+            let mut list = String::new();
+            if let Some(ref device) = console.device {
+                for mode in device.modes().iter() {
+                    list.push_str(&format!("{}/{}/{}\n", mode.width, mode.height, mode.depth));
+                }
+            } else if let Some(ref display) = console.display {
+                list.push_str(&format!("{}/{}/{}\n", display.width, display.height, display.depth));
+            }
+            Ok(box VecResource::new("display:modes".to_string(), list.into_bytes(), MODE_FILE))
+        } else if path == "mode" {
+            Ok(box DisplayModeResource {
+                seek: 0,
+            })
+        } else if path == "cursor" {
+            Ok(box DisplayCursorResource)
+        } else if path == "manager" {
             let console = unsafe { &mut *::env().console.get() };
             if console.draw {
                 console.draw = false;
 
-                if let Some(ref display) = console.display {
+                if console.display.is_some() {
                     Ok(box DisplayResource {
-                        path: format!("display:{}/{}", display.width, display.height),
                         seek: 0,
+                        damage: damage,
+                        flags: flags & O_NONBLOCK,
                     })
                 } else {
                     Err(Error::new(ENOENT))
@@ -123,10 +333,11 @@ impl KScheme for DisplayScheme {
             }
         } else {
             let console = unsafe { & *::env().console.get() };
-            if let Some(ref display) = console.display {
+            if console.display.is_some() {
                 Ok(box DisplayResource {
-                    path: format!("display:{}/{}", display.width, display.height),
                     seek: 0,
+                    damage: damage,
+                    flags: flags & O_NONBLOCK,
                 })
             } else {
                 Err(Error::new(ENOENT))
@@ -1,6 +1,6 @@
 use alloc::boxed::Box;
 use arch::context::EnvVar;
-use collections::string::String;
+use collections::string::{String, ToString};
 use core::cmp::min;
 use fs::resource::ResourceSeek;
 use fs::{KScheme, Resource};
@@ -28,6 +28,23 @@ impl KScheme for EnvScheme {
         }
     }
 
+    fn rename(&mut self, old: &str, new: &str) -> Result<()> {
+        let old_name = old.splitn(2, ":").nth(1).unwrap_or("");
+        let new_name = new.splitn(2, ":").nth(1).unwrap_or("");
+        if new_name.is_empty() || new_name.contains('=') {
+            return Err(Error::new(EINVAL));
+        }
+
+        let contexts = unsafe { &mut *::env().contexts.get() };
+        let current = try!(contexts.current_mut());
+        let value = try!(current.get_env_var(old_name)).to_string();
+        try!(current.set_env_var(new_name, &value));
+        if old_name != new_name {
+            try!(current.remove_env_var(old_name));
+        }
+        Ok(())
+    }
+
     fn unlink(&mut self, url: &str) -> Result<()> {
         let name = url.splitn(2, ":").nth(1).unwrap_or("");
         let contexts = unsafe { & *::env().contexts.get() };
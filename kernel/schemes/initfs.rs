@@ -1,13 +1,15 @@
 use alloc::boxed::Box;
 
-use collections::{BTreeMap, String};
+use collections::{BTreeMap, String, Vec};
 
 use core::cmp::{min, max};
 
-use fs::{KScheme, Resource, ResourceSeek, VecResource};
+use common::time::Duration;
+
+use fs::{DirEntry, DirResource, KScheme, Resource, ResourceSeek, stat_dev, stat_times};
 
 use system::error::{Error, Result, ENOENT};
-use system::syscall::{MODE_DIR, MODE_FILE, Stat};
+use system::syscall::{DT_DIR, DT_REG, MODE_FILE, Stat};
 
 #[path="../../build/initfs.gen"]
 pub mod gen;
@@ -17,14 +19,20 @@ pub struct InitFsResource {
     path: String,
     data: &'static [u8],
     seek: usize,
+    /// Inode number
+    ino: u64,
+    /// Time the filesystem was loaded
+    time: Duration,
 }
 
 impl InitFsResource {
-    pub fn new(path: String, data: &'static [u8]) -> Self {
+    pub fn new(path: String, data: &'static [u8], ino: u64, time: Duration) -> Self {
         InitFsResource {
             path: path,
             data: data,
             seek: 0,
+            ino: ino,
+            time: time,
         }
     }
 }
@@ -35,6 +43,8 @@ impl Resource for InitFsResource {
             path: self.path.clone(),
             data: self.data,
             seek: self.seek,
+            ino: self.ino,
+            time: self.time,
         })
     }
 
@@ -78,8 +88,14 @@ impl Resource for InitFsResource {
     }
 
     fn stat(&self, stat: &mut Stat) -> Result<()> {
-        stat.st_size = self.data.len() as u32;
-        stat.st_mode = MODE_FILE;
+        stat.st_dev = stat_dev("initfs");
+        stat.st_ino = self.ino;
+        stat.st_mode = MODE_FILE | 0o555;
+        stat.st_nlink = 1;
+        stat.st_size = self.data.len() as u64;
+        stat.st_blksize = 4096;
+        stat.st_blocks = (self.data.len() as u64 + 511) / 512;
+        stat_times(stat, self.time, self.time);
         Ok(())
     }
 
@@ -90,13 +106,16 @@ impl Resource for InitFsResource {
 
 /// A memory scheme
 pub struct InitFsScheme {
-    pub files: BTreeMap<&'static str, &'static [u8]>
+    pub files: BTreeMap<&'static str, &'static [u8]>,
+    /// Time the filesystem was loaded, reported as the time of all files
+    time: Duration,
 }
 
 impl InitFsScheme {
     pub fn new() -> Box<InitFsScheme> {
         Box::new(InitFsScheme {
-            files: gen::gen()
+            files: gen::gen(),
+            time: Duration::realtime(),
         })
     }
 }
@@ -110,11 +129,12 @@ impl KScheme for InitFsScheme {
         let reference = url.splitn(2, ":").nth(1).unwrap_or("").trim_matches('/');
 
         if let Some(data) = self.files.get(reference) {
-            Ok(box InitFsResource::new(format!("initfs:/{}", reference), data))
+            let ino = self.files.keys().position(|&name| name == reference).unwrap_or(0) as u64 + 1;
+            Ok(box InitFsResource::new(format!("initfs:/{}", reference), data, ino, self.time))
         } else {
-            let mut list = String::new();
+            let mut entries: Vec<DirEntry> = Vec::new();
 
-            'files: for file in self.files.iter() {
+            'files: for (i, file) in self.files.iter().enumerate() {
                 let mut file_parts = file.0.split('/');
 
                 if ! reference.is_empty() {
@@ -132,23 +152,24 @@ impl KScheme for InitFsScheme {
                 }
 
                 if let Some(file_part) = file_parts.next() {
-                    for item in list.split('\n') {
-                        if item == file_part {
+                    for entry in entries.iter() {
+                        if entry.name == file_part {
                             continue 'files;
                         }
                     }
-                    if ! list.is_empty() {
-                        list.push('\n');
+                    if file_parts.next().is_some() {
+                        entries.push(DirEntry::new(file_part, DT_DIR, 0));
+                    } else {
+                        entries.push(DirEntry::new(file_part, DT_REG, i as u64 + 1));
                     }
-                    list.push_str(file_part);
                 }
             }
 
-            if ! list.is_empty() {
+            if ! entries.is_empty() {
                 if ! reference.is_empty() {
-                    Ok(box VecResource::new(format!("initfs:/{}/", reference), list.into_bytes(), MODE_DIR))
+                    Ok(box DirResource::new(format!("initfs:/{}/", reference), entries))
                 } else {
-                    Ok(box VecResource::new(format!("initfs:/"), list.into_bytes(), MODE_DIR))
+                    Ok(box DirResource::new(format!("initfs:/"), entries))
                 }
             } else {
                 Err(Error::new(ENOENT))
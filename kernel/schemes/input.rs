@@ -0,0 +1,121 @@
+use alloc::arc::Arc;
+use alloc::boxed::Box;
+
+use collections::String;
+use collections::string::ToString;
+
+use core::ptr;
+use core::mem::size_of;
+
+use fs::{DirEntry, DirResource, KScheme, Resource, fcntl_flags};
+
+use sync::WaitQueue;
+
+use system::error::{Error, Result, EAGAIN, EINVAL, ENOENT};
+use system::input::InputEvent;
+use system::syscall::{DT_CHR, O_NONBLOCK};
+
+/// A reader of input events
+pub struct InputResource {
+    /// Path
+    path: String,
+    /// Events delivered to this reader
+    queue: Arc<WaitQueue<InputEvent>>,
+    /// Status flags
+    flags: usize,
+}
+
+impl Resource for InputResource {
+    fn dup(&self) -> Result<Box<Resource>> {
+        Ok(box InputResource {
+            path: self.path.clone(),
+            queue: self.queue.clone(),
+            flags: self.flags,
+        })
+    }
+
+    fn path(&self, buf: &mut [u8]) -> Result<usize> {
+        let path = self.path.as_bytes();
+
+        let mut i = 0;
+        while i < buf.len() && i < path.len() {
+            buf[i] = path[i];
+            i += 1;
+        }
+
+        Ok(i)
+    }
+
+    /// Read events, blocking until at least one is available unless `O_NONBLOCK` is set
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.len() >= size_of::<InputEvent>() {
+            if self.flags & O_NONBLOCK == O_NONBLOCK && unsafe { self.queue.inner() }.is_empty() {
+                return Err(Error::new(EAGAIN));
+            }
+
+            let event = self.queue.receive("InputResource::read");
+            unsafe { ptr::write(buf.as_mut_ptr() as *mut InputEvent, event) };
+            let mut i = size_of::<InputEvent>();
+
+            while i + size_of::<InputEvent>() <= buf.len() {
+                if let Some(event) = unsafe { self.queue.inner() }.pop_front() {
+                    unsafe { ptr::write(buf.as_mut_ptr().offset(i as isize) as *mut InputEvent, event) };
+                    i += size_of::<InputEvent>();
+                } else {
+                    break;
+                }
+            }
+
+            Ok(i)
+        } else {
+            Err(Error::new(EINVAL))
+        }
+    }
+
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        fcntl_flags(&mut self.flags, O_NONBLOCK, cmd, arg)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Input scheme
+///
+/// `input:/events` receives the events of every device, as well as hotplug announcements.
+/// `input:/<device>` receives the events of one device. Any number of readers may be open at once.
+pub struct InputScheme;
+
+impl KScheme for InputScheme {
+    fn scheme(&self) -> &str {
+        "input"
+    }
+
+    fn open(&mut self, url: &str, flags: usize) -> Result<Box<Resource>> {
+        let path = url.splitn(2, ":").nth(1).unwrap_or("").trim_matches('/');
+        let input = unsafe { &mut *::env().input.get() };
+
+        if path.is_empty() {
+            let mut entries = vec![DirEntry::new("events", DT_CHR, 0)];
+            for device in input.devices.iter() {
+                entries.push(DirEntry::new(&device.name, DT_CHR, 0));
+            }
+
+            Ok(box DirResource::new("input:/".to_string(), entries))
+        } else if path == "events" {
+            Ok(box InputResource {
+                path: "input:/events".to_string(),
+                queue: input.reader(None),
+                flags: flags & O_NONBLOCK,
+            })
+        } else {
+            let id = try!(input.find(path).map(|device| device.id).ok_or(Error::new(ENOENT)));
+            Ok(box InputResource {
+                path: format!("input:/{}", path),
+                queue: input.reader(Some(id)),
+                flags: flags & O_NONBLOCK,
+            })
+        }
+    }
+}
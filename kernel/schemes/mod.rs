@@ -6,6 +6,8 @@ pub mod disk;
 pub mod display;
 /// Environment variables scheme
 pub mod env;
+/// Input devices
+pub mod input;
 /// Init Filesystem
 pub mod initfs;
 /// Pipes
@@ -14,3 +16,7 @@ pub mod pipe;
 pub mod pty;
 /// Sys scheme
 pub mod sys;
+/// Temporary filesystem
+pub mod tmp;
+/// Change notifications
+pub mod watch;
@@ -3,21 +3,46 @@ use alloc::boxed::Box;
 
 use core::cmp;
 
-use fs::Resource;
+use common::time::Duration;
 
-use sync::WaitQueue;
+use fs::{Resource, fcntl_flags, stat_dev, stat_times};
 
-use system::error::{Error, Result, EPIPE};
+use sync::{WaitCondition, WaitQueue};
+
+use system::error::{Error, Result, EAGAIN, EPIPE};
+use system::syscall::{MODE_FIFO, Stat, O_NONBLOCK};
+
+/// The most bytes a pipe holds, after which writes wait for them to be read
+const PIPE_SIZE: usize = 65536;
+
+/// Fill in the stat of either side of a pipe
+fn pipe_stat(stat: &mut Stat, buffered: usize, time: Duration) {
+    stat.st_dev = stat_dev("pipe");
+    stat.st_mode = MODE_FIFO | 0o600;
+    stat.st_nlink = 1;
+    stat.st_size = buffered as u64;
+    stat.st_blksize = 4096;
+    stat_times(stat, time, time);
+}
 
 /// Read side of a pipe
 pub struct PipeRead {
-    vec: Arc<WaitQueue<u8>>
+    vec: Arc<WaitQueue<u8>>,
+    /// Notified when bytes are read, and when the read side is closed
+    space: Arc<WaitCondition>,
+    /// Time the pipe was created
+    time: Duration,
+    /// Status flags
+    flags: usize,
 }
 
 impl PipeRead {
     pub fn new() -> Self {
         PipeRead {
-            vec: Arc::new(WaitQueue::new())
+            vec: Arc::new(WaitQueue::new()),
+            space: Arc::new(WaitCondition::new()),
+            time: Duration::realtime(),
+            flags: 0,
         }
     }
 }
@@ -26,6 +51,9 @@ impl Resource for PipeRead {
     fn dup(&self) -> Result<Box<Resource>> {
         Ok(box PipeRead {
             vec: self.vec.clone(),
+            space: self.space.clone(),
+            time: self.time,
+            flags: self.flags,
         })
     }
 
@@ -42,6 +70,8 @@ impl Resource for PipeRead {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         if Arc::weak_count(&self.vec) == 0 && unsafe { self.vec.inner() }.is_empty() {
             Ok(0)
+        } else if self.flags & O_NONBLOCK == O_NONBLOCK && unsafe { self.vec.inner() }.is_empty() {
+            Err(Error::new(EAGAIN))
         } else {
             if !buf.is_empty() {
                 buf[0] = self.vec.receive("PipeRead::read");
@@ -59,20 +89,48 @@ impl Resource for PipeRead {
                 }
             }
 
+            self.space.notify("PipeRead::read");
+
             Ok(i)
         }
     }
+
+    fn stat(&self, stat: &mut Stat) -> Result<()> {
+        pipe_stat(stat, unsafe { self.vec.inner() }.len(), self.time);
+        Ok(())
+    }
+
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        fcntl_flags(&mut self.flags, O_NONBLOCK, cmd, arg)
+    }
 }
 
-/// Read side of a pipe
+impl Drop for PipeRead {
+    fn drop(&mut self) {
+        // Writers waiting for space fail with `EPIPE` once the last reader is gone
+        if Arc::strong_count(&self.vec) == 1 {
+            self.space.notify("PipeRead::drop");
+        }
+    }
+}
+
+/// Write side of a pipe
 pub struct PipeWrite {
     vec: Weak<WaitQueue<u8>>,
+    space: Arc<WaitCondition>,
+    /// Time the pipe was created
+    time: Duration,
+    /// Status flags
+    flags: usize,
 }
 
 impl PipeWrite {
     pub fn new(read: &PipeRead) -> Self {
         PipeWrite {
             vec: Arc::downgrade(&read.vec),
+            space: read.space.clone(),
+            time: read.time,
+            flags: 0,
         }
     }
 }
@@ -81,6 +139,9 @@ impl Resource for PipeWrite {
     fn dup(&self) -> Result<Box<Resource>> {
         Ok(box PipeWrite {
             vec: self.vec.clone(),
+            space: self.space.clone(),
+            time: self.time,
+            flags: self.flags,
         })
     }
 
@@ -94,19 +155,54 @@ impl Resource for PipeWrite {
         Ok(cmp::min(buf.len(), path.len()))
     }
 
+    /// Write to the pipe, waiting for bytes to be read while it is full, or writing as many bytes
+    /// as fit if `O_NONBLOCK` is set
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        match self.vec.upgrade() {
-            Some(vec) => {
-                for &b in buf.iter() {
-                    vec.send(b, "PipeWrite::write");
+        let mut i = 0;
+        loop {
+            match self.vec.upgrade() {
+                Some(vec) => {
+                    let inner = unsafe { vec.inner() };
+                    let start = i;
+                    while i < buf.len() && inner.len() < PIPE_SIZE {
+                        inner.push_back(buf[i]);
+                        i += 1;
+                    }
+                    if i > start {
+                        vec.condition.notify("PipeWrite::write");
+                    }
+                },
+                None => return if i == 0 {
+                    Err(Error::new(EPIPE))
+                } else {
+                    Ok(i)
                 }
+            }
+
+            if i == buf.len() {
+                return Ok(i);
+            } else if self.flags & O_NONBLOCK == O_NONBLOCK {
+                return if i == 0 {
+                    Err(Error::new(EAGAIN))
+                } else {
+                    Ok(i)
+                };
+            }
 
-                Ok(buf.len())
-            },
-            None => Err(Error::new(EPIPE))
+            self.space.wait("PipeWrite::write");
         }
     }
 
+    fn stat(&self, stat: &mut Stat) -> Result<()> {
+        let buffered = self.vec.upgrade().map_or(0, |vec| unsafe { vec.inner() }.len());
+        pipe_stat(stat, buffered, self.time);
+        Ok(())
+    }
+
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        fcntl_flags(&mut self.flags, O_NONBLOCK, cmd, arg)
+    }
+
     fn sync(&mut self) -> Result<()> {
         //TODO: Wait until empty
         Ok(())
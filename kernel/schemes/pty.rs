@@ -5,16 +5,20 @@ use collections::{Vec, VecDeque};
 
 use core::cmp;
 
-use fs::{KScheme, Resource};
+use common::time::Duration;
+
+use fs::{KScheme, Resource, fcntl_flags, stat_dev, stat_times};
 
 use sync::WaitQueue;
 
-use system::error::{Error, ENOENT, Result};
+use system::error::{Error, EAGAIN, ENOENT, Result};
+use system::syscall::{MODE_CHR, Stat, O_NONBLOCK};
 
 pub struct Pty {
     id: usize,
     input: WaitQueue<u8>,
-    output: WaitQueue<Vec<u8>>
+    output: WaitQueue<Vec<u8>>,
+    time: Duration,
 }
 
 impl Pty {
@@ -22,9 +26,20 @@ impl Pty {
         Pty {
             id: id,
             input: WaitQueue::new(),
-            output: WaitQueue::new()
+            output: WaitQueue::new(),
+            time: Duration::realtime(),
         }
     }
+
+    /// Fill in the stat of either side of the pseudoterminal
+    fn stat(&self, stat: &mut Stat) {
+        stat.st_dev = stat_dev("pty");
+        stat.st_ino = self.id as u64;
+        stat.st_rdev = self.id as u64;
+        stat.st_mode = MODE_CHR | 0o620;
+        stat.st_nlink = 1;
+        stat_times(stat, self.time, self.time);
+    }
 }
 
 /// Psuedoterminal scheme
@@ -47,7 +62,7 @@ impl KScheme for PtyScheme {
         "pty"
     }
 
-    fn open(&mut self, url: &str, _: usize) -> Result<Box<Resource>> {
+    fn open(&mut self, url: &str, flags: usize) -> Result<Box<Resource>> {
         let req_id = url.splitn(2, ":").nth(1).unwrap_or("").parse::<usize>().unwrap_or(0);
 
         self.ptys.retain(|pty| {
@@ -55,7 +70,8 @@ impl KScheme for PtyScheme {
         });
 
         if req_id == 0 {
-            let master = PtyMaster::new(self.next_id);
+            let mut master = PtyMaster::new(self.next_id);
+            master.flags = flags & O_NONBLOCK;
 
             self.ptys.push_back(Arc::downgrade(&master.inner));
 
@@ -67,7 +83,9 @@ impl KScheme for PtyScheme {
             for pty in self.ptys.iter() {
                 if let Some(pty_strong) = pty.upgrade() {
                     if pty_strong.id == req_id {
-                        return Ok(Box::new(PtySlave::new(&pty_strong)))
+                        let mut slave = PtySlave::new(&pty_strong);
+                        slave.flags = flags & O_NONBLOCK;
+                        return Ok(Box::new(slave));
                     }
                 }
             }
@@ -79,13 +97,16 @@ impl KScheme for PtyScheme {
 
 /// Psuedoterminal master
 pub struct PtyMaster {
-    inner: Arc<Pty>
+    inner: Arc<Pty>,
+    /// Status flags
+    flags: usize,
 }
 
 impl PtyMaster {
     pub fn new(id: usize) -> Self {
         PtyMaster {
-            inner: Arc::new(Pty::new(id))
+            inner: Arc::new(Pty::new(id)),
+            flags: 0,
         }
     }
 }
@@ -93,7 +114,8 @@ impl PtyMaster {
 impl Resource for PtyMaster {
     fn dup(&self) -> Result<Box<Resource>> {
         Ok(box PtyMaster {
-            inner: self.inner.clone()
+            inner: self.inner.clone(),
+            flags: self.flags,
         })
     }
 
@@ -108,6 +130,10 @@ impl Resource for PtyMaster {
     }
 
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if self.flags & O_NONBLOCK == O_NONBLOCK && unsafe { self.inner.output.inner() }.is_empty() {
+            return Err(Error::new(EAGAIN));
+        }
+
         let packet = self.inner.output.receive("PtyMaster::read");
 
         let mut i = 0;
@@ -127,17 +153,29 @@ impl Resource for PtyMaster {
 
         Ok(buf.len())
     }
+
+    fn stat(&self, stat: &mut Stat) -> Result<()> {
+        self.inner.stat(stat);
+        Ok(())
+    }
+
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        fcntl_flags(&mut self.flags, O_NONBLOCK, cmd, arg)
+    }
 }
 
 /// Psuedoterminal slave
 pub struct PtySlave {
-    inner: Weak<Pty>
+    inner: Weak<Pty>,
+    /// Status flags
+    flags: usize,
 }
 
 impl PtySlave {
     pub fn new(pty: &Arc<Pty>) -> Self {
         PtySlave {
-            inner: Arc::downgrade(&pty)
+            inner: Arc::downgrade(&pty),
+            flags: 0,
         }
     }
 }
@@ -145,7 +183,8 @@ impl PtySlave {
 impl Resource for PtySlave {
     fn dup(&self) -> Result<Box<Resource>> {
         Ok(box PtySlave {
-            inner: self.inner.clone()
+            inner: self.inner.clone(),
+            flags: self.flags,
         })
     }
 
@@ -167,6 +206,10 @@ impl Resource for PtySlave {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         match self.inner.upgrade() {
             Some(inner) => {
+                if self.flags & O_NONBLOCK == O_NONBLOCK && unsafe { inner.input.inner() }.is_empty() {
+                    return Err(Error::new(EAGAIN));
+                }
+
                 if ! buf.is_empty() {
                     buf[0] = inner.input.receive("PtySlave::read");
                 }
@@ -204,6 +247,17 @@ impl Resource for PtySlave {
         }
     }
 
+    fn stat(&self, stat: &mut Stat) -> Result<()> {
+        if let Some(inner) = self.inner.upgrade() {
+            inner.stat(stat);
+        }
+        Ok(())
+    }
+
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        fcntl_flags(&mut self.flags, O_NONBLOCK, cmd, arg)
+    }
+
     fn sync(&mut self) -> Result<()> {
         if let Some(inner) = self.inner.upgrade() {
             inner.output.send(vec![1], "PtySlave::sync");
@@ -9,13 +9,26 @@ use fs::{Resource, VecResource};
 use system::error::Result;
 use system::syscall::MODE_FILE;
 
+fn size_string(size: usize) -> String {
+    if size >= 1024 * 1024 * 1024 {
+        format!("{} GB", size / 1024 / 1024 / 1024)
+    } else if size >= 1024 * 1024 {
+        format!("{} MB", size / 1024 / 1024)
+    } else if size >= 1024 {
+        format!("{} KB", size / 1024)
+    } else {
+        format!("{} B", size)
+    }
+}
+
 pub fn resource() -> Result<Box<Resource>> {
-    let mut string = format!("{:<6}{:<6}{:<10}{:<10}{:<8}{:<6}{:<6}{:<6}{}\n",
+    let mut string = format!("{:<6}{:<6}{:<10}{:<10}{:<8}{:<8}{:<6}{:<6}{:<6}{}\n",
                              "PID",
                              "PPID",
                              "SWITCH",
                              "TIME",
                              "MEM",
+                             "STACK",
                              "FDS",
                              "FLAG",
                              "IOPL",
@@ -28,20 +41,18 @@ pub fn resource() -> Result<Box<Resource>> {
                 memory += context::CONTEXT_STACK_SIZE;
             }
             if let Some(ref stack) = context.stack {
-                memory += stack.virtual_size;
+                memory += stack.size();
             }
             memory += unsafe { (*context.image.get()).size() };
             memory += unsafe { (*context.heap.get()).size() };
             memory += unsafe { (*context.mmap.get()).size() };
 
-            let memory_string = if memory >= 1024 * 1024 * 1024 {
-                format!("{} GB", memory / 1024 / 1024 / 1024)
-            } else if memory >= 1024 * 1024 {
-                format!("{} MB", memory / 1024 / 1024)
-            } else if memory >= 1024 {
-                format!("{} KB", memory / 1024)
-            } else {
-                format!("{} B", memory)
+            let memory_string = size_string(memory);
+
+            // The part of the user stack that has been used, which is what is allocated for it
+            let stack_string = match context.stack {
+                Some(ref stack) => size_string(stack.size()),
+                None => "-".to_string()
             };
 
             let mut flags_string = String::new();
@@ -66,12 +77,13 @@ pub fn resource() -> Result<Box<Resource>> {
                 flags_string.push('T');
             }
 
-            string.push_str(&format!("{:<6}{:<6}{:<10}{:<10}{:<8}{:<6}{:<6}{:<6}{}\n",
+            string.push_str(&format!("{:<6}{:<6}{:<10}{:<10}{:<8}{:<8}{:<6}{:<6}{:<6}{}\n",
                                context.pid,
                                context.ppid,
                                context.switch,
                                context.time,
                                memory_string,
+                               stack_string,
                                unsafe { (*context.files.get()).len() },
                                flags_string,
                                context.iopl,
@@ -1,17 +1,20 @@
 use alloc::boxed::Box;
 
-use collections::{BTreeMap, String};
+use collections::{BTreeMap, Vec};
 
-use fs::{KScheme, Resource, VecResource};
+use fs::{DirEntry, DirResource, KScheme, Resource};
 
 use system::error::{Error, ENOENT, Result};
-use system::syscall::MODE_DIR;
+use system::syscall::{DT_DIR, DT_REG};
 
 mod context;
 mod disk;
 mod interrupt;
 mod log;
 mod memory;
+mod mounts;
+mod scheme_policy;
+mod schemes;
 mod test;
 
 /// System information scheme
@@ -28,6 +31,9 @@ impl SysScheme {
         files.insert("interrupt", box move || interrupt::resource());
         files.insert("log", box move || log::resource());
         files.insert("memory", box move || memory::resource());
+        files.insert("mounts", box move || mounts::resource());
+        files.insert("scheme_policy", box move || scheme_policy::resource());
+        files.insert("schemes", box move || schemes::resource());
         files.insert("test", box move || test::resource());
 
         Box::new(SysScheme {
@@ -47,7 +53,7 @@ impl KScheme for SysScheme {
         if let Some(func) = self.files.get(reference) {
             func()
         } else {
-            let mut list = String::new();
+            let mut entries: Vec<DirEntry> = Vec::new();
 
             'files: for file in self.files.iter() {
                 let mut file_parts = file.0.split('/');
@@ -67,23 +73,24 @@ impl KScheme for SysScheme {
                 }
 
                 if let Some(file_part) = file_parts.next() {
-                    for item in list.split('\n') {
-                        if item == file_part {
+                    for entry in entries.iter() {
+                        if entry.name == file_part {
                             continue 'files;
                         }
                     }
-                    if ! list.is_empty() {
-                        list.push('\n');
+                    if file_parts.next().is_some() {
+                        entries.push(DirEntry::new(file_part, DT_DIR, 0));
+                    } else {
+                        entries.push(DirEntry::new(file_part, DT_REG, 0));
                     }
-                    list.push_str(file_part);
                 }
             }
 
-            if ! list.is_empty() {
+            if ! entries.is_empty() {
                 if ! reference.is_empty() {
-                    Ok(box VecResource::new(format!("sys:/{}/", reference), list.into_bytes(), MODE_DIR))
+                    Ok(box DirResource::new(format!("sys:/{}/", reference), entries))
                 } else {
-                    Ok(box VecResource::new(format!("sys:/"), list.into_bytes(), MODE_DIR))
+                    Ok(box DirResource::new(format!("sys:/"), entries))
                 }
             } else {
                 Err(Error::new(ENOENT))
@@ -0,0 +1,21 @@
+use alloc::boxed::Box;
+
+use collections::string::ToString;
+
+use fs::{Resource, VecResource};
+
+use system::error::Result;
+use system::syscall::MODE_FILE;
+
+pub fn resource() -> Result<Box<Resource>> {
+    let mut string = format!("{:<32}{}\n", "PATH", "TARGET");
+
+    {
+        let mounts = unsafe { & *::env().mounts.get() };
+        for mount in mounts.mounts.iter() {
+            string.push_str(&format!("{:<32}{}\n", mount.prefix, mount.target));
+        }
+    }
+
+    Ok(box VecResource::new("sys:/mounts".to_string(), string.into_bytes(), MODE_FILE))
+}
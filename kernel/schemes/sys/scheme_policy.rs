@@ -0,0 +1,99 @@
+use alloc::boxed::Box;
+
+use collections::String;
+use collections::borrow::ToOwned;
+
+use core::cmp::min;
+use core::str;
+
+use fs::Resource;
+
+use system::error::{Error, Result, EINVAL, EPERM};
+
+/// The process that may change the scheme policy, which is init
+const POLICY_PID: usize = 1;
+
+pub fn resource() -> Result<Box<Resource>> {
+    Ok(box SchemePolicyResource {
+        pos: 0
+    })
+}
+
+/// The scheme names reserved for programs
+///
+/// Reading lists the reservations, as a name and the url of a program per line. Writing lines of
+/// the same form reserves a name for a program, so that only a process running that program may
+/// register it, and a line with just a name removes its reservation. Only init may write.
+pub struct SchemePolicyResource {
+    pos: usize
+}
+
+impl SchemePolicyResource {
+    fn list(&self) -> String {
+        let mut string = format!("{:<16}{}\n", "NAME", "PROGRAM");
+        for (name, program) in unsafe { & *::env().scheme_reservations.get() }.iter() {
+            string.push_str(&format!("{:<16}{}\n", name, program));
+        }
+        string
+    }
+}
+
+impl Resource for SchemePolicyResource {
+    fn dup(&self) -> Result<Box<Resource>> {
+        Ok(box SchemePolicyResource {
+            pos: self.pos
+        })
+    }
+
+    fn path(&self, buf: &mut [u8]) -> Result<usize> {
+        let path = b"sys:/scheme_policy";
+        let count = min(buf.len(), path.len());
+        buf[..count].copy_from_slice(&path[..count]);
+        Ok(count)
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let list = self.list();
+        let data = list.as_bytes();
+        if self.pos >= data.len() {
+            return Ok(0);
+        }
+
+        let count = min(buf.len(), data.len() - self.pos);
+        buf[..count].copy_from_slice(&data[self.pos..self.pos + count]);
+        self.pos += count;
+        Ok(count)
+    }
+
+    /// Each write is taken as whole lines
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let contexts = unsafe { & *::env().contexts.get() };
+        if try!(contexts.current()).pid != POLICY_PID {
+            return Err(Error::new(EPERM));
+        }
+
+        let text = try!(str::from_utf8(buf).or(Err(Error::new(EINVAL))));
+        let reservations = unsafe { &mut *::env().scheme_reservations.get() };
+        for line in text.lines() {
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(name), Some(program), None) => {
+                    if name.contains('/') || name.contains(':') {
+                        return Err(Error::new(EINVAL));
+                    }
+                    reservations.insert(name.to_owned(), program.to_owned());
+                },
+                (Some(name), None, None) => {
+                    reservations.remove(name);
+                },
+                (None, _, _) => (),
+                _ => return Err(Error::new(EINVAL))
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
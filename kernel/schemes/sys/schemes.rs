@@ -0,0 +1,35 @@
+use alloc::boxed::Box;
+
+use collections::string::{String, ToString};
+
+use fs::{Resource, VecResource};
+
+use system::error::Result;
+use system::syscall::MODE_FILE;
+
+pub fn resource() -> Result<Box<Resource>> {
+    let mut string = format!("{:<6}{:<8}{:<8}{:<8}{}\n",
+                             "PID",
+                             "KIND",
+                             "OPEN",
+                             "PENDING",
+                             "NAME");
+
+    {
+        let schemes = unsafe { & *::env().schemes.get() };
+        for scheme in schemes.iter() {
+            let (pid, kind) = match scheme.owner() {
+                Some(pid) => (pid.to_string(), "user"),
+                None => ("-".to_string(), "kernel"),
+            };
+            let (open, pending): (String, String) = match scheme.usage() {
+                Some((open, pending)) => (open.to_string(), pending.to_string()),
+                None => ("-".to_string(), "-".to_string()),
+            };
+
+            string.push_str(&format!("{:<6}{:<8}{:<8}{:<8}{}\n", pid, kind, open, pending, scheme.scheme()));
+        }
+    }
+
+    Ok(box VecResource::new("sys:/schemes".to_string(), string.into_bytes(), MODE_FILE))
+}
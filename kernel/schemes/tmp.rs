@@ -0,0 +1,413 @@
+use alloc::arc::Arc;
+use alloc::boxed::Box;
+
+use collections::{BTreeMap, String, Vec};
+use collections::borrow::ToOwned;
+
+use core::cell::UnsafeCell;
+use core::cmp::{max, min};
+
+use common::time::Duration;
+
+use fs::{DirEntry, DirResource, KScheme, Resource, ResourceSeek, fcntl_flags, stat_dev, stat_times};
+
+use system::error::{Error, Result, EEXIST, EFBIG, EINVAL, EISDIR, ENOENT, ENOSPC, ENOTDIR, ENOTEMPTY};
+use system::syscall::{DT_DIR, DT_REG, MODE_DIR, MODE_FILE, MODE_PERM, MODE_TYPE, Stat,
+                      O_APPEND, O_CREAT, O_EXCL, O_NONBLOCK, O_RDWR, O_TRUNC, O_WRONLY};
+use system::watch::{WATCH_CREATE, WATCH_DELETE, WATCH_MODIFY, WATCH_RENAME_FROM, WATCH_RENAME_TO};
+
+/// The most bytes the files of the temporary filesystem can hold together
+const TMP_LIMIT: usize = 64 * 1024 * 1024;
+
+/// The space used by the temporary filesystem, shared by its nodes
+struct TmpSpace {
+    used: usize,
+    limit: usize,
+}
+
+impl TmpSpace {
+    /// Account for a file changing size, failing with `ENOSPC` if it would exceed the limit
+    fn resize(&mut self, old: usize, new: usize) -> Result<()> {
+        if new > old {
+            if new - old > self.limit - self.used {
+                return Err(Error::new(ENOSPC));
+            }
+            self.used += new - old;
+        } else {
+            self.used -= old - new;
+        }
+        Ok(())
+    }
+}
+
+/// A file or directory of the temporary filesystem
+struct TmpNode {
+    ino: u64,
+    mode: u16,
+    data: Vec<u8>,
+    created: Duration,
+    modified: Duration,
+    space: Arc<UnsafeCell<TmpSpace>>,
+}
+
+impl TmpNode {
+    fn new(ino: u64, mode: u16, space: Arc<UnsafeCell<TmpSpace>>) -> TmpNode {
+        let time = Duration::realtime();
+        TmpNode {
+            ino: ino,
+            mode: mode,
+            data: Vec::new(),
+            created: time,
+            modified: time,
+            space: space,
+        }
+    }
+
+    /// Change the length of the data, zero filling it as needed
+    fn resize(&mut self, len: usize) -> Result<()> {
+        try!(unsafe { &mut *self.space.get() }.resize(self.data.len(), len));
+        if len > self.data.len() {
+            let extra = len - self.data.len();
+            self.data.extend((0..extra).map(|_| 0));
+        } else {
+            self.data.truncate(len);
+        }
+        self.modified = Duration::realtime();
+        Ok(())
+    }
+}
+
+impl Drop for TmpNode {
+    fn drop(&mut self) {
+        // Unlinked files hold their space until they are no longer open
+        let _ = unsafe { &mut *self.space.get() }.resize(self.data.len(), 0);
+    }
+}
+
+/// Tell the watchers of a path of a change to it
+fn notify(kind: u16, path: &str) {
+    let watches = unsafe { &mut *::env().watches.get() };
+    if watches.watching() {
+        watches.notify(kind, &format!("tmp:/{}", path));
+    }
+}
+
+/// An open file of the temporary filesystem
+pub struct TmpResource {
+    path: String,
+    node: Arc<UnsafeCell<TmpNode>>,
+    seek: usize,
+    /// Status flags
+    flags: usize,
+}
+
+impl Resource for TmpResource {
+    fn dup(&self) -> Result<Box<Resource>> {
+        Ok(box TmpResource {
+            path: self.path.clone(),
+            node: self.node.clone(),
+            seek: self.seek,
+            flags: self.flags,
+        })
+    }
+
+    fn path(&self, buf: &mut [u8]) -> Result<usize> {
+        let path = self.path.as_bytes();
+        let count = min(buf.len(), path.len());
+        buf[..count].copy_from_slice(&path[..count]);
+        Ok(count)
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let node = unsafe { & *self.node.get() };
+        if self.seek >= node.data.len() {
+            return Ok(0);
+        }
+
+        let count = min(buf.len(), node.data.len() - self.seek);
+        buf[..count].copy_from_slice(&node.data[self.seek..self.seek + count]);
+        self.seek += count;
+        Ok(count)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let node = unsafe { &mut *self.node.get() };
+        if self.flags & O_APPEND == O_APPEND {
+            self.seek = node.data.len();
+        }
+
+        let end = match self.seek.checked_add(buf.len()) {
+            Some(end) => end,
+            None => return Err(Error::new(EFBIG))
+        };
+        if end > node.data.len() {
+            try!(node.resize(end));
+        }
+
+        node.data[self.seek..end].copy_from_slice(buf);
+        node.modified = Duration::realtime();
+        self.seek = end;
+        notify(WATCH_MODIFY, tmp_path(&self.path));
+        Ok(buf.len())
+    }
+
+    /// Seeking past `TMP_LIMIT` fails with `EINVAL`, as no file can be written there
+    fn seek(&mut self, pos: ResourceSeek) -> Result<usize> {
+        let len = unsafe { & *self.node.get() }.data.len() as isize;
+        let seek = match pos {
+            ResourceSeek::Start(offset) => offset,
+            ResourceSeek::Current(offset) => max(0, (self.seek as isize).saturating_add(offset)) as usize,
+            ResourceSeek::End(offset) => max(0, len.saturating_add(offset)) as usize,
+        };
+        if seek > TMP_LIMIT {
+            return Err(Error::new(EINVAL));
+        }
+        self.seek = seek;
+        Ok(self.seek)
+    }
+
+    fn stat(&self, stat: &mut Stat) -> Result<()> {
+        let node = unsafe { & *self.node.get() };
+        stat.st_dev = stat_dev("tmp");
+        stat.st_ino = node.ino;
+        stat.st_mode = node.mode;
+        stat.st_nlink = 1;
+        stat.st_size = node.data.len() as u64;
+        stat.st_blksize = 4096;
+        stat.st_blocks = (node.data.len() as u64 + 511) / 512;
+        stat_times(stat, node.created, node.modified);
+        Ok(())
+    }
+
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        fcntl_flags(&mut self.flags, O_APPEND | O_NONBLOCK, cmd, arg)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn truncate(&mut self, len: usize) -> Result<()> {
+        try!(unsafe { &mut *self.node.get() }.resize(len));
+        notify(WATCH_MODIFY, tmp_path(&self.path));
+        Ok(())
+    }
+}
+
+/// A writable filesystem kept in memory, which is empty on boot
+pub struct TmpScheme {
+    /// The nodes by path, relative to the root, which is the empty path
+    nodes: BTreeMap<String, Arc<UnsafeCell<TmpNode>>>,
+    space: Arc<UnsafeCell<TmpSpace>>,
+    next_ino: u64,
+}
+
+impl TmpScheme {
+    pub fn new() -> Box<TmpScheme> {
+        let space = Arc::new(UnsafeCell::new(TmpSpace {
+            used: 0,
+            limit: TMP_LIMIT,
+        }));
+
+        let mut nodes = BTreeMap::new();
+        nodes.insert(String::new(), Arc::new(UnsafeCell::new(TmpNode::new(1, MODE_DIR | 0o1777, space.clone()))));
+
+        Box::new(TmpScheme {
+            nodes: nodes,
+            space: space,
+            next_ino: 2,
+        })
+    }
+
+    /// Create a node, the parent of which must be an existing directory
+    fn create(&mut self, path: &str, mode: u16) -> Result<Arc<UnsafeCell<TmpNode>>> {
+        if self.nodes.contains_key(path) {
+            return Err(Error::new(EEXIST));
+        }
+
+        let parent = path.rfind('/').map_or("", |i| &path[..i]);
+        match self.nodes.get(parent) {
+            Some(node) => if unsafe { & *node.get() }.mode & MODE_TYPE != MODE_DIR {
+                return Err(Error::new(ENOTDIR));
+            },
+            None => return Err(Error::new(ENOENT))
+        }
+
+        let node = Arc::new(UnsafeCell::new(TmpNode::new(self.next_ino, mode, self.space.clone())));
+        self.next_ino += 1;
+        self.nodes.insert(path.to_owned(), node.clone());
+        Ok(node)
+    }
+
+    /// Get the entries of a directory
+    fn entries(&self, path: &str) -> Vec<DirEntry> {
+        let mut entries = Vec::new();
+        for (name, node) in self.nodes.iter() {
+            let child = if path.is_empty() {
+                name.as_str()
+            } else if name.starts_with(path) && name[path.len()..].starts_with('/') {
+                &name[path.len() + 1..]
+            } else {
+                continue;
+            };
+
+            if ! child.is_empty() && child.find('/').is_none() {
+                let node = unsafe { & *node.get() };
+                let kind = if node.mode & MODE_TYPE == MODE_DIR { DT_DIR } else { DT_REG };
+                entries.push(DirEntry::new(child, kind, node.ino));
+            }
+        }
+        entries
+    }
+}
+
+/// Get the path of a url relative to the root of the scheme
+fn tmp_path(url: &str) -> &str {
+    url.splitn(2, ":").nth(1).unwrap_or("").trim_matches('/')
+}
+
+impl KScheme for TmpScheme {
+    fn scheme(&self) -> &str {
+        "tmp"
+    }
+
+    fn open(&mut self, url: &str, flags: usize) -> Result<Box<Resource>> {
+        let path = tmp_path(url);
+
+        let existing = self.nodes.get(path).cloned();
+        let node = match existing {
+            Some(node) => {
+                if flags & O_CREAT == O_CREAT && flags & O_EXCL == O_EXCL {
+                    return Err(Error::new(EEXIST));
+                }
+                node
+            },
+            None => if flags & O_CREAT == O_CREAT {
+                let node = try!(self.create(path, MODE_FILE | 0o644));
+                notify(WATCH_CREATE, path);
+                node
+            } else {
+                return Err(Error::new(ENOENT));
+            }
+        };
+
+        if unsafe { & *node.get() }.mode & MODE_TYPE == MODE_DIR {
+            let dir_path = if path.is_empty() { "tmp:/".to_owned() } else { format!("tmp:/{}/", path) };
+            return Ok(box DirResource::new(dir_path, self.entries(path)));
+        }
+
+        let writable = flags & (O_WRONLY | O_RDWR) != 0;
+        if writable && flags & O_TRUNC == O_TRUNC && ! unsafe { & *node.get() }.data.is_empty() {
+            try!(unsafe { &mut *node.get() }.resize(0));
+            notify(WATCH_MODIFY, path);
+        }
+
+        Ok(box TmpResource {
+            path: format!("tmp:/{}", path),
+            node: node,
+            seek: 0,
+            flags: flags & O_APPEND,
+        })
+    }
+
+    fn mkdir(&mut self, url: &str, mode: usize) -> Result<()> {
+        let perm = match mode as u16 & MODE_PERM {
+            0 => 0o755,
+            perm => perm,
+        };
+        let path = tmp_path(url);
+        try!(self.create(path, MODE_DIR | perm));
+        notify(WATCH_CREATE, path);
+        Ok(())
+    }
+
+    fn rename(&mut self, old: &str, new: &str) -> Result<()> {
+        let old_path = tmp_path(old).to_owned();
+        let new_path = tmp_path(new).to_owned();
+
+        let node = match self.nodes.get(&old_path) {
+            Some(node) => node.clone(),
+            None => return Err(Error::new(ENOENT))
+        };
+        if old_path.is_empty() || new_path.starts_with(&(old_path.clone() + "/")) {
+            return Err(Error::new(EINVAL));
+        }
+        if old_path == new_path {
+            return Ok(());
+        }
+
+        let is_dir = unsafe { & *node.get() }.mode & MODE_TYPE == MODE_DIR;
+        if let Some(target) = self.nodes.get(&new_path) {
+            let target_is_dir = unsafe { & *target.get() }.mode & MODE_TYPE == MODE_DIR;
+            if is_dir && ! target_is_dir {
+                return Err(Error::new(ENOTDIR));
+            } else if ! is_dir && target_is_dir {
+                return Err(Error::new(EISDIR));
+            } else if target_is_dir && ! self.entries(&new_path).is_empty() {
+                return Err(Error::new(ENOTEMPTY));
+            }
+        }
+
+        let parent = new_path.rfind('/').map_or("", |i| &new_path[..i]);
+        match self.nodes.get(parent) {
+            Some(parent) => if unsafe { & *parent.get() }.mode & MODE_TYPE != MODE_DIR {
+                return Err(Error::new(ENOTDIR));
+            },
+            None => return Err(Error::new(ENOENT))
+        }
+
+        // Move the node, and everything under it if it is a directory
+        let prefix = old_path.clone() + "/";
+        let moved: Vec<String> = self.nodes.keys()
+            .filter(|path| **path == old_path || path.starts_with(&prefix))
+            .cloned()
+            .collect();
+        for path in moved {
+            if let Some(node) = self.nodes.remove(&path) {
+                let renamed = new_path.clone() + &path[old_path.len()..];
+                self.nodes.insert(renamed, node);
+            }
+        }
+
+        notify(WATCH_RENAME_FROM, &old_path);
+        notify(WATCH_RENAME_TO, &new_path);
+        Ok(())
+    }
+
+    fn rmdir(&mut self, url: &str) -> Result<()> {
+        let path = tmp_path(url);
+        if path.is_empty() {
+            return Err(Error::new(EINVAL));
+        }
+
+        match self.nodes.get(path) {
+            Some(node) => if unsafe { & *node.get() }.mode & MODE_TYPE != MODE_DIR {
+                return Err(Error::new(ENOTDIR));
+            },
+            None => return Err(Error::new(ENOENT))
+        }
+
+        if ! self.entries(path).is_empty() {
+            return Err(Error::new(ENOTEMPTY));
+        }
+
+        self.nodes.remove(path);
+        notify(WATCH_DELETE, path);
+        Ok(())
+    }
+
+    fn unlink(&mut self, url: &str) -> Result<()> {
+        let path = tmp_path(url);
+
+        match self.nodes.get(path) {
+            Some(node) => if unsafe { & *node.get() }.mode & MODE_TYPE == MODE_DIR {
+                return Err(Error::new(EISDIR));
+            },
+            None => return Err(Error::new(ENOENT))
+        }
+
+        self.nodes.remove(path);
+        notify(WATCH_DELETE, path);
+        Ok(())
+    }
+}
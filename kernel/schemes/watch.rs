@@ -0,0 +1,128 @@
+use alloc::arc::Arc;
+use alloc::boxed::Box;
+
+use collections::String;
+
+use core::cmp::min;
+use core::mem::size_of;
+use core::ptr;
+
+use fs::{KScheme, Resource, fcntl_flags};
+use fs::watch::Change;
+
+use sync::WaitQueue;
+
+use system::error::{Error, Result, EAGAIN, EINVAL};
+use system::syscall::{O_NONBLOCK, Stat};
+use system::watch::WatchEvent;
+
+/// A watcher of changes to a path and the paths under it
+pub struct WatchResource {
+    /// Path
+    path: String,
+    /// Changes delivered to this watcher
+    queue: Arc<WaitQueue<Change>>,
+    /// Status flags
+    flags: usize,
+}
+
+impl Resource for WatchResource {
+    fn dup(&self) -> Result<Box<Resource>> {
+        Ok(box WatchResource {
+            path: self.path.clone(),
+            queue: self.queue.clone(),
+            flags: self.flags,
+        })
+    }
+
+    fn path(&self, buf: &mut [u8]) -> Result<usize> {
+        let path = self.path.as_bytes();
+        let count = min(buf.len(), path.len());
+        buf[..count].copy_from_slice(&path[..count]);
+        Ok(count)
+    }
+
+    /// Read changes as `WatchEvent` headers followed by paths, blocking until at least one is
+    /// available unless `O_NONBLOCK` is set
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            let mut i = 0;
+            while let Some(change) = unsafe { self.queue.inner() }.pop_front() {
+                let reclen = size_of::<WatchEvent>() + change.path.len();
+                if i + reclen > buf.len() {
+                    unsafe { self.queue.inner() }.push_front(change);
+                    break;
+                }
+
+                // Paths too long for `reclen` were delivered as `WATCH_OVERFLOW` instead
+                let event = WatchEvent {
+                    kind: change.kind,
+                    reclen: reclen as u16,
+                };
+                unsafe { ptr::write(buf.as_mut_ptr().offset(i as isize) as *mut WatchEvent, event) };
+                buf[i + size_of::<WatchEvent>()..i + reclen].copy_from_slice(change.path.as_bytes());
+                i += reclen;
+            }
+
+            if i > 0 {
+                return Ok(i);
+            } else if ! unsafe { self.queue.inner() }.is_empty() {
+                // The next change does not fit in the buffer
+                return Err(Error::new(EINVAL));
+            } else if self.flags & O_NONBLOCK == O_NONBLOCK {
+                return Err(Error::new(EAGAIN));
+            }
+
+            self.queue.condition.wait("WatchResource::read");
+        }
+    }
+
+    fn stat(&self, stat: &mut Stat) -> Result<()> {
+        stat.st_size = unsafe { self.queue.inner() }.len() as u64;
+        Ok(())
+    }
+
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        fcntl_flags(&mut self.flags, O_NONBLOCK, cmd, arg)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Change notification scheme
+///
+/// `watch:<url>` receives the creation, modification, removal and renaming of `url` and of the
+/// paths under it. The `tmp:` and `disk:` schemes and `sys:/mounts` report their own changes, and
+/// user schemes report theirs with `PACKET_NOTIFY` packets. Any number of watchers may be open
+/// at once, each of which queues at most `WATCH_QUEUE_MAX` changes.
+pub struct WatchScheme;
+
+impl KScheme for WatchScheme {
+    fn scheme(&self) -> &str {
+        "watch"
+    }
+
+    fn open(&mut self, url: &str, flags: usize) -> Result<Box<Resource>> {
+        let target = url.splitn(2, ":").nth(1).unwrap_or("");
+        if target.is_empty() {
+            return Err(Error::new(EINVAL));
+        }
+
+        let contexts = unsafe { & *::env().contexts.get() };
+        let current = try!(contexts.current());
+        let target = current.canonicalize(target);
+
+        // Make sure the target exists, then watch it where it is mounted
+        let mut stat = Stat::default();
+        try!(::env().stat(&target, &mut stat));
+        let target = unsafe { & *::env().mounts.get() }.resolve(&target);
+
+        Ok(box WatchResource {
+            path: format!("watch:{}", target),
+            queue: unsafe { &mut *::env().watches.get() }.watch(&target),
+            flags: flags & O_NONBLOCK,
+        })
+    }
+}
@@ -1,5 +1,6 @@
 use collections::BTreeMap;
 use core::cell::UnsafeCell;
+use common::time::Duration;
 use super::WaitCondition;
 
 pub struct WaitMap<K, V> {
@@ -32,4 +33,19 @@ impl<K, V> WaitMap<K, V> where K: Ord {
             self.condition.wait(reason);
         }
     }
+
+    /// Receive the value of a key, or `None` if it was not sent within `time`
+    pub fn receive_for(&self, key: &K, reason: &str, time: Duration) -> Option<V> {
+        let end = Duration::monotonic() + time;
+        loop {
+            if let Some(value) = unsafe { self.inner() }.remove(key) {
+                return Some(value);
+            }
+
+            let now = Duration::monotonic();
+            if now >= end || ! self.condition.wait_for(reason, end - now) {
+                return unsafe { self.inner() }.remove(key);
+            }
+        }
+    }
 }
@@ -4,100 +4,277 @@ use alloc::arc::Arc;
 
 use arch::context::{CONTEXT_IMAGE_ADDR, CONTEXT_IMAGE_SIZE, CONTEXT_HEAP_ADDR, CONTEXT_HEAP_SIZE,
                     CONTEXT_MMAP_ADDR, CONTEXT_MMAP_SIZE, CONTEXT_STACK_SIZE, CONTEXT_STACK_ADDR,
-                    CONTEXT_TLS_ADDR,
-                    context_switch, context_userspace, Context, ContextMemory, ContextZone};
+                    CONTEXT_TLS_ADDR, CONTEXT_RANDOM_SIZE, CONTEXT_STACK_GROW_SIZE, CONTEXT_STACK_MAX_SIZE,
+                    context_switch, context_userspace, Context, ContextFile, ContextMemory, ContextStack, ContextZone,
+                    EnvVar};
 use arch::gdt::{GDT_USER_CODE, GDT_USER_DATA, GDT_USER_TLS, GdtEntry};
 use arch::elf::Elf;
 use arch::memory;
+use arch::paging;
 use arch::regs::Regs;
 
 use collections::borrow::ToOwned;
 use collections::string::{String, ToString};
 use collections::vec::Vec;
 
+use common::random::{random, random_bytes};
 use common::slice::GetSlice;
 
 use core::cell::UnsafeCell;
 use core::ops::DerefMut;
 use core::{mem, ptr, slice, str};
 
-use system::error::{Error, Result, ENOEXEC, ENOMEM};
-use system::syscall::O_RDONLY;
+use system::auxv::{AT_BASE, AT_ENTRY, AT_NULL, AT_PAGESZ, AT_PHDR, AT_PHENT, AT_PHNUM, AT_RANDOM};
+use system::error::{Error, Result, E2BIG, ENOEXEC, ENOMEM};
+use system::syscall::{FD_CLOEXEC, O_RDONLY, RLIMIT_AS};
 
-pub fn execute_thread(context_ptr: *mut Context, entry: usize, mut args: Vec<String>) -> ! {
-    Context::spawn("kexec".into(),
-                   box move || {
-        let context = unsafe { &mut *context_ptr };
+/// The most space that the arguments and environment of a program may take on its initial stack
+const ARG_MAX: usize = CONTEXT_STACK_SIZE / 4;
+
+/// The space that the strings and pointers of arguments and environment variables take on the
+/// initial stack
+fn args_size(args: &[String], vars: &[EnvVar]) -> usize {
+    let mut size = 0;
+    for arg in args.iter() {
+        size += arg.len() + 1 + mem::size_of::<usize>();
+    }
+    for var in vars.iter() {
+        size += var.name().len() + var.value().len() + 2 + mem::size_of::<usize>();
+    }
+    size
+}
+
+/// Copy a string with a null terminator below `sp` in the physical memory of `stack`, returning
+/// the address of the copy in the context
+unsafe fn push_string(sp: &mut usize, stack: &ContextMemory, string: &[u8]) -> usize {
+    *sp -= string.len() + 1;
+    ::memcpy(*sp as *mut u8, string.as_ptr(), string.len());
+    ptr::write((*sp + string.len()) as *mut u8, 0);
+    *sp - stack.physical_address + stack.virtual_address
+}
+
+/// A random, page aligned offset below `CONTEXT_RANDOM_SIZE`
+fn random_offset() -> usize {
+    (random() % (CONTEXT_RANDOM_SIZE / 4096)) * 4096
+}
+
+/// Read a whole file, using memory in the mmap zone of `current` so that user schemes can write
+/// to it
+fn read_file(current: &mut Context, path: &str) -> Result<Vec<u8>> {
+    let mut vec: Vec<u8> = Vec::new();
+
+    let mut resource = try!(::env().open(path, O_RDONLY));
+
+    // Hack to allow file scheme to find memory in context's memory space
+    unsafe {
+        let mmap = &mut *current.mmap.get();
 
-        let mut context_args: Vec<usize> = Vec::new();
-        context_args.push(0); // ENVP
-        context_args.push(0); // ARGV NULL
-        let mut argc = 0;
-        while let Some(mut arg) = args.pop() {
-            if ! arg.ends_with('\0') {
-                arg.push('\0');
+        let virtual_size = 1024*1024;
+        if ! current.memory_allows(virtual_size) {
+            return Err(Error::new(ENOMEM));
+        }
+
+        let physical_address = memory::alloc_aligned(virtual_size, 4096);
+        if physical_address == 0 {
+            return Err(Error::new(ENOMEM));
+        }
+
+        let virtual_address = try!(mmap.add_mem(physical_address, virtual_size, true, true));
+
+        for i in 0..mmap.memory.len() {
+            if mmap.memory[i].virtual_address == virtual_address {
+                mmap.memory[i].map();
+                break;
             }
+        }
 
-            let mut physical_address = arg.as_ptr() as usize;
-            if physical_address >= 0x80000000 {
-                physical_address -= 0x80000000;
+        let mut read_loop = || -> Result<usize> {
+            loop {
+                let mut bytes = slice::from_raw_parts_mut(virtual_address as *mut u8, virtual_size);
+                match resource.read(&mut bytes) {
+                    Ok(0) => return Ok(0),
+                    Ok(count) => vec.extend_from_slice(bytes.get_slice(.. count)),
+                    Err(err) => return Err(err)
+                }
             }
+        };
 
-            let virtual_size = arg.len();
-            let virtual_address = unsafe { (*context.image.get()).add_mem(physical_address, virtual_size, false, true) }.unwrap();
+        // The buffer is in user memory, which the kernel thread that starts init cannot access
+        // unless it is allowed for the read
+        let allowed = paging::user_access_allowed();
+        paging::allow_user_access();
+        let res = read_loop();
+        if ! allowed {
+            paging::deny_user_access();
+        }
 
-            mem::forget(arg);
+        for i in 0..mmap.memory.len() {
+            if mmap.memory[i].virtual_address == virtual_address {
+                mmap.memory.remove(i).unmap();
+                break;
+            }
+        }
 
-            context_args.push(virtual_address as usize);
-            argc += 1;
+        try!(res);
+    }
+
+    Ok(vec)
+}
+
+/// The end of the loadable segments of an executable, relative to its base
+fn image_end(executable: &Elf) -> usize {
+    unsafe { executable.load_segments() }.iter()
+        .map(|segment| (segment.p_vaddr + segment.p_memsz) as usize)
+        .max()
+        .unwrap_or(0)
+}
+
+/// The memory the loadable segments of an executable take up
+fn image_size(executable: &Elf) -> usize {
+    unsafe { executable.load_segments() }.iter()
+        .filter(|segment| segment.p_type == 1)
+        .fold(0, |size: usize, segment| size.saturating_add(segment.p_memsz as usize).saturating_add(segment.p_vaddr as usize % 4096))
+}
+
+/// Copy the loadable segments of an executable into new memory in `image`, at their addresses
+/// plus `base`, returning the master copy of its TLS segment if it has one
+fn load_image(executable: &Elf, base: usize, image: &mut ContextZone) -> Option<ContextMemory> {
+    let mut tls_master = None;
+
+    for segment in unsafe { executable.load_segments() }.iter() {
+        let virtual_address = base + segment.p_vaddr as usize;
+        let virtual_size = segment.p_memsz as usize;
+
+        let offset = virtual_address % 4096;
+
+        let physical_address = unsafe { memory::alloc_aligned(virtual_size + offset, 4096) };
+
+        if physical_address == 0 {
+            panic!("OOM in exec");
+        }
+
+        let mut memory = ContextMemory {
+            physical_address: physical_address,
+            virtual_address: virtual_address - offset,
+            virtual_size: virtual_size + offset,
+            writeable: true,
+            executable: false,
+            allocated: true,
+        };
+
+        // Copy progbits, through the physical memory, as the kernel cannot write to user pages
+        // outside of system calls
+        unsafe {
+            ::memcpy((physical_address + offset) as *mut u8,
+                    executable.data.as_ptr().offset(segment.p_offset as isize),
+                    segment.p_filesz as usize)
+        };
+
+        memory.writeable = segment.p_flags & 2 == 2;
+        memory.executable = segment.p_flags & 1 == 1;
+
+        if segment.p_type == 1 {
+            image.memory.push(memory);
+        } else if segment.p_type == 7 {
+            tls_master = Some(memory);
         }
-        context_args.push(argc);
+    }
+
+    tls_master
+}
+
+/// Start the program of a context at `entry`
+///
+/// The initial stack follows the System V ABI. From the stack pointer up, it holds `argc`, the
+/// `argv` pointers and a null pointer, the `envp` pointers and a null pointer, and the auxiliary
+/// vector `auxv`, followed by `AT_RANDOM` and `AT_NULL`. The strings and random bytes these point
+/// to are above them, at the top of the stack.
+pub fn execute_thread(context_ptr: *mut Context, entry: usize, args: Vec<String>, mut auxv: Vec<(usize, usize)>) -> ! {
+    Context::spawn("kexec".into(),
+                   box move || {
+        let context = unsafe { &mut *context_ptr };
 
         context.iopl = 0;
 
         context.regs = Regs::default();
         context.regs.sp = context.kernel_stack + CONTEXT_STACK_SIZE - 128;
 
-        context.stack = Some(ContextMemory {
-            physical_address: unsafe { memory::alloc_aligned(CONTEXT_STACK_SIZE, 4096) },
-            virtual_address: CONTEXT_STACK_ADDR,
-            virtual_size: CONTEXT_STACK_SIZE,
+        // The stack starts out large enough for the arguments, environment and auxiliary vector,
+        // and grows when it is used below that. Its top is placed at random with the space
+        // reserved for it and the guard below it kept above the mmap zone.
+        let initial_size = args_size(&args, context.list_env_vars()) + (auxv.len() + 8) * 2 * mem::size_of::<usize>() + 32;
+        let stack_size = (initial_size/CONTEXT_STACK_GROW_SIZE + 1) * CONTEXT_STACK_GROW_SIZE;
+        let stack_top = CONTEXT_STACK_ADDR + CONTEXT_STACK_SIZE - random_offset() % (CONTEXT_RANDOM_SIZE - CONTEXT_STACK_MAX_SIZE);
+        context.stack = Some(ContextStack::new(ContextMemory {
+            physical_address: unsafe { memory::alloc_aligned(stack_size, 4096) },
+            virtual_address: stack_top - stack_size,
+            virtual_size: stack_size,
             writeable: true,
+            executable: false,
             allocated: true,
-        });
+        }));
 
         unsafe {
-            if let Some(ref mut tls_master) = *context.tls_master.get() {
-                let mut tls = ContextMemory {
+            if let Some(ref tls_master) = *context.tls_master.get() {
+                let tls = ContextMemory {
                     physical_address: memory::alloc_aligned(tls_master.virtual_size + 4096, 4096),
                     virtual_address: CONTEXT_TLS_ADDR,
                     virtual_size: tls_master.virtual_size + 4096,
                     writeable: true,
+                    executable: false,
                     allocated: true
                 };
 
-                tls_master.map();
-                tls.map();
-
-                *(tls.virtual_address as *mut usize) = tls.virtual_address + tls.virtual_size;
+                // Written through the physical memory, like the stack, as this thread cannot
+                // access user pages
+                *(tls.physical_address as *mut usize) = tls.virtual_address + tls.virtual_size;
 
-                ::memcpy((tls.virtual_address + tls.virtual_size - tls_master.virtual_size) as *mut u8,
-                        tls_master.virtual_address as *const u8,
+                ::memcpy((tls.physical_address + tls.virtual_size - tls_master.virtual_size) as *mut u8,
+                        tls_master.physical_address as *const u8,
                         tls_master.virtual_size);
 
-                tls.unmap();
-                tls_master.unmap();
-
                 context.tls = Some(tls);
             }
         }
 
-        let user_sp = if let Some(ref stack) = context.stack {
-            let mut sp = stack.physical_address + stack.virtual_size - 128;
-            for arg in context_args.iter() {
-                sp -= mem::size_of::<usize>();
-                unsafe { ptr::write(sp as *mut usize, *arg) };
+        context.account_memory();
+
+        // The new stack is one piece, which the arguments are written to
+        let user_sp = if let Some(stack) = context.stack.as_ref().and_then(|stack| stack.memory.first()) {
+            let mut sp = stack.physical_address + stack.virtual_size;
+
+            let mut argv = Vec::new();
+            for arg in args.iter() {
+                argv.push(unsafe { push_string(&mut sp, stack, arg.trim_right_matches('\0').as_bytes()) });
+            }
+
+            let mut envp = Vec::new();
+            for var in context.list_env_vars().iter() {
+                let string = format!("{}={}", var.name(), var.value());
+                envp.push(unsafe { push_string(&mut sp, stack, string.as_bytes()) });
+            }
+
+            sp -= 16;
+            random_bytes(unsafe { slice::from_raw_parts_mut(sp as *mut u8, 16) });
+            auxv.push((AT_RANDOM, sp - stack.physical_address + stack.virtual_address));
+            auxv.push((AT_NULL, 0));
+
+            let mut words = Vec::new();
+            words.push(argv.len());
+            words.extend_from_slice(&argv);
+            words.push(0);
+            words.extend_from_slice(&envp);
+            words.push(0);
+            for &(kind, value) in auxv.iter() {
+                words.push(kind);
+                words.push(value);
+            }
+
+            sp = (sp - words.len() * mem::size_of::<usize>()) & !15;
+            for (i, word) in words.iter().enumerate() {
+                unsafe { ptr::write((sp + i * mem::size_of::<usize>()) as *mut usize, *word) };
             }
+
             sp - stack.physical_address + stack.virtual_address
         } else {
             0
@@ -124,60 +301,14 @@ pub fn execute_thread(context_ptr: *mut Context, entry: usize, mut args: Vec<Str
     }
 }
 
-/// Execute an executable
-pub fn execute(mut args: Vec<String>) -> Result<usize> {
+/// Execute an executable, with the environment variables `vars`, or those of the current context
+/// if `vars` is `None`
+pub fn execute(mut args: Vec<String>, vars: Option<Vec<EnvVar>>) -> Result<usize> {
     let contexts = unsafe { &mut *::env().contexts.get() };
     let current = try!(contexts.current_mut());
 
-    let mut vec: Vec<u8> = Vec::new();
-
     let path = current.canonicalize(args.get(0).map_or("", |p| &p));
-    {
-        let mut resource = try!(::env().open(&path, O_RDONLY));
-
-        // Hack to allow file scheme to find memory in context's memory space
-        unsafe {
-            let mmap = &mut *current.mmap.get();
-
-            let virtual_size = 1024*1024;
-
-            let physical_address = memory::alloc_aligned(virtual_size, 4096);
-            if physical_address == 0 {
-                return Err(Error::new(ENOMEM));
-            }
-
-            let virtual_address = try!(mmap.add_mem(physical_address, virtual_size, true, true));
-
-            for i in 0..mmap.memory.len() {
-                if mmap.memory[i].virtual_address == virtual_address {
-                    mmap.memory[i].map();
-                    break;
-                }
-            }
-
-            let mut read_loop = || -> Result<usize> {
-                loop {
-                    let mut bytes = slice::from_raw_parts_mut(virtual_address as *mut u8, virtual_size);
-                    match resource.read(&mut bytes) {
-                        Ok(0) => return Ok(0),
-                        Ok(count) => vec.extend_from_slice(bytes.get_slice(.. count)),
-                        Err(err) => return Err(err)
-                    }
-                }
-            };
-
-            let res = read_loop();
-
-            for i in 0..mmap.memory.len() {
-                if mmap.memory[i].virtual_address == virtual_address {
-                    mmap.memory.remove(i).unmap();
-                    break;
-                }
-            }
-
-            try!(res);
-        }
-    }
+    let vec = try!(read_file(current, &path));
 
     if vec.starts_with(b"#!") {
         if let Some(mut arg) = args.get_mut(0) {
@@ -195,73 +326,133 @@ pub fn execute(mut args: Vec<String>) -> Result<usize> {
         if i == 0 {
             args.insert(i, "/bin/sh".to_owned());
         }
-        execute(args)
+        execute(args, vars)
     } else {
         match Elf::from(&vec) {
             Ok(executable) => {
-                let entry = unsafe { executable.entry() };
                 let segments = unsafe { executable.load_segments() };
 
-                if entry > 0 && ! segments.is_empty() {
+                if unsafe { executable.entry() } > 0 && ! segments.is_empty() {
+                    if args_size(&args, vars.as_ref().map_or(current.list_env_vars(), |vars| &vars[..])) > ARG_MAX {
+                        return Err(Error::new(E2BIG));
+                    }
+
+                    // Position independent executables are loaded at a random base
+                    let base = if unsafe { executable.is_dynamic() } {
+                        CONTEXT_IMAGE_ADDR + random_offset()
+                    } else {
+                        0
+                    };
+                    let entry = base + unsafe { executable.entry() };
+
+                    // The interpreter named by `PT_INTERP`, usually the dynamic linker, is loaded
+                    // after the executable and started instead of it
+                    let interpreter_data = match unsafe { executable.interpreter() } {
+                        Some(interpreter_path) => {
+                            let interpreter_path = current.canonicalize(&interpreter_path);
+                            try!(read_file(current, &interpreter_path))
+                        },
+                        None => Vec::new()
+                    };
+                    let interpreter = if interpreter_data.is_empty() {
+                        None
+                    } else {
+                        match Elf::from(&interpreter_data) {
+                            Ok(interpreter) => {
+                                let interpreter_base = if unsafe { interpreter.is_dynamic() } {
+                                    ((base + image_end(&executable) + 4095)/4096) * 4096 + random_offset()
+                                } else {
+                                    0
+                                };
+                                Some((interpreter, interpreter_base))
+                            },
+                            Err(msg) => {
+                                debugln!("execute: failed to load interpreter of '{:?}': {}", path, msg);
+                                return Err(Error::new(ENOEXEC));
+                            }
+                        }
+                    };
+
+                    if base > 0 && base + image_end(&executable) > CONTEXT_IMAGE_ADDR + CONTEXT_IMAGE_SIZE {
+                        return Err(Error::new(ENOEXEC));
+                    }
+                    if let Some((ref interpreter, interpreter_base)) = interpreter {
+                        if interpreter_base > 0 && interpreter_base + image_end(interpreter) > CONTEXT_IMAGE_ADDR + CONTEXT_IMAGE_SIZE {
+                            return Err(Error::new(ENOEXEC));
+                        }
+                    }
+
+                    // The program replaces the address space, so only its own size counts
+                    // against the address space limit
+                    let size = image_size(&executable) + interpreter.as_ref().map_or(0, |&(ref interpreter, _)| image_size(interpreter));
+                    if size > current.rlimits[RLIMIT_AS].rlim_cur {
+                        return Err(Error::new(ENOMEM));
+                    }
+
+                    let mut auxv = Vec::new();
+                    if let Some(address) = unsafe { executable.program_headers() } {
+                        let (size, count) = unsafe { executable.program_header_count() };
+                        auxv.push((AT_PHDR, base + address));
+                        auxv.push((AT_PHENT, size));
+                        auxv.push((AT_PHNUM, count));
+                    }
+                    auxv.push((AT_PAGESZ, 4096));
+                    if let Some((_, interpreter_base)) = interpreter {
+                        auxv.push((AT_BASE, interpreter_base));
+                    }
+                    auxv.push((AT_ENTRY, entry));
+
                     unsafe { current.unmap() };
 
                     current.name = path.to_string().into();
                     current.cwd = Arc::new(UnsafeCell::new(unsafe { (*current.cwd.get()).clone() }));
 
                     current.image = Arc::new(UnsafeCell::new(ContextZone::new(CONTEXT_IMAGE_ADDR, CONTEXT_IMAGE_SIZE)));
-                    current.heap = Arc::new(UnsafeCell::new(ContextZone::new(CONTEXT_HEAP_ADDR, CONTEXT_HEAP_SIZE)));
-                    current.mmap = Arc::new(UnsafeCell::new(ContextZone::new(CONTEXT_MMAP_ADDR, CONTEXT_MMAP_SIZE)));
-                    current.env_vars = Arc::new(UnsafeCell::new(unsafe { (*current.env_vars.get()).clone() }));
+                    let heap_offset = random_offset();
+                    current.heap = Arc::new(UnsafeCell::new(ContextZone::new(CONTEXT_HEAP_ADDR + heap_offset, CONTEXT_HEAP_SIZE - heap_offset)));
+                    let mmap_offset = random_offset();
+                    current.mmap = Arc::new(UnsafeCell::new(ContextZone::new(CONTEXT_MMAP_ADDR + mmap_offset, CONTEXT_MMAP_SIZE - CONTEXT_RANDOM_SIZE - mmap_offset)));
+                    current.env_vars = Arc::new(UnsafeCell::new(vars.unwrap_or_else(|| unsafe { (*current.env_vars.get()).clone() })));
+
+                    // Files marked close-on-exec are closed, and files shared with other
+                    // contexts are duplicated so that closing them does not affect the others
+                    if let Some(files) = Arc::get_mut(&mut current.files) {
+                        unsafe { &mut *files.get() }.retain(|file| file.flags & FD_CLOEXEC != FD_CLOEXEC);
+                    } else {
+                        let files: Vec<ContextFile> = unsafe { (*current.files.get()).iter() }
+                            .filter(|file| file.flags & FD_CLOEXEC != FD_CLOEXEC)
+                            .filter_map(|file| {
+                                file.resource.dup().ok().map(|resource| ContextFile {
+                                    fd: file.fd,
+                                    flags: file.flags,
+                                    resource: resource,
+                                    lock_owner: file.lock_owner.clone(),
+                                })
+                            })
+                            .collect();
+                        current.files = Arc::new(UnsafeCell::new(files));
+                    }
 
                     {
                         let image = unsafe { &mut *current.image.get() };
 
-                        for segment in segments.iter() {
-                            let virtual_address = segment.p_vaddr as usize;
-                            let virtual_size = segment.p_memsz as usize;
-
-                            let offset = virtual_address % 4096;
-
-                            let physical_address = unsafe { memory::alloc_aligned(virtual_size + offset, 4096) };
-
-                            if physical_address == 0 {
-                                panic!("OOM in exec");
-                            }
-
-                            let mut memory = ContextMemory {
-                                physical_address: physical_address,
-                                virtual_address: virtual_address - offset,
-                                virtual_size: virtual_size + offset,
-                                writeable: true,
-                                allocated: true,
-                            };
-
-                            unsafe { memory.map() };
-
-                            // Copy progbits
-                            unsafe {
-                                ::memcpy(virtual_address as *mut u8,
-                                        executable.data.as_ptr().offset(segment.p_offset as isize),
-                                        segment.p_filesz as usize)
-                            };
-
-                            unsafe { memory.unmap() };
+                        unsafe { *current.tls_master.get() = load_image(&executable, base, image) };
 
-                            memory.writeable = segment.p_flags & 2 == 2;
-
-                            if segment.p_type == 1 {
-                                image.memory.push(memory);
-                            } else if segment.p_type == 7 {
-                                unsafe { *current.tls_master.get() = Some(memory) };
-                            }
+                        if let Some((ref interpreter, interpreter_base)) = interpreter {
+                            load_image(interpreter, interpreter_base, image);
                         }
                     }
 
+                    let start = match interpreter {
+                        Some((ref interpreter, interpreter_base)) => interpreter_base + unsafe { interpreter.entry() },
+                        None => entry
+                    };
+
                     //debugln!("{}: {}: execute {}", context.pid, context.name, url.string);
 
                     unsafe { current.map() };
 
-                    execute_thread(current.deref_mut(), entry, args);
+                    execute_thread(current.deref_mut(), start, args, auxv);
                 } else {
                     Err(Error::new(ENOEXEC))
                 }
@@ -2,15 +2,22 @@
 
 use arch::context::ContextFile;
 
+use collections::Vec;
+use collections::borrow::ToOwned;
+
 use core::str;
 
-use fs::ResourceSeek;
+use fs::{LockOwner, Resource, ResourceSeek};
 
 use schemes::pipe::{PipeRead, PipeWrite};
 
-use syscall::{Stat, SEEK_CUR, SEEK_END, SEEK_SET};
+use syscall::{Stat, SEEK_CUR, SEEK_END, SEEK_SET, F_GETFD, F_SETFD, F_GETFL, F_SETFL, FD_CLOEXEC,
+              F_GETTIMEOUT, F_SETTIMEOUT, F_GETPACKET, F_SETPACKET, F_GETRING, F_GETSYMLINKS,
+              F_SETSYMLINKS, O_APPEND, O_CLOEXEC, O_NONBLOCK, O_SHLOCK, O_EXLOCK, LOCK_SH, LOCK_EX,
+              LOCK_NB, RLIMIT_NOFILE, IoVec, MODE_DIR, MODE_TYPE};
 
-use system::error::{Error, Result, EBADF, EINVAL};
+use system::error::{Error, Result, EACCES, EBADF, EINVAL, ENOTDIR};
+use system::watch::WATCH_MODIFY;
 
 /** <!-- @MANSTART{sys_chdir} -->
 NAME
@@ -52,12 +59,44 @@ ERRORS
 pub fn chdir(path: &[u8]) -> Result<usize> {
     let contexts = unsafe { & *::env().contexts.get() };
     let current = try!(contexts.current());
+    let path_string = current.canonicalize(unsafe { str::from_utf8_unchecked(path) });
+    // The directory is kept as it was spelled, so that it stays under the mounts and symlinks
+    // it was reached through
     unsafe {
-        *current.cwd.get() = current.canonicalize(str::from_utf8_unchecked(path));
+        *current.cwd.get() = normalize_dir(&path_string);
     }
     Ok(0)
 }
 
+/// Collapse the `.` and `..` components of a directory url without looking them up, as
+/// `canonicalize` leaves them in place, and end it with `/` so that paths can be appended
+fn normalize_dir(url: &str) -> String {
+    let mut url_split = url.splitn(2, ':');
+    let scheme = url_split.next().unwrap_or("");
+    let path = url_split.next().unwrap_or("");
+
+    let mut parts: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => (),
+            ".." => {
+                parts.pop();
+            },
+            _ => parts.push(component)
+        }
+    }
+
+    let mut dir = scheme.to_owned() + ":";
+    if path.starts_with('/') {
+        dir.push('/');
+    }
+    for part in parts.iter() {
+        dir.push_str(part);
+        dir.push('/');
+    }
+    dir
+}
+
 /** <!-- @MANSTART{sys_close} -->
 NAME
     sys_close - close a file descriptor
@@ -131,18 +170,92 @@ pub fn dup(fd: usize) -> Result<usize> {
     let contexts = unsafe { & *::env().contexts.get() };
     let current = try!(contexts.current());
     let resource = try!(current.get_file(fd));
+    let lock_owner = try!(current.get_lock_owner(fd));
+    let new_resource = try!(resource.dup());
+    let new_fd = try!(current.next_fd());
+
+    unsafe {
+        (*current.files.get()).push(ContextFile {
+            fd: new_fd,
+            flags: 0,
+            resource: new_resource,
+            lock_owner: lock_owner,
+        });
+    }
+    Ok(new_fd)
+}
+
+/// Duplicate a file descriptor to `new_fd`, closing the resource `new_fd` referred to if any
+///
+/// The new file descriptor does not have `FD_CLOEXEC` set.
+pub fn dup2(fd: usize, new_fd: usize) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    let resource = try!(current.get_file(fd));
+    if fd == new_fd {
+        return Ok(new_fd);
+    }
+
+    if new_fd >= current.rlimits[RLIMIT_NOFILE].rlim_cur {
+        return Err(Error::new(EBADF));
+    }
+
+    let lock_owner = try!(current.get_lock_owner(fd));
     let new_resource = try!(resource.dup());
-    let new_fd = current.next_fd();
+
+    let _ = close(new_fd);
 
     unsafe {
         (*current.files.get()).push(ContextFile {
             fd: new_fd,
+            flags: 0,
             resource: new_resource,
+            lock_owner: lock_owner,
         });
     }
     Ok(new_fd)
 }
 
+/// Take or release an advisory lock on the resource of a file descriptor
+///
+/// The lock belongs to the open file, so it is shared with the file descriptors duplicated from
+/// `fd`, and released when the last of them is closed.
+pub fn flock(fd: usize, operation: usize) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let mut current = try!(contexts.current_mut());
+    let lock_owner = try!(current.get_lock_owner(fd));
+    let mut resource = try!(current.get_file_mut(fd));
+    resource.flock(lock_owner.id(), operation).and(Ok(0))
+}
+
+/// Get or set the flags of a file descriptor, the status flags of its resource, or the call
+/// timeout, packet version, call ring and symlinks of the scheme it serves
+///
+/// Only `O_NONBLOCK` and `O_APPEND` are passed to the resource with `F_SETFL`.
+pub fn fcntl(fd: usize, cmd: usize, arg: usize) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+
+    for file in unsafe { (*current.files.get()).iter_mut() } {
+        if file.fd == fd {
+            return match cmd {
+                F_GETFD => Ok(file.flags),
+                F_SETFD => {
+                    file.flags = arg & FD_CLOEXEC;
+                    Ok(0)
+                },
+                F_GETFL => file.resource.fcntl(F_GETFL, 0),
+                F_SETFL => file.resource.fcntl(F_SETFL, arg & (O_NONBLOCK | O_APPEND)),
+                F_GETTIMEOUT | F_SETTIMEOUT | F_GETPACKET | F_SETPACKET | F_GETRING | F_GETSYMLINKS |
+                F_SETSYMLINKS => file.resource.fcntl(cmd, arg),
+                _ => Err(Error::new(EINVAL))
+            };
+        }
+    }
+
+    Err(Error::new(EBADF))
+}
+
 pub fn fpath(fd: usize, buf: &mut [u8]) -> Result<usize> {
     if buf.len() > 0 {
         let contexts = unsafe { & *::env().contexts.get() };
@@ -158,6 +271,7 @@ pub fn fstat(fd: usize, stat: &mut Stat) -> Result<usize> {
     let contexts = unsafe { & *::env().contexts.get() };
     let current = contexts.current()?;
     let resource = current.get_file(fd)?;
+    *stat = Stat::default();
     resource.stat(stat).and(Ok(0))
 }
 
@@ -231,7 +345,22 @@ pub fn ftruncate(fd: usize, length: usize) -> Result<usize> {
     resource.truncate(length).and(Ok(0))
 }
 
-//TODO: Link
+/// Read directory entries as `Dirent` records, each followed by its name
+pub fn getdents(fd: usize, buf: &mut [u8]) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let mut current = try!(contexts.current_mut());
+    let mut resource = try!(current.get_file_mut(fd));
+    resource.getdents(buf)
+}
+
+/// Create a hard link, which fails with `EXDEV` if the paths are in different schemes
+pub fn link(old: &[u8], new: &[u8]) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    let old_string = current.canonicalize(unsafe { str::from_utf8_unchecked(old) });
+    let new_string = current.canonicalize(unsafe { str::from_utf8_unchecked(new) });
+    ::env().link(&old_string, &new_string).and(Ok(0))
+}
 
 /** <!-- @MANSTART{sys_lseek} -->
 NAME
@@ -325,6 +454,34 @@ pub fn mkdir(path: &[u8], flags: usize) -> Result<usize> {
     ::env().mkdir(&path_string, flags).and(Ok(0))
 }
 
+/// Mount the directory at target on a path prefix, so that the prefix and the paths under it
+/// refer to target and the paths under it
+///
+/// Supervised processes may not mount, as their mounts would redirect the paths of processes
+/// that are not supervised.
+pub fn mount(prefix: &[u8], target: &[u8]) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    if current.supervised {
+        return Err(Error::new(EACCES));
+    }
+    let prefix_string = current.canonicalize(unsafe { str::from_utf8_unchecked(prefix) });
+    let target_string = current.canonicalize(unsafe { str::from_utf8_unchecked(target) });
+
+    let mut stat = Stat::default();
+    try!(::env().stat(&target_string, &mut stat));
+    if stat.st_mode & MODE_TYPE != MODE_DIR {
+        return Err(Error::new(ENOTDIR));
+    }
+
+    // Targets are stored resolved, so that a mount under another mount is one lookup
+    let mounts = unsafe { &mut *::env().mounts.get() };
+    let target_string = mounts.resolve(&target_string);
+    try!(mounts.mount(&prefix_string, &target_string, current.pid));
+    unsafe { &mut *::env().watches.get() }.notify(WATCH_MODIFY, "sys:/mounts");
+    Ok(0)
+}
+
 /** <!-- @MANSTART{sys_open} -->
 NAME
     sys_open - open and possibly create a file
@@ -381,34 +538,76 @@ pub fn open(path: &[u8], flags: usize) -> Result<usize> {
     let contexts = unsafe { & *::env().contexts.get() };
     let current = try!(contexts.current());
     let path_canon = current.canonicalize(unsafe { str::from_utf8_unchecked(path) });
-    let resource = try!(::env().open(&path_canon, flags));
-    let fd = current.next_fd();
+
+    // The file descriptor is found before the file is opened, so that running out of them does
+    // not create or truncate the file
+    let mut fd = try!(current.next_fd());
+    let mut resource = try!(::env().open(&path_canon, flags));
+
+    // Locks requested at open are taken before the file descriptor exists, and are released
+    // with the lock owner and the resource if it is not created
+    let lock_owner = LockOwner::new();
+    let lock = if flags & O_EXLOCK == O_EXLOCK {
+        Some(LOCK_EX)
+    } else if flags & O_SHLOCK == O_SHLOCK {
+        Some(LOCK_SH)
+    } else {
+        None
+    };
+    if let Some(operation) = lock {
+        let nonblock = if flags & O_NONBLOCK == O_NONBLOCK { LOCK_NB } else { 0 };
+        try!(resource.flock(lock_owner.id(), operation | nonblock));
+
+        // Other threads can open files while this one waits for the lock, and take the file
+        // descriptor
+        fd = try!(current.next_fd());
+    }
+
     unsafe {
         (*current.files.get()).push(ContextFile {
             fd: fd,
+            flags: if flags & O_CLOEXEC == O_CLOEXEC { FD_CLOEXEC } else { 0 },
             resource: resource,
+            lock_owner: lock_owner,
         });
     }
     Ok(fd)
 }
 
-pub fn pipe2(fds: &mut [usize; 2], _flags: usize) -> Result<usize> {
+/// Create a pipe, `O_NONBLOCK` and `O_CLOEXEC` in `flags` applying to both ends
+pub fn pipe2(fds: &mut [usize; 2], flags: usize) -> Result<usize> {
     let contexts = unsafe { & *::env().contexts.get() };
     let current = try!(contexts.current());
-    let read = box PipeRead::new();
-    let write = box PipeWrite::new(&read);
+    let mut read = box PipeRead::new();
+    try!(read.fcntl(F_SETFL, flags & O_NONBLOCK));
+    let mut write = box PipeWrite::new(&read);
+    try!(write.fcntl(F_SETFL, flags & O_NONBLOCK));
 
+    let fd_flags = if flags & O_CLOEXEC == O_CLOEXEC { FD_CLOEXEC } else { 0 };
+
+    fds[0] = try!(current.next_fd());
     unsafe {
-        fds[0] = current.next_fd();
         (*current.files.get()).push(ContextFile {
             fd: fds[0],
+            flags: fd_flags,
             resource: read,
+            lock_owner: LockOwner::new(),
         });
+    }
 
-        fds[1] = current.next_fd();
+    fds[1] = match current.next_fd() {
+        Ok(fd) => fd,
+        Err(err) => {
+            let _ = close(fds[0]);
+            return Err(err);
+        }
+    };
+    unsafe {
         (*current.files.get()).push(ContextFile {
             fd: fds[1],
+            flags: fd_flags,
             resource: write,
+            lock_owner: LockOwner::new(),
         });
     }
 
@@ -457,6 +656,61 @@ pub fn read(fd: usize, buf: &mut [u8]) -> Result<usize> {
     }
 }
 
+/// Read at `offset`, without using or changing the seek position
+pub fn pread(fd: usize, buf: &mut [u8], offset: u64) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let mut current = try!(contexts.current_mut());
+    let mut resource = try!(current.get_file_mut(fd));
+    if buf.len() > 0 {
+        resource.pread(buf, offset)
+    } else {
+        Ok(0)
+    }
+}
+
+/// Write at `offset`, without using or changing the seek position
+pub fn pwrite(fd: usize, buf: &[u8], offset: u64) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let mut current = try!(contexts.current_mut());
+    let mut resource = try!(current.get_file_mut(fd));
+    if buf.len() > 0 {
+        resource.pwrite(buf, offset)
+    } else {
+        Ok(0)
+    }
+}
+
+/// Read to several buffers, each of which is checked to be writable by the current context
+pub fn readv(fd: usize, iov: &[IoVec]) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let mut current = try!(contexts.current_mut());
+    let mut resource = try!(current.get_file_mut(fd));
+
+    let mut bufs: Vec<&mut [u8]> = Vec::new();
+    for vec in iov.iter() {
+        bufs.push(try!(current.get_slice_mut(vec.iov_base as *mut u8, vec.iov_len)));
+    }
+
+    resource.readv(&mut bufs)
+}
+
+/// Read the target of a symlink
+pub fn readlink(path: &[u8], buf: &mut [u8]) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    let path_string = current.canonicalize(unsafe { str::from_utf8_unchecked(path) });
+    ::env().readlink(&path_string, buf)
+}
+
+/// Rename a resource, which fails with `EXDEV` if the paths are in different schemes
+pub fn rename(old: &[u8], new: &[u8]) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    let old_string = current.canonicalize(unsafe { str::from_utf8_unchecked(old) });
+    let new_string = current.canonicalize(unsafe { str::from_utf8_unchecked(new) });
+    ::env().rename(&old_string, &new_string).and(Ok(0))
+}
+
 pub fn rmdir(path: &[u8]) -> Result<usize> {
     let contexts = unsafe { & *::env().contexts.get() };
     let current = try!(contexts.current());
@@ -464,6 +718,14 @@ pub fn rmdir(path: &[u8]) -> Result<usize> {
     ::env().rmdir(&path_string).and(Ok(0))
 }
 
+/// Get information about a resource by path, following symlinks
+pub fn stat(path: &[u8], stat: &mut Stat) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    let path_string = current.canonicalize(unsafe { str::from_utf8_unchecked(path) });
+    ::env().stat(&path_string, stat).and(Ok(0))
+}
+
 pub fn unlink(path: &[u8]) -> Result<usize> {
     let contexts = unsafe { & *::env().contexts.get() };
     let current = try!(contexts.current());
@@ -471,6 +733,24 @@ pub fn unlink(path: &[u8]) -> Result<usize> {
     ::env().unlink(&path_string).and(Ok(0))
 }
 
+/// Remove the mount at a path prefix, which only the process that made it may do while it exists
+pub fn umount(prefix: &[u8]) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    let prefix_string = current.canonicalize(unsafe { str::from_utf8_unchecked(prefix) });
+    try!(unsafe { &mut *::env().mounts.get() }.umount(&prefix_string, current.pid));
+    unsafe { &mut *::env().watches.get() }.notify(WATCH_MODIFY, "sys:/mounts");
+    Ok(0)
+}
+
+/// Create a symlink at a path, pointing to a target that is stored as given
+pub fn symlink(target: &[u8], path: &[u8]) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    let path_string = current.canonicalize(unsafe { str::from_utf8_unchecked(path) });
+    ::env().symlink(unsafe { str::from_utf8_unchecked(target) }, &path_string).and(Ok(0))
+}
+
 /** <!-- @MANSTART{sys_write} -->
 NAME
     sys_write - read from a file descriptor
@@ -518,3 +798,17 @@ pub fn write(fd: usize, buf: &[u8]) -> Result<usize> {
         Ok(0)
     }
 }
+
+/// Write several buffers, each of which is checked to be readable by the current context
+pub fn writev(fd: usize, iov: &[IoVec]) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let mut current = try!(contexts.current_mut());
+    let mut resource = try!(current.get_file_mut(fd));
+
+    let mut bufs: Vec<&[u8]> = Vec::new();
+    for vec in iov.iter() {
+        bufs.push(try!(current.get_slice(vec.iov_base as *const u8, vec.iov_len)));
+    }
+
+    resource.writev(&bufs)
+}
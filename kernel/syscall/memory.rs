@@ -10,8 +10,8 @@ use system::error::Result;
 pub fn brk(addr: usize) -> Result<usize> {
     let mut ret = 0;
 
-    let contexts = unsafe { & *::env().contexts.get() };
-    if let Ok(current) = contexts.current() {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    if let Ok(mut current) = contexts.current_mut() {
         ret = unsafe { (*current.heap.get()).address };
 
         for mem in unsafe { (*current.heap.get()).memory.iter() } {
@@ -26,7 +26,12 @@ pub fn brk(addr: usize) -> Result<usize> {
             //Return current break
         } else if addr > ret {
             let size = addr - ret;
-            let physical_address = unsafe { memory::alloc_aligned(size, 4096) };
+            // Like a failed allocation, going over the address space limit leaves the break as is
+            let physical_address = if current.memory_allows(size) {
+                unsafe { memory::alloc_aligned(size, 4096) }
+            } else {
+                0
+            };
             if physical_address > 0 {
                 // debugln!("BRK: Alloc {}", size);
                 let mut mem = ContextMemory {
@@ -34,6 +39,7 @@ pub fn brk(addr: usize) -> Result<usize> {
                     virtual_address: ret,
                     virtual_size: size,
                     writeable: true,
+                    executable: false,
                     allocated: true
                 };
                 ret = mem.virtual_address + mem.virtual_size;
@@ -42,6 +48,7 @@ pub fn brk(addr: usize) -> Result<usize> {
                     mem.map();
                     (*current.heap.get()).memory.push(mem);
                 }
+                current.account_memory();
             } else {
                 debugln!("BRK: Alloc failed {}", size);
             }
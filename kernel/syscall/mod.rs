@@ -2,7 +2,13 @@ pub use system::error::*;
 pub use system::syscall::*;
 
 use arch::regs::Regs;
-use arch::context::context_switch;
+use arch::context::{Context, context_switch};
+
+use collections::Vec;
+
+use core::cmp;
+
+use system::scheme::Packet;
 
 pub mod execute;
 pub mod fs;
@@ -10,41 +16,43 @@ pub mod memory;
 pub mod process;
 pub mod time;
 
-pub fn name(number: usize) -> &'static str {
-    match number {
-        // Redox
-        SYS_SUPERVISE => "supervise",
-
-        // Unix
-        SYS_BRK => "brk",
-        SYS_CHDIR => "chdir",
-        SYS_CLONE => "clone",
-        SYS_CLOSE => "close",
-        SYS_CLOCK_GETTIME => "clock_gettime",
-        SYS_DUP => "dup",
-        SYS_EXECVE => "execve",
-        SYS_EXIT => "exit",
-        SYS_FPATH => "fpath",
-        SYS_FSTAT => "fstat",
-        SYS_FSYNC => "fsync",
-        SYS_FTRUNCATE => "ftruncate",
-        SYS_FUTEX => "futex",
-        SYS_GETPID => "getpid",
-        SYS_IOPL => "iopl",
-        // TODO: link
-        SYS_LSEEK => "lseek",
-        SYS_MKDIR => "mkdir",
-        SYS_NANOSLEEP => "nanosleep",
-        SYS_OPEN => "open",
-        SYS_PIPE2 => "pipe2",
-        SYS_READ => "read",
-        SYS_RMDIR => "rmdir",
-        SYS_UNLINK => "unlink",
-        SYS_WAITPID => "waitpid",
-        SYS_WRITE => "write",
-        SYS_YIELD => "yield",
-
-        _ => "unknown",
+/// The most bytes of a syscall buffer sent to the supervisor
+const SUPERVISE_DATA: usize = 4096;
+
+/// Copy the buffer a supervised syscall refers to, to be sent to the supervisor.
+///
+/// On entry, this is the path of path based calls and the data of `write`. On exit, this is the
+/// data of `read` and `fpath`, as far as it was filled in.
+fn supervise_data(cur: &Context, regs: &Regs, result: Option<usize>) -> Vec<u8> {
+    let (ptr, len) = match (regs.ax, result) {
+        (SYS_OPEN, None) | (SYS_MKDIR, None) | (SYS_RMDIR, None) |
+        (SYS_UNLINK, None) | (SYS_CHDIR, None) | (SYS_RENAME, None) |
+        (SYS_LINK, None) | (SYS_SYMLINK, None) | (SYS_READLINK, None) |
+        (SYS_STAT, None) | (SYS_MOUNT, None) | (SYS_UMOUNT, None) => (regs.bx, regs.cx),
+        (SYS_WRITE, None) | (SYS_PWRITE, None) => (regs.cx, regs.dx),
+        (SYS_READ, Some(count)) | (SYS_PREAD, Some(count)) | (SYS_FPATH, Some(count)) => (regs.cx, count),
+        (SYS_READLINK, Some(count)) => (regs.dx, count),
+        _ => (0, 0),
+    };
+
+    cur.get_slice(ptr as *const u8, cmp::min(len, SUPERVISE_DATA))
+       .map(|data| data.to_vec())
+       .unwrap_or(Vec::new())
+}
+
+/// Hand an event to the supervisor, and stop the process until the supervisor has handled it.
+fn supervise_wait(cur: &mut Context, event: Packet, data: Vec<u8>) {
+    cur.supervise_event = Some((event, data));
+    cur.supervise_reply = None;
+
+    // Block the process.
+    cur.blocked_syscall = true;
+    cur.block("syscall::handle Supervise");
+    // Clear the timer.
+    cur.wake = None;
+
+    while cur.blocked > 0 {
+        unsafe { context_switch() };
     }
 }
 
@@ -54,36 +62,79 @@ pub fn name(number: usize) -> &'static str {
 /// the specific sycall.
 ///
 /// The return value is placed in AX, unless otherwise specified.
+///
+/// The syscalls of supervised processes are first handed to the supervisor, which either replies
+/// with a return value, or passes the syscall through and is handed its result afterwards.
 pub fn handle(regs: &mut Regs) {
-    let contexts = unsafe { &mut *::env().contexts.get() };
-    let cur = contexts.current_mut().unwrap();
-    cur.current_syscall = Some((regs.ip, regs.ax, regs.bx, regs.cx, regs.dx));
-    // debugln!("PID {}: {} @ {:X}: {} {} {:X} {:X} {:X}", cur.pid, cur.name, regs.ip, regs.ax, name(regs.ax), regs.bx, regs.cx, regs.dx);
-    if cur.supervised {
-        // Block the process.
-        cur.blocked_syscall = true;
-        cur.block("syscall::handle Supervise");
-        // Clear the timer.
-        cur.wake = None;
-
-        loop {
-            if cur.blocked > 0 {
-                unsafe { context_switch() };
-            } else {
-                return;
+    let mut passed = false;
+
+    {
+        let contexts = unsafe { &mut *::env().contexts.get() };
+        let cur = contexts.current_mut().unwrap();
+        cur.current_syscall = Some((regs.ip, regs.ax, regs.bx, regs.cx, regs.dx));
+        // Buffers on the stack may be below its bottom if they have not been touched yet, so the
+        // stack is grown to the stack pointer of the caller
+        unsafe { cur.grow_stack(regs.sp) };
+        // debugln!("PID {}: {} @ {:X}: {} {} {:X} {:X} {:X}", cur.pid, cur.name, regs.ip, regs.ax, name(regs.ax), regs.bx, regs.cx, regs.dx);
+        if cur.supervised {
+            let data = supervise_data(cur, regs, None);
+            supervise_wait(cur, Packet {
+                id: SUPERVISE_ENTER,
+                a: regs.ax,
+                b: regs.bx,
+                c: regs.cx,
+                d: regs.dx,
+            }, data);
+
+            match cur.supervise_reply.take() {
+                Some((SUPERVISE_PASS, _)) => passed = true,
+                Some((_, value)) => {
+                    cur.current_syscall = None;
+                    regs.ax = value;
+                    return;
+                },
+                None => {
+                    cur.current_syscall = None;
+                    regs.ax = Error::mux(Err(Error::new(EINTR)));
+                    return;
+                }
+            }
+        }
+    }
+
+    let result = dispatch(regs);
+    let value = Error::mux(result);
+
+    {
+        let contexts = unsafe { &mut *::env().contexts.get() };
+        if let Ok(cur) = contexts.current_mut() {
+            // debugln!("PID {}: {} @ {:X}: {} {} {:X} {:X} {:X} = {:?}", cur.pid, cur.name, regs.ip, regs.ax, name(regs.ax), regs.bx, regs.cx, regs.dx, result);
+            if passed {
+                let data = supervise_data(cur, regs, Error::demux(value).ok());
+                supervise_wait(cur, Packet {
+                    id: SUPERVISE_EXIT,
+                    a: regs.ax,
+                    b: value,
+                    c: 0,
+                    d: 0,
+                }, data);
             }
+
+            cur.current_syscall = None;
         }
     }
 
+    regs.ax = value;
+}
+
+/// Run the syscall defined by the given registers
+fn dispatch(regs: &mut Regs) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let cur = try!(contexts.current_mut());
+
     macro_rules! check {
         ( $r:expr ) => (
-            match $r {
-                Err(result) => {
-                    regs.ax = -result.errno as usize;
-                    return;
-                },
-                Ok(val) => val,
-            }
+            try!($r)
         )
     }
 
@@ -113,7 +164,17 @@ pub fn handle(regs: &mut Regs) {
         ( $buf:ident, $len:ident ) => ( check!(cur.get_slice_mut(regs.$buf as *mut u8, regs.$len)) );
     }
 
-    let result = match regs.ax {
+    macro_rules! get_iov {
+        ( $buf:ident, $len:ident ) => (
+            if regs.$len > IOV_MAX {
+                return Err(Error::new(EINVAL));
+            } else {
+                check!(cur.get_slice(regs.$buf as *const IoVec, regs.$len))
+            }
+        );
+    }
+
+    match regs.ax {
         // These are arranged in such a way that the most frequent syscalls preceeds less frequent
         // once, to acheive the best performance.
 
@@ -122,6 +183,10 @@ pub fn handle(regs: &mut Regs) {
         SYS_WRITE => fs::write(regs.bx, get_slice!(cx, dx)),
         SYS_READ => fs::read(regs.bx, get_slice_mut!(cx, dx)),
         SYS_LSEEK => fs::lseek(regs.bx, regs.cx as isize, regs.dx),
+        SYS_PREAD => fs::pread(regs.bx, get_slice_mut!(cx, dx), regs.si as u64),
+        SYS_PWRITE => fs::pwrite(regs.bx, get_slice!(cx, dx), regs.si as u64),
+        SYS_READV => fs::readv(regs.bx, get_iov!(cx, dx)),
+        SYS_WRITEV => fs::writev(regs.bx, get_iov!(cx, dx)),
         SYS_OPEN => fs::open(get_slice!(bx, cx), regs.dx),
         SYS_CLOSE => fs::close(regs.bx),
         SYS_CLONE => process::clone(regs),
@@ -131,30 +196,42 @@ pub fn handle(regs: &mut Regs) {
         SYS_FSTAT => fs::fstat(regs.bx, get_ref_mut!(cx, Stat)),
         SYS_FSYNC => fs::fsync(regs.bx),
         SYS_FTRUNCATE => fs::ftruncate(regs.bx, regs.cx),
+        SYS_GETDENTS => fs::getdents(regs.bx, get_slice_mut!(cx, dx)),
         SYS_DUP => fs::dup(regs.bx),
+        SYS_DUP2 => fs::dup2(regs.bx, regs.cx),
+        SYS_FCNTL => fs::fcntl(regs.bx, regs.cx, regs.dx),
+        SYS_FLOCK => fs::flock(regs.bx, regs.cx),
         SYS_IOPL => process::iopl(regs),
         SYS_CLOCK_GETTIME => time::clock_gettime(regs.bx, get_ref_mut!(cx, TimeSpec)),
-        SYS_EXECVE => process::execve(regs.bx as *const u8, regs.cx as *const *const u8),
+        SYS_EXECVE => process::execve(check!(cur.get_c_str(regs.bx as *const u8)),
+                                      check!(cur.get_c_array(regs.cx as *const *const u8)),
+                                      None),
+        SYS_EXECVE_ENV => process::execve(check!(cur.get_c_str(regs.bx as *const u8)),
+                                          check!(cur.get_c_array(regs.cx as *const *const u8)),
+                                          if regs.dx != 0 {
+                                              Some(check!(cur.get_c_array(regs.dx as *const *const u8)))
+                                          } else {
+                                              None
+                                          }),
         SYS_EXIT => process::exit(regs.bx),
         SYS_GETPID => process::getpid(),
-        // TODO: link
+        SYS_GETRLIMIT => process::getrlimit(regs.bx, get_ref_mut!(cx, RLimit)),
+        SYS_GETRUSAGE => process::getrusage(regs.bx, get_ref_mut!(cx, RUsage)),
+        SYS_LINK => fs::link(get_slice!(bx, cx), get_slice!(dx, si)),
+        SYS_MOUNT => fs::mount(get_slice!(bx, cx), get_slice!(dx, si)),
         SYS_PIPE2 => fs::pipe2(get_ref_mut!(bx, [usize; 2]), regs.cx),
+        SYS_READLINK => fs::readlink(get_slice!(bx, cx), get_slice_mut!(dx, si)),
+        SYS_RENAME => fs::rename(get_slice!(bx, cx), get_slice!(dx, si)),
         SYS_RMDIR => fs::rmdir(get_slice!(bx, cx)),
+        SYS_SETRLIMIT => process::setrlimit(regs.bx, get_ref!(cx, RLimit)),
+        SYS_STAT => fs::stat(get_slice!(bx, cx), get_ref_mut!(dx, Stat)),
+        SYS_SYMLINK => fs::symlink(get_slice!(bx, cx), get_slice!(dx, si)),
         SYS_UNLINK => fs::unlink(get_slice!(bx, cx)),
+        SYS_UMOUNT => fs::umount(get_slice!(bx, cx)),
         SYS_WAITPID => process::waitpid(regs.bx as isize, get_ref_mut_opt!(cx, usize), regs.dx),
         SYS_BRK => memory::brk(regs.bx),
         SYS_CHDIR => fs::chdir(get_slice!(bx, cx)),
         SYS_SUPERVISE => process::supervise(regs.bx),
         _ => Err(Error::new(ENOSYS)),
-    };
-
-    {
-        let contexts = unsafe { &mut *::env().contexts.get() };
-        if let Ok(cur) = contexts.current_mut() {
-            // debugln!("PID {}: {} @ {:X}: {} {} {:X} {:X} {:X} = {:?}", cur.pid, cur.name, regs.ip, regs.ax, name(regs.ax), regs.bx, regs.cx, regs.dx, result);
-            cur.current_syscall = None;
-        }
     }
-
-    regs.ax = Error::mux(result);
 }
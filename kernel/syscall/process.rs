@@ -1,33 +1,57 @@
 //! System calls related to process managment.
-use arch::context::{context_clone, context_switch, Context, ContextFile};
+use arch::context::{context_clone, context_switch, Context, ContextFile, EnvVar};
 use arch::regs::Regs;
 
-use collections::{BTreeMap, Vec};
+use collections::{BTreeMap, String, Vec};
 use collections::string::ToString;
 
-use core::{intrinsics, mem};
+use common::time::Duration;
+
+use core::{cmp, intrinsics, mem};
 use core::ops::DerefMut;
 
-use system::{c_array_to_slice, c_string_to_str};
-use system::error::{Error, Result, EAGAIN, EACCES, ECHILD, EINVAL};
-use system::syscall::{FUTEX_WAKE, FUTEX_WAIT, FUTEX_REQUEUE};
+use system::error::{Error, Result, EAGAIN, EACCES, ECHILD, EINVAL, EPERM};
+use system::syscall::{FUTEX_WAKE, FUTEX_WAIT, FUTEX_REQUEUE, RLIMIT_AS, RLIMIT_CPU, RLIMIT_NOFILE,
+                      RLIMIT_NPROC, RLIMIT_STACK, RUSAGE_CHILDREN, RUSAGE_SELF, RLimit, RUsage, TimeSpec};
 
 use super::execute::execute;
 
-use fs::SupervisorResource;
+use fs::{LockOwner, SupervisorResource};
 
 pub fn clone(regs: &Regs) -> Result<usize> {
     unsafe { context_clone(regs) }
 }
 
-pub fn execve(path: *const u8, args: *const *const u8) -> Result<usize> {
+/// Execute a program, with the environment `envp` if it is given, and the environment of the
+/// current context otherwise. The pointers of `args` and `envp` are checked as they are read
+pub fn execve(path: &[u8], args: &[*const u8], envp: Option<&[*const u8]>) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+
     let mut args_vec = Vec::new();
-    args_vec.push(c_string_to_str(path).to_string());
-    for arg in c_array_to_slice(args) {
-        args_vec.push(c_string_to_str(*arg).to_string());
+    args_vec.push(String::from_utf8_lossy(path).into_owned());
+    for &arg in args.iter() {
+        args_vec.push(String::from_utf8_lossy(try!(current.get_c_str(arg))).into_owned());
     }
 
-    execute(args_vec)
+    let vars = if let Some(envp) = envp {
+        let mut vars = Vec::new();
+        for &var in envp.iter() {
+            let var = String::from_utf8_lossy(try!(current.get_c_str(var)));
+            let mut parts = var.splitn(2, '=');
+            if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                if ! name.is_empty() {
+                    vars.retain(|var: &EnvVar| var.name() != name);
+                    vars.push(EnvVar(name.to_string(), value.to_string()));
+                }
+            }
+        }
+        Some(vars)
+    } else {
+        None
+    };
+
+    execute(args_vec, vars)
 }
 
 /// Exit context
@@ -36,9 +60,17 @@ pub fn exit(status: usize) -> ! {
         let contexts = unsafe { &mut *::env().contexts.get() };
 
         let mut statuses = BTreeMap::new();
+        let mut cpu_time = Duration::new(0, 0);
+        let mut peak_memory = 0;
         let (pid, ppid) = {
             if let Ok(mut current) = contexts.current_mut() {
+                // The usage of the context and of its children is added to that of its parent
+                cpu_time = current.cpu_time + current.children_cpu_time;
+                peak_memory = cmp::max(cmp::max(current.peak_memory, current.memory_size()),
+                                       current.children_peak_memory);
+
                 mem::swap(&mut statuses, &mut unsafe { current.statuses.inner() }.deref_mut());
+
                 current.exit();
                 (current.pid, current.ppid)
             } else {
@@ -46,9 +78,14 @@ pub fn exit(status: usize) -> ! {
             }
         };
 
+        contexts.count_descendant(ppid, false);
+
         for mut context in contexts.iter_mut() {
             // Add exit status to parent
             if context.pid == ppid {
+                context.children_cpu_time = context.children_cpu_time + cpu_time;
+                context.children_peak_memory = cmp::max(context.children_peak_memory, peak_memory);
+
                 context.statuses.send(pid, status, "exit parent status");
                 for (pid, status) in statuses.iter() {
                     context.statuses.send(*pid, *status, "exit child status");
@@ -150,6 +187,68 @@ pub fn getpid() -> Result<usize> {
     Ok(current.pid)
 }
 
+/// Fail with `EINVAL` if `resource` is not a resource limit that is supported
+fn check_rlimit(resource: usize) -> Result<()> {
+    match resource {
+        RLIMIT_AS | RLIMIT_CPU | RLIMIT_NOFILE | RLIMIT_NPROC | RLIMIT_STACK => Ok(()),
+        _ => Err(Error::new(EINVAL))
+    }
+}
+
+/// Get a resource limit of the current context
+pub fn getrlimit(resource: usize, rlim: &mut RLimit) -> Result<usize> {
+    try!(check_rlimit(resource));
+
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    *rlim = current.rlimits[resource];
+    Ok(0)
+}
+
+/// Set a resource limit of the current context, which is inherited by its children
+///
+/// The soft limit cannot be above the hard limit, and the hard limit can only be lowered.
+pub fn setrlimit(resource: usize, rlim: &RLimit) -> Result<usize> {
+    try!(check_rlimit(resource));
+
+    let (rlim_cur, rlim_max) = (rlim.rlim_cur, rlim.rlim_max);
+    if rlim_cur > rlim_max {
+        return Err(Error::new(EINVAL));
+    }
+
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let mut current = try!(contexts.current_mut());
+    if rlim_max > current.rlimits[resource].rlim_max {
+        return Err(Error::new(EPERM));
+    }
+
+    current.rlimits[resource] = RLimit {
+        rlim_cur: rlim_cur,
+        rlim_max: rlim_max,
+    };
+    Ok(0)
+}
+
+/// Get the CPU time and peak memory use of the current context, or of its children that have
+/// exited
+pub fn getrusage(who: usize, usage: &mut RUsage) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+
+    let (cpu_time, peak_memory) = match who {
+        RUSAGE_SELF => (current.cpu_time, cmp::max(current.peak_memory, current.memory_size())),
+        RUSAGE_CHILDREN => (current.children_cpu_time, current.children_peak_memory),
+        _ => return Err(Error::new(EINVAL))
+    };
+
+    usage.ru_utime = TimeSpec {
+        tv_sec: cpu_time.secs,
+        tv_nsec: cpu_time.nanos,
+    };
+    usage.ru_maxrss = peak_memory / 1024;
+    Ok(0)
+}
+
 #[cfg(target_arch = "x86")]
 pub fn iopl(regs: &mut Regs) -> Result<usize> {
     let level = regs.bx;
@@ -217,14 +316,13 @@ pub fn sched_yield() -> Result<usize> {
 ///
 /// This routine is done by having a field defining whether the process is blocked by a syscall.
 /// When the syscall is read from the file handle, this field is set to false, but the process is
-/// still stopped (because it is marked as `blocked`), until the new value of the EAX register is
-/// written to the file handle.
+/// still stopped (because it is marked as `blocked`), until the reply is written to the file
+/// handle. A syscall that is passed through blocks the process again once it has finished, until
+/// its result is read.
 pub fn supervise(pid: usize) -> Result<usize> {
     let contexts = unsafe { &mut *::env().contexts.get() };
     let cur_pid = try!(contexts.current_mut()).pid;
 
-    let procc;
-
     {
         let jailed = try!(contexts.find_mut(pid));
 
@@ -234,18 +332,18 @@ pub fn supervise(pid: usize) -> Result<usize> {
         }
 
         jailed.supervised = true;
-
-        procc = &mut **jailed as *mut _;
     }
 
     let current = try!(contexts.current_mut());
 
-    let fd = current.next_fd();
+    let fd = try!(current.next_fd());
 
     unsafe {
         (*current.files.get()).push(ContextFile {
             fd: fd,
-            resource: box try!(SupervisorResource::new(procc)),
+            flags: 0,
+            resource: box try!(SupervisorResource::new(pid)),
+            lock_owner: LockOwner::new(),
         });
     }
 
@@ -6,7 +6,7 @@ use common::time::{self, Duration};
 
 use core::{cmp, mem, ptr, slice};
 
-use graphics::display::VBEMODEINFO;
+use system::input::{ABS_X, ABS_Y, BTN_LEFT, BTN_MIDDLE, BTN_RIGHT};
 
 use super::{Packet, Pipe, Setup};
 use super::desc::*;
@@ -128,9 +128,11 @@ pub trait Hci {
                             let this = self as *mut Hci;
                             Context::spawn("kuhci_hid".into(),
                                            box move || {
-                                if let Some(mode_info) = VBEMODEINFO {
+                                if (& *::env().console.get()).display.is_some() {
                                     syslog_info!("Starting HID driver");
 
+                                    let input_id = (&mut *::env().input.get()).add_device(&format!("usb_hid{}", address));
+
                                     let in_ptr = memory::alloc_aligned(in_len, 4096) as *mut u8;
 
                                     loop {
@@ -145,17 +147,32 @@ pub trait Hci {
                                             let x = ptr::read(in_ptr.offset(1) as *const u16) as usize;
                                             let y = ptr::read(in_ptr.offset(3) as *const u16) as usize;
 
-                                            let mouse_x = (x * mode_info.xresolution as usize) / 32768;
-                                            let mouse_y = (y * mode_info.yresolution as usize) / 32768;
+                                            let (width, height) = match (& *::env().console.get()).display {
+                                                Some(ref display) => (display.width, display.height),
+                                                None => (0, 0)
+                                            };
+
+                                            let mouse_x = (x * width) / 32768;
+                                            let mouse_y = (y * height) / 32768;
 
                                             let mouse_event = MouseEvent {
-                                                x: cmp::max(0, cmp::min(mode_info.xresolution as i32 - 1, mouse_x as i32)),
-                                                y: cmp::max(0, cmp::min(mode_info.yresolution as i32 - 1, mouse_y as i32)),
+                                                x: cmp::max(0, cmp::min(width as i32 - 1, mouse_x as i32)),
+                                                y: cmp::max(0, cmp::min(height as i32 - 1, mouse_y as i32)),
                                                 left_button: buttons & 1 == 1,
                                                 middle_button: buttons & 4 == 4,
                                                 right_button: buttons & 2 == 2,
                                             };
 
+                                            {
+                                                let input = &mut *::env().input.get();
+                                                input.absolute(input_id, ABS_X, mouse_event.x);
+                                                input.absolute(input_id, ABS_Y, mouse_event.y);
+                                                input.buttons(input_id, (mouse_event.left_button as u32) << BTN_LEFT |
+                                                                        (mouse_event.right_button as u32) << BTN_RIGHT |
+                                                                        (mouse_event.middle_button as u32) << BTN_MIDDLE);
+                                                input.sync(input_id);
+                                            }
+
                                             if (& *::env().console.get()).draw {
                                                 //ignore mouse event
                                             } else {
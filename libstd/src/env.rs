@@ -2,6 +2,8 @@
 
 use alloc::boxed::Box;
 
+use core::{ptr, slice, str};
+
 use core_collections::borrow::ToOwned;
 
 use ffi::{OsString, OsStr};
@@ -19,6 +21,8 @@ use io::{Error, Result, Read, Write};
 
 static mut _args: *mut Vec<&'static str> = 0 as *mut Vec<&'static str>;
 
+static mut _vars: *mut Vec<(String, String)> = 0 as *mut Vec<(String, String)>;
+
 /// An iterator over the arguments of a process, yielding a `String` value for each argument.
 pub struct Args {
     i: usize
@@ -68,6 +72,52 @@ pub unsafe fn args_destroy() {
     }
 }
 
+/// Initialize environment variables from `envp`, the null terminated array of `NAME=VALUE`
+/// strings that the kernel places on the initial stack
+pub unsafe fn vars_init(envp: *const *const u8) {
+    let mut vars: Vec<(String, String)> = Vec::new();
+    if ! envp.is_null() {
+        let mut i = 0;
+        loop {
+            let var = ptr::read(envp.offset(i));
+            if var.is_null() {
+                break;
+            }
+
+            let mut len = 0;
+            while ptr::read(var.offset(len)) != 0 {
+                len += 1;
+            }
+
+            if let Ok(string) = str::from_utf8(slice::from_raw_parts(var, len as usize)) {
+                if let Some(equal_sign) = string.find('=') {
+                    vars.push((string[..equal_sign].to_owned(), string[equal_sign + 1..].to_owned()));
+                }
+            }
+
+            i += 1;
+        }
+    }
+    _vars = Box::into_raw(box vars);
+}
+
+/// Destroy environment variables
+pub unsafe fn vars_destroy() {
+    if _vars as usize > 0 {
+        drop(Box::from_raw(_vars));
+        _vars = 0 as *mut Vec<(String, String)>;
+    }
+}
+
+/// The environment variables of this process, if they were initialized
+fn vars_mut() -> Option<&'static mut Vec<(String, String)>> {
+    if unsafe { _vars } as usize > 0 {
+        Some(unsafe { &mut *_vars })
+    } else {
+        None
+    }
+}
+
 /// Private function to get the path from a custom location
 /// If the custom directory cannot be found, None will be returned
 fn get_path_from(location : &str) -> Result<PathBuf> {
@@ -100,24 +150,16 @@ pub fn temp_dir() -> Option<PathBuf> {
 /// Set the current directory
 pub fn set_current_dir<P: AsRef<Path>>(path: P) -> Result<()> {
     let path_str = path.as_ref().as_os_str().as_inner();
-    let file_result = if path_str.is_empty() || path_str.ends_with('/') {
-        File::open(path_str)
-    } else {
-        let mut path_string = path_str.to_owned();
+    let mut path_string = path_str.to_owned();
+    if ! path_string.is_empty() && ! path_string.ends_with('/') {
         path_string.push_str("/");
-        File::open(path_string)
-    };
+    }
 
-    match file_result {
-        Ok(file) => {
-            match file.path() {
-                Ok(path) => {
-                    let path_str = path.as_os_str().as_inner();
-                    sys_chdir(path_str).and(Ok(())).map_err(|x| Error::from_sys(x))
-                }
-                Err(err) => Err(err),
-            }
-        }
+    // Opening the directory checks that it exists. The path is then passed on as it was given,
+    // rather than as the file reports it, so that the working directory stays under the mounts
+    // and symlinks it was reached through
+    match File::open(&path_string) {
+        Ok(_) => sys_chdir(&path_string).and(Ok(())).map_err(|x| Error::from_sys(x)),
         Err(err) => Err(err),
     }
 }
@@ -154,6 +196,12 @@ impl error::Error for VarError {
 /// or if the variable is not present then `Err` is returned
 pub fn var<K: AsRef<OsStr>>(key: K) -> ::core::result::Result<String, VarError> {
     if let Some(key_str) = key.as_ref().to_str() {
+        if let Some(vars) = vars_mut() {
+            return vars.iter().find(|&&(ref name, _)| name == key_str)
+                       .map(|&(_, ref value)| value.clone())
+                       .ok_or(VarError::NotPresent);
+        }
+
         let mut file = try!(File::open(&("env:".to_owned() + key_str)).or(Err(VarError::NotPresent)));
         let mut string = String::new();
         try!(file.read_to_string(&mut string).or(Err(VarError::NotPresent)));
@@ -174,8 +222,18 @@ pub fn var_os<K: AsRef<OsStr>>(key: K) -> Option<OsString> {
 }
 
 /// Sets the environment variable `key` to the value `value` for the current process
+///
+/// The variable is also set in the `env:` scheme, which keeps it when executing a program without
+/// an explicit environment.
 pub fn set_var<K: AsRef<OsStr>, V: AsRef<OsStr>>(key: K, value: V) {
     if let (Some(key_str), Some(value_str)) = (key.as_ref().to_str(), value.as_ref().to_str()) {
+        if let Some(vars) = vars_mut() {
+            match vars.iter_mut().find(|&&mut (ref name, _)| name == key_str) {
+                Some(&mut (_, ref mut old_value)) => *old_value = value_str.to_owned(),
+                None => vars.push((key_str.to_owned(), value_str.to_owned()))
+            }
+        }
+
         if let Ok(mut file) = File::open(&("env:".to_owned() + key_str)) {
             let _ = file.write_all(value_str.as_bytes());
         }
@@ -185,6 +243,10 @@ pub fn set_var<K: AsRef<OsStr>, V: AsRef<OsStr>>(key: K, value: V) {
 /// Removes an environment variable from the environment of the current process
 pub fn remove_var<K: AsRef<OsStr>>(key: K) {
     if let Some(key_str) = key.as_ref().to_str() {
+        if let Some(vars) = vars_mut() {
+            vars.retain(|&(ref name, _)| name != key_str);
+        }
+
         let _ = fs::remove_file(&("env:".to_owned() + key_str));
     }
 }
@@ -208,6 +270,10 @@ impl Iterator for Vars {
 
 /// Returns an iterator over the environment variables of the current process
 pub fn vars() -> Vars {
+    if let Some(vars) = vars_mut() {
+        return Vars { vars: vars.clone(), pos: 0 };
+    }
+
     let mut variables: Vec<(String, String)> = Vec::new();
     if let Ok(mut file) = File::open("env:") {
         let mut string = String::new();
@@ -1,15 +1,21 @@
 use core_collections::borrow::ToOwned;
 use io::{self, BufRead, BufReader, Read, Error, Result, Write, Seek, SeekFrom};
 use os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
-use mem;
+use mem::{self, size_of};
 use path::{PathBuf, Path};
+use ptr;
 use string::String;
 use sys_common::AsInner;
+use time::{Duration, SystemTime, UNIX_EPOCH};
 use vec::Vec;
 
 use system::syscall::{sys_open, sys_dup, sys_close, sys_fpath, sys_fstat, sys_ftruncate, sys_read,
-              sys_write, sys_lseek, sys_fsync, sys_mkdir, sys_rmdir, sys_unlink};
-use system::syscall::{O_RDWR, O_RDONLY, O_WRONLY, O_APPEND, O_CREAT, O_TRUNC, MODE_DIR, MODE_FILE, SEEK_SET, SEEK_CUR, SEEK_END, Stat};
+              sys_write, sys_lseek, sys_fsync, sys_mkdir, sys_rename, sys_rmdir, sys_unlink,
+              sys_link, sys_readlink, sys_symlink, sys_stat, sys_getdents};
+use system::error::{EIO, ENOSYS};
+use system::syscall::{O_RDWR, O_RDONLY, O_WRONLY, O_APPEND, O_CREAT, O_TRUNC, O_NOFOLLOW, O_CLOEXEC,
+              MODE_TYPE, MODE_DIR, MODE_FILE, MODE_SYMLINK, MODE_PERM, SEEK_SET, SEEK_CUR, SEEK_END, Stat,
+              Dirent, DT_DIR, DT_LNK, DT_REG, DT_UNKNOWN};
 
 /// A Unix-style file
 #[derive(Debug)]
@@ -22,13 +28,13 @@ impl File {
     /// Open a new file using a path
     pub fn open<P: AsRef<Path>>(path: P) -> Result<File> {
         let path_str = path.as_ref().as_os_str().as_inner();
-        sys_open(path_str, O_RDONLY).map(|fd| unsafe { File::from_raw_fd(fd) }).map_err(|x| Error::from_sys(x))
+        sys_open(path_str, O_RDONLY | O_CLOEXEC).map(|fd| unsafe { File::from_raw_fd(fd) }).map_err(|x| Error::from_sys(x))
     }
 
     /// Create a new file using a path
     pub fn create<P: AsRef<Path>>(path: P) -> Result<File> {
         let path_str = path.as_ref().as_os_str().as_inner();
-        sys_open(path_str, O_CREAT | O_RDWR | O_TRUNC).map(|fd| unsafe { File::from_raw_fd(fd) }).map_err(|x| Error::from_sys(x))
+        sys_open(path_str, O_CREAT | O_RDWR | O_TRUNC | O_CLOEXEC).map(|fd| unsafe { File::from_raw_fd(fd) }).map_err(|x| Error::from_sys(x))
     }
 
     /// Duplicate the file
@@ -131,6 +137,7 @@ impl Drop for File {
 pub struct FileType {
     dir: bool,
     file: bool,
+    symlink: bool,
 }
 
 impl FileType {
@@ -143,7 +150,7 @@ impl FileType {
     }
 
     pub fn is_symlink(&self) -> bool {
-        false
+        self.symlink
     }
 }
 
@@ -192,7 +199,7 @@ impl OpenOptions {
     }
 
     pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<File> {
-        let mut flags = 0;
+        let mut flags = O_CLOEXEC;
 
         if self.read && self.write {
             flags |= O_RDWR;
@@ -226,28 +233,92 @@ pub struct Metadata {
 impl Metadata {
     pub fn file_type(&self) -> FileType {
         FileType {
-            dir: self.stat.st_mode & MODE_DIR == MODE_DIR,
-            file: self.stat.st_mode & MODE_FILE == MODE_FILE
+            dir: self.is_dir(),
+            file: self.is_file(),
+            symlink: self.is_symlink(),
         }
     }
 
     pub fn is_dir(&self) -> bool {
-        self.stat.st_mode & MODE_DIR == MODE_DIR
+        self.stat.st_mode & MODE_TYPE == MODE_DIR
     }
 
     pub fn is_file(&self) -> bool {
-        self.stat.st_mode & MODE_FILE == MODE_FILE
+        self.stat.st_mode & MODE_TYPE == MODE_FILE
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.stat.st_mode & MODE_TYPE == MODE_SYMLINK
     }
 
     pub fn len(&self) -> u64 {
-        self.stat.st_size as u64
+        self.stat.st_size
+    }
+
+    pub fn permissions(&self) -> Permissions {
+        Permissions {
+            mode: self.stat.st_mode & MODE_PERM
+        }
+    }
+
+    /// The time of the last modification of the data
+    pub fn modified(&self) -> Result<SystemTime> {
+        Ok(UNIX_EPOCH + Duration::new(self.stat.st_mtime, self.stat.st_mtime_nsec))
+    }
+
+    /// The time of the last access to the data
+    pub fn accessed(&self) -> Result<SystemTime> {
+        Ok(UNIX_EPOCH + Duration::new(self.stat.st_atime, self.stat.st_atime_nsec))
+    }
+}
+
+/// The permission bits of a file
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Permissions {
+    mode: u16
+}
+
+/// Unix specific extensions to `Permissions`, reexported in `os::unix::fs`
+pub trait PermissionsExt {
+    /// The permission bits
+    fn mode(&self) -> u32;
+
+    /// Create permissions from permission bits
+    fn from_mode(mode: u32) -> Self;
+}
+
+impl PermissionsExt for Permissions {
+    fn mode(&self) -> u32 {
+        self.mode as u32
+    }
+
+    fn from_mode(mode: u32) -> Permissions {
+        Permissions {
+            mode: mode as u16 & MODE_PERM
+        }
+    }
+}
+
+impl Permissions {
+    /// Whether the file cannot be written by anyone
+    pub fn readonly(&self) -> bool {
+        self.mode & 0o222 == 0
+    }
+
+    pub fn set_readonly(&mut self, readonly: bool) {
+        if readonly {
+            self.mode &= !0o222;
+        } else {
+            self.mode |= 0o200;
+        }
     }
 }
 
 pub struct DirEntry {
     path: PathBuf,
-    dir: bool,
-    file: bool,
+    /// Type of the entry, one of the `DT_*` constants
+    kind: u8,
+    ino: u64,
 }
 
 impl DirEntry {
@@ -255,13 +326,24 @@ impl DirEntry {
         unsafe { mem::transmute(self.path.file_name().unwrap().to_str().unwrap()) }
     }
 
+    /// Get the type of the entry, which only needs a `stat` if the scheme did not give it
     pub fn file_type(&self) -> Result<FileType> {
+        if self.kind == DT_UNKNOWN {
+            return symlink_metadata(&self.path).map(|metadata| metadata.file_type());
+        }
+
         Ok(FileType {
-            dir: self.dir,
-            file: self.file,
+            dir: self.kind == DT_DIR,
+            file: self.kind == DT_REG,
+            symlink: self.kind == DT_LNK,
         })
     }
 
+    /// Get the inode number of the entry, or 0 if the scheme has none
+    pub fn ino(&self) -> u64 {
+        self.ino
+    }
+
     pub fn metadata(&self) -> Result<Metadata> {
         metadata(&self.path)
     }
@@ -274,11 +356,59 @@ impl DirEntry {
 pub struct ReadDir {
     path: PathBuf,
     file: BufReader<File>,
+    /// Entries returned by `sys_getdents`, which is not used if the scheme does not support it
+    buf: Vec<u8>,
+    getdents: bool,
+    /// The range of `buf` that has not been returned yet
+    offset: usize,
+    len: usize,
 }
 
-impl Iterator for ReadDir {
-    type Item = Result<DirEntry>;
-    fn next(&mut self) -> Option<Result<DirEntry>> {
+impl ReadDir {
+    /// Get the next entry from the `Dirent` records returned by `sys_getdents`
+    fn next_dirent(&mut self) -> Option<Result<DirEntry>> {
+        if self.offset >= self.len {
+            let fd = self.file.get_ref().fd;
+            match sys_getdents(fd, &mut self.buf) {
+                Ok(count) => {
+                    self.offset = 0;
+                    self.len = count;
+                },
+                Err(err) => if err.errno == ENOSYS {
+                    self.getdents = false;
+                    return self.next_line();
+                } else {
+                    return Some(Err(Error::from_sys(err)));
+                }
+            }
+
+            if self.len == 0 {
+                return None;
+            }
+        }
+
+        let dirent = unsafe { ptr::read(self.buf.as_ptr().offset(self.offset as isize) as *const Dirent) };
+        let reclen = dirent.d_reclen as usize;
+        if reclen < size_of::<Dirent>() || self.offset + reclen > self.len {
+            self.offset = self.len;
+            return Some(Err(Error::new_sys(EIO)));
+        }
+
+        let name = String::from_utf8_lossy(&self.buf[self.offset + size_of::<Dirent>()..self.offset + reclen]).into_owned();
+        self.offset += reclen;
+
+        let mut path = self.path.clone();
+        path.push(name);
+        Some(Ok(DirEntry {
+            path: path,
+            kind: dirent.d_type,
+            ino: dirent.d_ino,
+        }))
+    }
+
+    /// Get the next entry from the list of names read from the directory, for schemes that do not
+    /// support `sys_getdents`
+    fn next_line(&mut self) -> Option<Result<DirEntry>> {
         let mut name = String::new();
         match self.file.read_line(&mut name) {
             Ok(0) => None,
@@ -295,8 +425,8 @@ impl Iterator for ReadDir {
                 path.push(name);
                 Some(Ok(DirEntry {
                     path: path,
-                    dir: dir,
-                    file: !dir,
+                    kind: if dir { DT_DIR } else { DT_REG },
+                    ino: 0,
                 }))
             },
             Err(err) => Some(Err(err))
@@ -304,6 +434,17 @@ impl Iterator for ReadDir {
     }
 }
 
+impl Iterator for ReadDir {
+    type Item = Result<DirEntry>;
+    fn next(&mut self) -> Option<Result<DirEntry>> {
+        if self.getdents {
+            self.next_dirent()
+        } else {
+            self.next_line()
+        }
+    }
+}
+
 /// Find the canonical path of a file
 pub fn canonicalize<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
     match File::open(path) {
@@ -319,13 +460,43 @@ pub fn canonicalize<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
 
 /// Get information about a file
 pub fn metadata<P: AsRef<Path>>(path: P) -> Result<Metadata> {
-    try!(File::open(path)).metadata()
+    let path_str = path.as_ref().as_os_str().as_inner();
+    let mut stat = Stat::default();
+    try!(sys_stat(path_str, &mut stat).map_err(|x| Error::from_sys(x)));
+    Ok(Metadata {
+        stat: stat
+    })
 }
 
 /// Get information about a file without following symlinks
-/// Warning: Redox does not currently support symlinks
 pub fn symlink_metadata<P: AsRef<Path>>(path: P) -> Result<Metadata> {
-    metadata(path)
+    let path_str = path.as_ref().as_os_str().as_inner();
+    let file = try!(sys_open(path_str, O_RDONLY | O_NOFOLLOW).map(|fd| unsafe { File::from_raw_fd(fd) }).map_err(|x| Error::from_sys(x)));
+    file.metadata()
+}
+
+/// Create a new hard link to a file
+pub fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> Result<()> {
+    let src_str = src.as_ref().as_os_str().as_inner();
+    let dst_str = dst.as_ref().as_os_str().as_inner();
+    sys_link(src_str, dst_str).and(Ok(())).map_err(|x| Error::from_sys(x))
+}
+
+/// Create a new symlink at `dst`, pointing to `src`
+pub fn soft_link<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> Result<()> {
+    let src_str = src.as_ref().as_os_str().as_inner();
+    let dst_str = dst.as_ref().as_os_str().as_inner();
+    sys_symlink(src_str, dst_str).and(Ok(())).map_err(|x| Error::from_sys(x))
+}
+
+/// Read the target of a symlink
+pub fn read_link<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
+    let path_str = path.as_ref().as_os_str().as_inner();
+    let mut buf: [u8; 4096] = [0; 4096];
+    match sys_readlink(path_str, &mut buf) {
+        Ok(count) => Ok(PathBuf::from(unsafe { String::from_utf8_unchecked(Vec::from(&buf[0..count])) })),
+        Err(err) => Err(Error::from_sys(err)),
+    }
 }
 
 /// Create a new directory, using a path
@@ -353,16 +524,26 @@ pub fn copy<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> Result<u64> {
     io::copy(&mut infile, &mut outfile)
 }
 
-/// Rename a file or directory to a new name
+/// Rename a file or directory to a new name, replacing the target atomically
+///
+/// Renaming across schemes is not possible, and fails with `EXDEV`
 pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> Result<()> {
-    try!(copy(Path::new(from.as_ref()), to));
-    remove_file(from)
+    let from_str = from.as_ref().as_os_str().as_inner();
+    let to_str = to.as_ref().as_os_str().as_inner();
+    sys_rename(from_str, to_str).and(Ok(())).map_err(|x| Error::from_sys(x))
 }
 
 /// Return an iterator over the entries within a directory
 pub fn read_dir<P: AsRef<Path>>(path: P) -> Result<ReadDir> {
     let path_buf = path.as_ref().to_owned();
-    File::open(&path_buf).map(|file| ReadDir { path: path_buf, file: BufReader::new(file) })
+    File::open(&path_buf).map(|file| ReadDir {
+        path: path_buf,
+        file: BufReader::new(file),
+        buf: vec![0; 4096],
+        getdents: true,
+        offset: 0,
+        len: 0,
+    })
 }
 
 /// Removes an existing, empty directory
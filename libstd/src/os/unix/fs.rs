@@ -0,0 +1,22 @@
+use fs;
+use io::Result;
+use path::Path;
+
+pub use fs::PermissionsExt;
+
+/// Unix-specific extensions to `DirEntry`
+pub trait DirEntryExt {
+    /// Get the inode number of the entry
+    fn ino(&self) -> u64;
+}
+
+impl DirEntryExt for fs::DirEntry {
+    fn ino(&self) -> u64 {
+        fs::DirEntry::ino(self)
+    }
+}
+
+/// Create a new symlink at `dst`, pointing to `src`
+pub fn symlink<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> Result<()> {
+    fs::soft_link(src, dst)
+}
@@ -11,7 +11,7 @@ use core_collections::borrow::ToOwned;
 use vec::Vec;
 
 use io::Error;
-use system::syscall::{sys_clone, sys_close, sys_dup, sys_execve, sys_exit, sys_pipe2, sys_read, sys_write, sys_waitpid, CLONE_VM, CLONE_VFORK, CLONE_SUPERVISE};
+use system::syscall::{sys_clone, sys_close, sys_dup2, sys_execve_env, sys_exit, sys_fcntl, sys_pipe2, sys_read, sys_write, sys_waitpid, CLONE_VM, CLONE_VFORK, CLONE_SUPERVISE, F_SETFD, O_CLOEXEC};
 use system::error::Error as SysError;
 
 pub struct ExitStatus {
@@ -101,6 +101,19 @@ impl Child {
     }
 }
 
+/// Make `fd` refer to the resource of `from`, closing `from`, and keep `fd` open across exec
+///
+/// `dup2` does nothing when both are the same, so the close-on-exec flag is cleared instead.
+fn redirect(from: usize, fd: usize) -> Result<usize> {
+    if from == fd {
+        return sys_fcntl(fd, F_SETFD, 0).map_err(|x| Error::from_sys(x));
+    }
+
+    let dup_res = sys_dup2(from, fd).map_err(|x| Error::from_sys(x));
+    let _ = sys_close(from);
+    dup_res
+}
+
 pub struct Command {
     path: String,
     args: Vec<String>,
@@ -201,7 +214,26 @@ impl Command {
         }
         args_c.push(0 as *const u8);
 
-        let env = self.env.clone();
+        // The child gets the environment of this process with the variables set on the command
+        // replacing or adding to it
+        let mut vars = env::vars().collect::<Vec<(String, String)>>();
+        for (key, val) in self.env.iter() {
+            match vars.iter_mut().find(|&&mut (ref name, _)| name == key) {
+                Some(&mut (_, ref mut value)) => *value = val.to_owned(),
+                None => vars.push((key.to_owned(), val.to_owned()))
+            }
+        }
+
+        let mut vars_vec: Vec<String> = Vec::new();
+        for &(ref key, ref val) in vars.iter() {
+            vars_vec.push(format!("{}={}\0", key, val));
+        }
+
+        let mut vars_c: Vec<*const u8> = Vec::new();
+        for var_vec in vars_vec.iter() {
+            vars_c.push(var_vec.as_ptr());
+        }
+        vars_c.push(0 as *const u8);
 
         let child_res = res.deref_mut() as *mut usize;
         let child_stderr = self.stderr.inner;
@@ -211,17 +243,9 @@ impl Command {
             let child_stderr_res = match child_stderr {
                 StdioType::Piped(read, write) => {
                     let _ = sys_close(read);
-                    let _ = sys_close(2);
-                    let dup_res = sys_dup(write).map_err(|x| Error::from_sys(x));
-                    let _ = sys_close(write);
-                    dup_res
-                },
-                StdioType::Raw(fd) => {
-                    let _ = sys_close(2);
-                    let dup_res = sys_dup(fd).map_err(|x| Error::from_sys(x));
-                    let _ = sys_close(fd);
-                    dup_res
+                    redirect(write, 2)
                 },
+                StdioType::Raw(fd) => redirect(fd, 2),
                 StdioType::Null => {
                     let _ = sys_close(2);
                     Ok(0)
@@ -232,17 +256,9 @@ impl Command {
             let child_stdout_res = match child_stdout {
                 StdioType::Piped(read, write) => {
                     let _ = sys_close(read);
-                    let _ = sys_close(1);
-                    let dup_res = sys_dup(write).map_err(|x| Error::from_sys(x));
-                    let _ = sys_close(write);
-                    dup_res
-                },
-                StdioType::Raw(fd) => {
-                    let _ = sys_close(1);
-                    let dup_res = sys_dup(fd).map_err(|x| Error::from_sys(x));
-                    let _ = sys_close(fd);
-                    dup_res
+                    redirect(write, 1)
                 },
+                StdioType::Raw(fd) => redirect(fd, 1),
                 StdioType::Null => {
                     let _ = sys_close(1);
                     Ok(0)
@@ -253,17 +269,9 @@ impl Command {
             let child_stdin_res = match child_stdin {
                 StdioType::Piped(read, write) => {
                     let _ = sys_close(write);
-                    let _ = sys_close(0);
-                    let dup_res = sys_dup(read).map_err(|x| Error::from_sys(x));
-                    let _ = sys_close(read);
-                    dup_res
-                },
-                StdioType::Raw(fd) => {
-                    let _ = sys_close(0);
-                    let dup_res = sys_dup(fd).map_err(|x| Error::from_sys(x));
-                    let _ = sys_close(fd);
-                    dup_res
+                    redirect(read, 0)
                 },
+                StdioType::Raw(fd) => redirect(fd, 0),
                 StdioType::Null => {
                     let _ = sys_close(0);
                     Ok(0)
@@ -275,11 +283,7 @@ impl Command {
             let _ = try!(child_stdout_res);
             let _ = try!(child_stdin_res);
 
-            for (key, val) in env.iter() {
-                env::set_var(key, val);
-            }
-
-            unsafe { sys_execve(path_c.as_ptr(), args_c.as_ptr()) }.map_err(|x| Error::from_sys(x))
+            unsafe { sys_execve_env(path_c.as_ptr(), args_c.as_ptr(), vars_c.as_ptr()) }.map_err(|x| Error::from_sys(x))
         });
 
         match unsafe { sys_clone(flags) } {
@@ -395,7 +399,7 @@ pub struct Stdio {
 impl Stdio {
     pub fn piped() -> Stdio {
         let mut fds = [0; 2];
-        if sys_pipe2(&mut fds, 0).is_ok() {
+        if sys_pipe2(&mut fds, O_CLOEXEC).is_ok() {
             Stdio {
                 inner: StdioType::Piped(fds[0], fds[1])
             }
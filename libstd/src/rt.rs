@@ -1,6 +1,6 @@
 use core::{fmt, mem, ptr, slice, str};
 use panic::panic_impl;
-use env::{args_init, args_destroy};
+use env::{args_init, args_destroy, vars_init, vars_destroy};
 use system::syscall::sys_exit;
 use vec::Vec;
 
@@ -62,7 +62,15 @@ pub unsafe extern "C" fn _start_stack(stack: *const usize){
 
     let argc = *stack;
     let argv = stack.offset(1) as *const *const u8;
-    let _ = sys_exit(main(argc, argv));
+    let envp = stack.offset(argc as isize + 2) as *const *const u8;
+
+    vars_init(envp);
+
+    let status = main(argc, argv);
+
+    vars_destroy();
+
+    let _ = sys_exit(status);
 }
 
 #[lang = "start"]
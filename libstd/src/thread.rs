@@ -2,7 +2,9 @@ use alloc::boxed::Box;
 
 use core::mem;
 
-use system::syscall::{sys_clone, sys_exit, sys_yield, sys_nanosleep, sys_waitpid, CLONE_VM, CLONE_FS, CLONE_FILES,
+use io::{Error, Result};
+
+use system::syscall::{sys_clone, sys_clone_stack, sys_exit, sys_yield, sys_nanosleep, sys_waitpid, CLONE_VM, CLONE_FS, CLONE_FILES,
               TimeSpec};
 
 use time::Duration;
@@ -53,6 +55,66 @@ pub fn sleep_ms(ms: u32) {
     sleep(Duration::new(secs, nanos))
 }
 
+/// Thread configuration, which sets the size of the stack of a new thread
+pub struct Builder {
+    stack_size: Option<usize>,
+}
+
+impl Builder {
+    /// A builder for a thread with the stack limit of the current thread
+    pub fn new() -> Builder {
+        Builder {
+            stack_size: None,
+        }
+    }
+
+    /// Set the most that the stack of the new thread can grow to, in bytes
+    pub fn stack_size(mut self, size: usize) -> Builder {
+        self.stack_size = Some(size);
+        self
+    }
+
+    /// Spawns a new thread with this configuration, returning a `JoinHandle` for it, or an error
+    /// if the thread could not be created
+    // TODO: Catch panic
+    pub fn spawn<F, T>(self, f: F) -> Result<JoinHandle<T>>
+        where F: FnOnce() -> T,
+              F: Send + 'static,
+              T: Send + 'static
+    {
+        let result_ptr: *mut Option<T> = Box::into_raw(box None);
+        //This must only be used by the child
+        let boxed_f = Box::new(f);
+
+        let flags = CLONE_VM | CLONE_FS | CLONE_FILES;
+        let clone_res = match self.stack_size {
+            Some(stack_size) => unsafe { sys_clone_stack(flags, stack_size) },
+            None => unsafe { sys_clone(flags) }
+        };
+
+        match clone_res {
+            Ok(0) => {
+                unsafe { *result_ptr = Some(boxed_f()) };
+                loop {
+                    let _ = sys_exit(0);
+                }
+            },
+            Ok(pid) => {
+                //Forget so that the parent will not drop while the child is using
+                mem::forget(boxed_f);
+                Ok(JoinHandle {
+                    pid: pid,
+                    result_ptr: result_ptr
+                })
+            },
+            Err(err) => {
+                unsafe { drop(Box::from_raw(result_ptr)) };
+                Err(Error::from_sys(err))
+            }
+        }
+    }
+}
+
 /// Spawns a new thread, returning a `JoinHandle` for it.
 ///
 /// The join handle will implicitly *detach* the child thread upon being
@@ -67,32 +129,12 @@ pub fn sleep_ms(ms: u32) {
 ///
 /// Panics if the OS fails to create a thread; use `Builder::spawn`
 /// to recover from such errors.
-// TODO: Catch panic
 pub fn spawn<F, T>(f: F) -> JoinHandle<T>
     where F: FnOnce() -> T,
           F: Send + 'static,
           T: Send + 'static
 {
-    let result_ptr: *mut Option<T> = Box::into_raw(box None);
-    //This must only be used by the child
-    let boxed_f = Box::new(f);
-
-    match unsafe { sys_clone(CLONE_VM | CLONE_FS | CLONE_FILES).unwrap() } {
-        0 => {
-            unsafe { *result_ptr = Some(boxed_f()) };
-            loop {
-                let _ = sys_exit(0);
-            }
-        },
-        pid => {
-            //Forget so that the parent will not drop while the child is using
-            mem::forget(boxed_f);
-            JoinHandle {
-                pid: pid,
-                result_ptr: result_ptr
-            }
-        }
-    }
+    Builder::new().spawn(f).unwrap()
 }
 
 pub fn yield_now() {
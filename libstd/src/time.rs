@@ -226,6 +226,14 @@ impl SystemTime {
     }
 }
 
+impl Add<Duration> for SystemTime {
+    type Output = SystemTime;
+
+    fn add(self, dur: Duration) -> SystemTime {
+        SystemTime(self.0 + dur)
+    }
+}
+
 pub const UNIX_EPOCH: SystemTime = SystemTime(Duration {
     secs: 0,
     nanos: 0